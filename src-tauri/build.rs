@@ -1,12 +1,99 @@
 fn main() {
-    let webui_var = std::env::var("AW_WEBUI_DIR");
-    if webui_var.is_err() {
-        panic!("AW_WEBUI_DIR environment variable not set, Try running make");
+    // Without a web UI the app still builds, e.g. for `cargo check` or when packaging the web UI
+    // separately, and explains at runtime what is missing
+    println!("cargo:rustc-check-cfg=cfg(no_webui)");
+    println!("cargo:rerun-if-env-changed=AW_WEBUI_DIR");
+    match std::env::var("AW_WEBUI_DIR") {
+        Ok(webui_dir) => {
+            let index = std::path::Path::new(&webui_dir).join("index.html");
+            if !index.is_file() {
+                panic!(
+                    "AW_WEBUI_DIR={webui_dir} contains no index.html, build the web UI first \
+                    (e.g. with make)"
+                );
+            }
+            // Rebuild if the webui directory changes
+            println!("cargo:rerun-if-changed={webui_dir}");
+        }
+        Err(_) => {
+            println!(
+                "cargo:warning=AW_WEBUI_DIR not set, building without the web UI. \
+                Run make to bundle it."
+            );
+            println!("cargo:rustc-cfg=no_webui");
+        }
     }
 
-    // Rebuild if the webui directory changes
-    println!("cargo:rerun-if-changed={}", webui_var.unwrap());
-    println!("cargo:rerun-if-env-changed=AW_WEBUI_DIR");
+    // Build metadata for bug reports, "unknown" when building without git, e.g. from a tarball
+    let commit = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    let dirty = command_output("git", &["status", "--porcelain", "--untracked-files=no"])
+        .map(|status| (!status.is_empty()).to_string());
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+    let build_timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs().to_string())
+        .ok();
+    let tauri_version = locked_version("tauri");
+    for (key, value) in [
+        ("AW_TAURI_COMMIT", commit),
+        ("AW_TAURI_DIRTY", dirty),
+        ("AW_TAURI_RUSTC_VERSION", rustc_version),
+        ("AW_TAURI_BUILD_TIMESTAMP", build_timestamp),
+        ("AW_TAURI_TAURI_VERSION", tauri_version),
+    ] {
+        println!(
+            "cargo:rustc-env={key}={}",
+            value.unwrap_or_else(|| "unknown".to_string())
+        );
+    }
+    // HEAD only changes when switching branches, the branch's ref changes with each commit
+    if let Some(git_dir) = command_output("git", &["rev-parse", "--git-dir"]) {
+        let git_dir = std::path::Path::new(&git_dir);
+        let head_ref = command_output("git", &["symbolic-ref", "-q", "HEAD"]);
+        let watched = [Some("HEAD"), head_ref.as_deref(), Some("packed-refs")];
+        // A missing file would rerun the build script on every build
+        for path in watched.into_iter().flatten().map(|path| git_dir.join(path)) {
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
 
     tauri_build::build();
 }
+
+/// Returns the version of a dependency in Cargo.lock, `None` without a lock file
+fn locked_version(package: &str) -> Option<String> {
+    let manifest_dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").ok()?);
+    // The lock file is next to the manifest, or next to the workspace's
+    let lock_file = [
+        manifest_dir.join("Cargo.lock"),
+        manifest_dir.join("../Cargo.lock"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())?;
+    println!("cargo:rerun-if-changed={}", lock_file.display());
+    let lock = std::fs::read_to_string(lock_file).ok()?;
+    let name = format!("name = \"{package}\"");
+    let mut lines = lock.lines();
+    lines.find(|line| line.trim() == name)?;
+    lines
+        .next()?
+        .trim()
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+        .map(String::from)
+}
+
+/// Returns the trimmed stdout of a command, `None` if it can't be run or fails
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
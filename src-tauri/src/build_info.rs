@@ -0,0 +1,77 @@
+/// Version and build metadata, for bug reports
+///
+/// The values are captured by build.rs and are "unknown" when building without git.
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub commit: &'static str,
+    /// "true" when built with uncommitted changes
+    pub dirty: &'static str,
+    /// Unix timestamp of the build
+    pub build_timestamp: &'static str,
+    pub rustc_version: &'static str,
+    pub tauri_version: &'static str,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    commit: env!("AW_TAURI_COMMIT"),
+    dirty: env!("AW_TAURI_DIRTY"),
+    build_timestamp: env!("AW_TAURI_BUILD_TIMESTAMP"),
+    rustc_version: env!("AW_TAURI_RUSTC_VERSION"),
+    tauri_version: env!("AW_TAURI_TAURI_VERSION"),
+};
+
+impl BuildInfo {
+    /// Returns the version with the commit, e.g. `0.1.0 (abc1234-dirty)`
+    pub fn version_line(&self) -> String {
+        let dirty = if self.dirty == "true" { "-dirty" } else { "" };
+        format!("{} ({}{dirty})", self.version, self.commit)
+    }
+
+    /// Describes the build on multiple lines, for the About dialog and `--version`
+    pub fn describe(&self) -> String {
+        let built = self
+            .build_timestamp
+            .parse::<i64>()
+            .ok()
+            .and_then(|timestamp| chrono::DateTime::from_timestamp(timestamp, 0))
+            .map_or_else(|| "unknown".to_string(), |date| date.to_rfc3339());
+        format!(
+            "aw-tauri {}\nBuilt: {built}\n{}\nTauri {}",
+            self.version_line(),
+            self.rustc_version,
+            self.tauri_version
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_not_empty() {
+        let info = serde_json::to_value(&BUILD_INFO).unwrap();
+        for (field, value) in info.as_object().unwrap() {
+            assert!(!value.as_str().unwrap().is_empty(), "{field} is empty");
+        }
+    }
+
+    #[test]
+    fn git_build_knows_its_commit() {
+        let in_git = std::process::Command::new("git")
+            .args(["rev-parse", "--git-dir"])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !in_git {
+            return;
+        }
+        assert_ne!(BUILD_INFO.commit, "unknown");
+        assert!(["true", "false"].contains(&BUILD_INFO.dirty));
+        assert_ne!(BUILD_INFO.tauri_version, "unknown");
+    }
+}
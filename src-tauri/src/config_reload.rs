@@ -0,0 +1,115 @@
+/// Reloading config.toml when it changes on disk
+///
+/// Most settings are read when used, e.g. the hooks, the crash webhook or the restart limits, so
+/// they apply as soon as the new config is in place. Changes to the modules to start are applied
+/// by starting and stopping modules. Settings only read at startup, such as the port or the
+/// server mode, need a restart, which the user is told about. The `config-reloaded` event lets
+/// the UI pick up the new config.
+use log::{error, info};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::Emitter;
+
+use crate::manager::ManagerState;
+use crate::{get_app_handle, get_config, get_config_path, send_notification, shutdown};
+use crate::{SpecificFileWatcher, UserConfig};
+
+/// Event emitted with the new config after a reload
+pub const CONFIG_RELOADED_EVENT: &str = "config-reloaded";
+
+/// Time for an editor to finish writing the file before it is read
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// Watches config.toml and applies the changes made to it
+pub fn start_watching(manager_state: Arc<Mutex<ManagerState>>) {
+    shutdown::spawn("config watcher", move || {
+        let config_path = get_config_path();
+        let Some(config_dir) = config_path.parent() else {
+            return;
+        };
+        let file_name = config_path
+            .file_name()
+            .expect("config path has no file name")
+            .to_string_lossy();
+        let watcher = match SpecificFileWatcher::new(config_dir, &file_name) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Failed to watch {}: {e}", config_path.display());
+                return;
+            }
+        };
+        let watcher_shutdown = watcher.shutdown_handle();
+        shutdown::on_shutdown(move || watcher_shutdown.shutdown());
+        while watcher.wait_for_file().is_some() {
+            if shutdown::sleep(SETTLE_DELAY) {
+                return;
+            }
+            reload(&manager_state);
+        }
+    });
+}
+
+fn reload(manager_state: &Arc<Mutex<ManagerState>>) {
+    let config_path = get_config_path();
+    let (config, report) = match UserConfig::load(&config_path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("Failed to reload {}: {e}", config_path.display());
+            send_notification(format!(
+                "The changed settings could not be loaded, keeping the previous ones: {e}"
+            ));
+            return;
+        }
+    };
+    let old = get_config();
+    for warning in &report.warnings {
+        error!("Problem in {}: {warning}", config_path.display());
+        send_notification(warning.clone());
+    }
+    let server_port = manager_state.lock().unwrap().server_port;
+    let restart_needed = restart_needed(&old, &config, server_port);
+    if !restart_needed.is_empty() {
+        info!(
+            "Changed settings need a restart: {}",
+            restart_needed.join(", ")
+        );
+        send_notification(format!(
+            "Restart Aw-Tauri to apply the new {}",
+            restart_needed.join(" and ")
+        ));
+    }
+    crate::set_config(config);
+    let new = get_config();
+    info!("Reloaded {}", config_path.display());
+
+    let mut state = manager_state.lock().unwrap();
+    let profile = state.active_profile.clone();
+    state.apply_module_changes(
+        old.profile_modules(profile.as_deref()),
+        new.profile_modules(profile.as_deref()),
+    );
+    drop(state);
+
+    if !crate::is_headless() {
+        if let Err(e) = get_app_handle().emit(CONFIG_RELOADED_EVENT, &*new) {
+            error!("Failed to emit {CONFIG_RELOADED_EVENT}: {e}");
+        }
+    }
+}
+
+/// Returns the changed settings that are only read at startup.
+///
+/// The port is compared with the one in use, since `set_port` saves the port it switched to.
+fn restart_needed(old: &UserConfig, new: &UserConfig, server_port: u16) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if old.defaults.port != new.defaults.port && new.defaults.port != server_port {
+        changed.push("port");
+    }
+    if old.defaults.db_path != new.defaults.db_path {
+        changed.push("database path");
+    }
+    if old.server.mode != new.server.mode || old.server.remote_url != new.server.remote_url {
+        changed.push("server settings");
+    }
+    changed
+}
@@ -0,0 +1,207 @@
+/// Crash reports for aw-tauri itself
+///
+/// A panic hook writes a report with the panic message, a backtrace, build and OS info and the
+/// tail of the log to `<log dir>/crashes`. Aborts and signals can't be handled that way, so a
+/// session marker is written on startup and removed on a clean exit: if it is still there on the
+/// next start, the previous session died abnormally and a report is written for it then.
+///
+/// Panics in tauri callbacks are caught with [`guard`] so they don't unwind across the event
+/// loop, which would abort without unwinding on some platforms.
+use log::{error, info, warn};
+use std::backtrace::Backtrace;
+use std::fs::{self, read_dir, read_to_string, remove_file, write};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::thread;
+use sysinfo::System;
+use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::dirs::create_dir_with_retry;
+use crate::logging::get_log_dir;
+use crate::open_folder;
+
+const SESSION_MARKER: &str = "session.running";
+const LOG_TAIL_LINES: usize = 200;
+const MAX_REPORTS: usize = 5;
+
+pub fn get_crash_dir() -> PathBuf {
+    get_log_dir().join("crashes")
+}
+
+fn session_marker_path() -> PathBuf {
+    get_crash_dir().join(SESSION_MARKER)
+}
+
+fn is_report(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "txt")
+}
+
+/// Returns the reports in the crash dir, oldest first
+fn list_reports() -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(get_crash_dir()) else {
+        return Vec::new();
+    };
+    let mut reports: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_report(path))
+        .collect();
+    // Report names start with a sortable timestamp
+    reports.sort();
+    reports
+}
+
+fn prune_reports() {
+    let reports = list_reports();
+    let excess = reports.len().saturating_sub(MAX_REPORTS);
+    for report in &reports[..excess] {
+        if let Err(e) = remove_file(report) {
+            warn!(
+                "Failed to remove old crash report {}: {e}",
+                report.display()
+            );
+        }
+    }
+}
+
+fn log_tail() -> String {
+    let log = read_to_string(get_log_dir().join("aw-tauri.log")).unwrap_or_default();
+    let lines: Vec<&str> = log.lines().collect();
+    lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n")
+}
+
+fn build_report(reason: &str, backtrace: Option<&Backtrace>) -> String {
+    let backtrace = backtrace
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "not available".to_string());
+    format!(
+        "Reason: {reason}\n\n\
+        Version: {}\n\
+        OS: {} ({} {})\n\
+        Kernel: {}\n\n\
+        Backtrace:\n{backtrace}\n\n\
+        Last {LOG_TAIL_LINES} log lines:\n{}\n",
+        crate::build_info::BUILD_INFO.version_line(),
+        System::long_os_version().unwrap_or_else(|| "unknown".to_string()),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        System::kernel_version().unwrap_or_else(|| "unknown".to_string()),
+        log_tail(),
+    )
+}
+
+fn write_report(report: &str) -> std::io::Result<PathBuf> {
+    let dir = get_crash_dir();
+    create_dir_with_retry(&dir)?;
+    let name = format!(
+        "crash-{}.txt",
+        chrono::Local::now().format("%Y%m%d-%H%M%S%.3f")
+    );
+    let path = dir.join(name);
+    write(&path, report)?;
+    prune_reports();
+    Ok(path)
+}
+
+fn on_panic(info: &PanicHookInfo) {
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string());
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_else(|| "unknown location".to_string());
+    let thread = thread::current();
+    let reason = format!(
+        "panic in thread '{}' at {location}: {message}",
+        thread.name().unwrap_or("<unnamed>")
+    );
+    error!("{reason}");
+
+    let backtrace = Backtrace::force_capture();
+    match write_report(&build_report(&reason, Some(&backtrace))) {
+        Ok(path) => eprintln!("Crash report written to {}", path.display()),
+        Err(e) => eprintln!("Failed to write crash report: {e}"),
+    }
+}
+
+/// Installs the panic hook and checks whether the previous session ended abnormally.
+///
+/// Returns the report of the previous session's crash, if there was one. Should be called before
+/// logging is set up so the log tail still belongs to the previous session.
+pub fn install() -> Option<PathBuf> {
+    let marker = session_marker_path();
+    let previous_crash = fs::metadata(&marker).ok().and_then(|metadata| {
+        let started = metadata.modified().ok()?;
+        // A panic already left a report behind, otherwise it was an abort or a signal
+        let latest = list_reports().pop();
+        let reported = latest.as_ref().and_then(|report| {
+            let written = fs::metadata(report).ok()?.modified().ok()?;
+            (written >= started).then_some(report.clone())
+        });
+        reported.or_else(|| {
+            write_report(&build_report(
+                "terminated unexpectedly (abort, signal or power loss)",
+                None,
+            ))
+            .ok()
+        })
+    });
+
+    if let Err(e) = create_dir_with_retry(&get_crash_dir())
+        .and_then(|_| write(&marker, chrono::Local::now().to_rfc3339()))
+    {
+        eprintln!("Failed to write session marker: {e}");
+    }
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        on_panic(info);
+        default_hook(info);
+    }));
+
+    previous_crash
+}
+
+/// Marks the session as cleanly ended
+pub fn clear_session_marker() {
+    if let Err(e) = remove_file(session_marker_path()) {
+        warn!("Failed to remove session marker: {e}");
+    }
+}
+
+/// Runs a tauri callback, catching panics so they don't cross the event loop boundary.
+///
+/// The panic hook has already written a report by the time the panic is caught here.
+pub fn guard<F: FnOnce()>(context: &str, f: F) {
+    if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+        error!("Recovered from a panic in {context}");
+    }
+}
+
+/// Tells the user about the previous crash and offers to reveal the report folder
+pub fn offer_report_folder(app: AppHandle, report: PathBuf) {
+    info!("Previous session crashed, report: {}", report.display());
+    thread::spawn(move || {
+        let open = app
+            .dialog()
+            .message(
+                "Aw-Tauri quit unexpectedly last time. A crash report was saved, \
+                please attach it when reporting the problem.",
+            )
+            .kind(MessageDialogKind::Warning)
+            .title("Aw-Tauri")
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                "Show report".to_string(),
+                "Dismiss".to_string(),
+            ))
+            .blocking_show();
+        if open {
+            open_folder(&app, &get_crash_dir());
+        }
+    });
+}
@@ -0,0 +1,213 @@
+/// Reuse of an existing ActivityWatch database on first run
+///
+/// A previous installation (aw-qt with aw-server-rust or the Python aw-server) leaves its
+/// database in the standard ActivityWatch data directory. Instead of starting from an empty
+/// datastore, the user is offered to keep using the aw-server-rust database or to import the
+/// Python one through the datastore's legacy import.
+use log::{info, warn};
+use std::fs::{self, write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::{get_app_handle, get_config_path, update_config};
+
+const RUST_DB_DIR: &str = "aw-server-rust";
+const RUST_DB_FILE: &str = "sqlite.db";
+const PYTHON_DB_DIR: &str = "aw-server";
+const PYTHON_DB_FILE: &str = "peewee-sqlite.v2.db";
+const DECLINED_MARKER: &str = "db-import-declined";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseKind {
+    /// aw-server-rust sqlite database, can be used directly
+    Rust,
+    /// aw-server-python peewee database, imported through `legacy_import`
+    Python,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseCandidate {
+    pub kind: DatabaseKind,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseChoice {
+    /// Use the database at the given path instead of creating a new one
+    UseExisting(PathBuf),
+    /// Create a new database and import the aw-server-python data into it
+    LegacyImport,
+    /// Create a new, empty database
+    Fresh,
+}
+
+/// Finds existing databases in the ActivityWatch data directory (the parent of the
+/// aw-server-rust data dir), skipping the database aw-tauri itself would use.
+pub fn find_database_candidates(activitywatch_dir: &Path, own_db: &Path) -> Vec<DatabaseCandidate> {
+    [
+        (
+            DatabaseKind::Rust,
+            activitywatch_dir.join(RUST_DB_DIR).join(RUST_DB_FILE),
+        ),
+        (
+            DatabaseKind::Python,
+            activitywatch_dir.join(PYTHON_DB_DIR).join(PYTHON_DB_FILE),
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, path)| path != own_db)
+    .filter_map(|(kind, path)| {
+        let metadata = fs::metadata(&path).ok()?;
+        if !metadata.is_file() || metadata.len() == 0 {
+            return None;
+        }
+        Some(DatabaseCandidate {
+            kind,
+            path,
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        })
+    })
+    .collect()
+}
+
+/// Maps an accepted candidate to what the datastore should do with it
+pub fn choice_for(candidate: &DatabaseCandidate) -> DatabaseChoice {
+    match candidate.kind {
+        DatabaseKind::Rust => DatabaseChoice::UseExisting(candidate.path.clone()),
+        DatabaseKind::Python => DatabaseChoice::LegacyImport,
+    }
+}
+
+fn describe(candidate: &DatabaseCandidate) -> String {
+    let kind = match candidate.kind {
+        DatabaseKind::Rust => "aw-server-rust",
+        DatabaseKind::Python => "aw-server (Python)",
+    };
+    let modified: chrono::DateTime<chrono::Local> = candidate.modified.into();
+    format!(
+        "{kind} database\n{}\n{:.1} MB, last modified {}",
+        candidate.path.display(),
+        candidate.size as f64 / 1_000_000.0,
+        modified.format("%Y-%m-%d %H:%M")
+    )
+}
+
+fn declined_marker_path() -> PathBuf {
+    get_config_path()
+        .parent()
+        .expect("config path has no parent")
+        .join(DECLINED_MARKER)
+}
+
+/// Asks the user which existing database to use, one candidate at a time.
+///
+/// Blocks on the dialogs, so this must not be called from the main thread.
+pub fn choose_database(candidates: &[DatabaseCandidate]) -> DatabaseChoice {
+    if candidates.is_empty() || declined_marker_path().exists() {
+        return DatabaseChoice::Fresh;
+    }
+
+    let app = get_app_handle();
+    for candidate in candidates {
+        let accepted = app
+            .dialog()
+            .message(format!(
+                "An existing ActivityWatch database was found:\n\n{}\n\nDo you want to keep using your existing data?",
+                describe(candidate)
+            ))
+            .kind(MessageDialogKind::Info)
+            .title("Aw-Tauri")
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                "Use this database".to_string(),
+                "Skip".to_string(),
+            ))
+            .blocking_show();
+        if accepted {
+            info!("Using existing database: {}", candidate.path.display());
+            return choice_for(candidate);
+        }
+    }
+
+    info!("Existing databases declined, starting with an empty database");
+    if let Err(e) = write(declined_marker_path(), "") {
+        warn!("Failed to remember declined database import: {e}");
+    }
+    DatabaseChoice::Fresh
+}
+
+/// Persists a directly used database so later launches keep using it
+pub fn remember_choice(choice: &DatabaseChoice) {
+    if let DatabaseChoice::UseExisting(path) = choice {
+        if let Err(e) = update_config(|config| config.defaults.db_path = Some(path.clone())) {
+            warn!("Failed to remember the database {}: {e}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a new empty ActivityWatch data folder
+    fn activitywatch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aw-tauri-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn fake_db(dir: &Path, folder: &str, file: &str, contents: &[u8]) -> PathBuf {
+        fs::create_dir_all(dir.join(folder)).unwrap();
+        let path = dir.join(folder).join(file);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn finds_both_databases() {
+        let dir = activitywatch_dir("db-both");
+        let rust_db = fake_db(&dir, RUST_DB_DIR, RUST_DB_FILE, b"rust");
+        let python_db = fake_db(&dir, PYTHON_DB_DIR, PYTHON_DB_FILE, b"python");
+        let candidates = find_database_candidates(&dir, &dir.join("aw-tauri/sqlite.db"));
+        let found: Vec<_> = candidates
+            .iter()
+            .map(|candidate| (candidate.kind, candidate.path.clone(), candidate.size))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (DatabaseKind::Rust, rust_db.clone(), 4),
+                (DatabaseKind::Python, python_db, 6),
+            ]
+        );
+        assert_eq!(
+            choice_for(&candidates[0]),
+            DatabaseChoice::UseExisting(rust_db)
+        );
+        assert_eq!(choice_for(&candidates[1]), DatabaseChoice::LegacyImport);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_the_own_empty_and_missing_databases() {
+        let dir = activitywatch_dir("db-skipped");
+        // aw-tauri's own database, e.g. when it shares aw-server-rust's folder
+        let own_db = fake_db(&dir, RUST_DB_DIR, RUST_DB_FILE, b"own");
+        fake_db(&dir, PYTHON_DB_DIR, PYTHON_DB_FILE, b"");
+        assert!(find_database_candidates(&dir, &own_db).is_empty());
+        assert!(find_database_candidates(&dir.join("missing"), &own_db).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_folders_named_like_databases() {
+        let dir = activitywatch_dir("db-folder");
+        fs::create_dir_all(dir.join(RUST_DB_DIR).join(RUST_DB_FILE)).unwrap();
+        assert!(find_database_candidates(&dir, &dir.join("own.db")).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+/// Diagnostics export for bug reports
+///
+/// Writes a single gzipped JSON file with a manifest of what is included, the build info, the
+/// config, the module states and a `doctor` report. The aw-tauri log and the recent module output can hold private
+/// data such as window titles, so the caller decides whether they are included.
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::AppHandle;
+use tauri_plugin_dialog::DialogExt;
+
+use crate::build_info::{BuildInfo, BUILD_INFO};
+use crate::doctor;
+use crate::manager::{CrashRecord, ManagerState};
+use crate::{logging, read_current_config, UserConfig};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct DiagnosticsOptions {
+    /// Include the current aw-tauri log
+    #[serde(default)]
+    pub include_log: bool,
+    /// Include the recent output of each module
+    #[serde(default)]
+    pub include_module_output: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest {
+    created: String,
+    build: BuildInfo,
+    server_url: String,
+    included: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct Diagnostics {
+    manifest: Manifest,
+    config: UserConfig,
+    modules_running: BTreeMap<String, bool>,
+    missing_modules: Vec<String>,
+    crash_history: BTreeMap<String, Vec<CrashRecord>>,
+    doctor: doctor::Report,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module_output: Option<BTreeMap<String, Vec<String>>>,
+}
+
+fn collect(
+    state: &ManagerState,
+    options: DiagnosticsOptions,
+    doctor: doctor::Report,
+) -> Diagnostics {
+    let mut included = vec!["config", "modules", "crash_history", "doctor"];
+    let mut config = read_current_config();
+    // The webhook URL may embed a token
+    if config.defaults.crash_webhook_url.is_some() {
+        config.defaults.crash_webhook_url = Some("(redacted)".to_string());
+    }
+    let log = options.include_log.then(|| {
+        included.push("log");
+        read_to_string(logging::get_log_dir().join(logging::LOG_FILE)).unwrap_or_default()
+    });
+    let module_output = options.include_module_output.then(|| {
+        included.push("module_output");
+        state
+            .installed_modules()
+            .into_iter()
+            .chain(state.modules_running.keys().cloned())
+            .map(|name| {
+                let output = state.get_module_output(&name);
+                (name, output)
+            })
+            .filter(|(_, output)| !output.is_empty())
+            .collect()
+    });
+    Diagnostics {
+        manifest: Manifest {
+            created: chrono::Local::now().to_rfc3339(),
+            build: BUILD_INFO,
+            server_url: state.server_url(),
+            included,
+        },
+        config,
+        modules_running: state.modules_running.clone(),
+        missing_modules: state.missing_modules(),
+        crash_history: state
+            .modules_crash_history
+            .keys()
+            .map(|name| (name.clone(), state.get_crash_history(name)))
+            .collect(),
+        doctor,
+        log,
+        module_output,
+    }
+}
+
+/// Writes the diagnostics to a file picked by the user, returning its path.
+///
+/// Blocks on the dialog, so this must not be called from the main thread.
+pub fn export_diagnostics(
+    app: &AppHandle,
+    manager_state: &Arc<Mutex<ManagerState>>,
+    options: DiagnosticsOptions,
+) -> Result<PathBuf, String> {
+    // Before locking the manager, the checks may wait on the server
+    let doctor = doctor::run(Some(app));
+    let diagnostics = collect(&manager_state.lock().unwrap(), options, doctor);
+    let json = serde_json::to_vec_pretty(&diagnostics).map_err(|e| e.to_string())?;
+    let file_name = format!(
+        "aw-tauri-diagnostics-{}.json.gz",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let path = app
+        .dialog()
+        .file()
+        .add_filter("Gzipped JSON", &["gz"])
+        .set_file_name(file_name)
+        .blocking_save_file()
+        .ok_or("Export cancelled")?
+        .into_path()
+        .map_err(|e| e.to_string())?;
+    let file =
+        File::create(&path).map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder
+        .write_all(&json)
+        .and_then(|()| encoder.finish())
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    info!("Exported diagnostics to {}", path.display());
+    Ok(path)
+}
@@ -0,0 +1,80 @@
+/// Locating and creating aw-tauri's folders
+///
+/// Creating a folder is retried a few times, since antivirus software and slow network homes can
+/// make it fail transiently. Without a home folder a folder in the temp directory is used, so
+/// aw-tauri can still start and explain what is wrong.
+use directories::{ProjectDirs, UserDirs};
+use log::{error, warn};
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const CREATE_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+fn fallback_dir() -> PathBuf {
+    std::env::temp_dir().join("aw-tauri")
+}
+
+pub fn project_dirs() -> Option<ProjectDirs> {
+    let project_dirs = ProjectDirs::from("net", "ActivityWatch", "Aw-Tauri");
+    if project_dirs.is_none() {
+        error!(
+            "Failed to find the home folder, using {}",
+            fallback_dir().display()
+        );
+    }
+    project_dirs
+}
+
+/// Returns aw-tauri's data folder, holding e.g. the logs
+#[cfg(not(test))]
+pub fn app_data_dir() -> PathBuf {
+    project_dirs().map_or_else(fallback_dir, |dirs| dirs.data_dir().to_path_buf())
+}
+
+/// Returns the data folder of the test run, keeping the files the tests write out of the user's
+#[cfg(test)]
+pub fn app_data_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("aw-tauri-test-{}", std::process::id()));
+    let _ = create_dir_all(&dir);
+    dir
+}
+
+/// Returns aw-tauri's config folder
+pub fn app_config_dir() -> PathBuf {
+    project_dirs().map_or_else(fallback_dir, |dirs| dirs.config_dir().to_path_buf())
+}
+
+pub fn home_dir() -> PathBuf {
+    match UserDirs::new() {
+        Some(dirs) => dirs.home_dir().to_path_buf(),
+        None => {
+            error!(
+                "Failed to find the home folder, using {}",
+                fallback_dir().display()
+            );
+            fallback_dir()
+        }
+    }
+}
+
+/// Creates a folder and its parents, retrying a couple of times on failure
+pub fn create_dir_with_retry(path: &Path) -> std::io::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match create_dir_all(path) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < CREATE_ATTEMPTS => {
+                warn!(
+                    "Failed to create {} (attempt {attempt}), retrying: {e}",
+                    path.display()
+                );
+                attempt += 1;
+                thread::sleep(RETRY_DELAY);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
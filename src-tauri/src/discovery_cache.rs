@@ -0,0 +1,82 @@
+/// Cache of the discovered modules
+///
+/// Scanning every PATH folder on startup is slow on some machines, so the discovered name→path
+/// map is kept next to the config. On startup the cached map is used if it was made with the same
+/// search folders and patterns, isn't older than `MAX_AGE` and all cached executables still
+/// exist; a rediscovery in the background then picks up any changes.
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{get_config, get_config_path};
+
+const CACHE_FILE: &str = "module-cache.json";
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DiscoveryCache {
+    /// Unix timestamp of the discovery
+    discovered_at: u64,
+    /// What the discovery depended on, the cache is invalid once it changes
+    fingerprint: String,
+    modules: BTreeMap<String, PathBuf>,
+}
+
+fn cache_path() -> PathBuf {
+    get_config_path()
+        .parent()
+        .expect("config path has no parent")
+        .join(CACHE_FILE)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn fingerprint() -> String {
+    let config = get_config();
+    let defaults = &config.defaults;
+    format!(
+        "{}\n{}\n{:?}",
+        std::env::var("PATH").unwrap_or_default(),
+        defaults.discovery_path.display(),
+        defaults.executable_patterns
+    )
+}
+
+/// Returns the cached modules if the cache is still valid
+pub fn load() -> Option<BTreeMap<String, PathBuf>> {
+    let cache: DiscoveryCache = serde_json::from_str(&read_to_string(cache_path()).ok()?).ok()?;
+    if cache.fingerprint != fingerprint() {
+        debug!("Module cache is for other search folders, discovering modules");
+        return None;
+    }
+    if now().saturating_sub(cache.discovered_at) > MAX_AGE.as_secs() {
+        debug!("Module cache is stale, discovering modules");
+        return None;
+    }
+    if !cache.modules.values().all(|path| path.exists()) {
+        debug!("A cached module is gone, discovering modules");
+        return None;
+    }
+    Some(cache.modules)
+}
+
+pub fn save(modules: &BTreeMap<String, PathBuf>) {
+    let cache = DiscoveryCache {
+        discovered_at: now(),
+        fingerprint: fingerprint(),
+        modules: modules.clone(),
+    };
+    let result = serde_json::to_string_pretty(&cache)
+        .map_err(|e| e.to_string())
+        .and_then(|json| write(cache_path(), json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Failed to save the module cache: {e}");
+    }
+}
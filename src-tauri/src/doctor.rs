@@ -0,0 +1,466 @@
+/// Built-in diagnostics check, `aw-tauri doctor`
+///
+/// Runs the checks support usually walks users through: does the config load, is the port free
+/// or answered by a server, are the modules to start discovered, can aw-tauri write its folders,
+/// is autostart registered and, on macOS, are the watchers' permissions granted. Each check is
+/// independent, so one failing doesn't hide the others. `aw-tauri doctor` prints the report as
+/// text, or as JSON with `--json`, and exits with 1 if a check failed.
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{remove_file, write};
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri_plugin_autostart::ManagerExt;
+
+use crate::server::Server;
+use crate::{get_config_path, UserConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub message: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, status: Status, message: impl Into<String>) -> Check {
+        Check {
+            name: name.into(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Returns the worst status of the checks
+    pub fn status(&self) -> Status {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(Status::Pass)
+    }
+
+    /// Returns the report as text, one line per check
+    pub fn describe(&self) -> String {
+        self.checks
+            .iter()
+            .map(|check| {
+                let status = match check.status {
+                    Status::Pass => "PASS",
+                    Status::Warn => "WARN",
+                    Status::Fail => "FAIL",
+                };
+                format!("[{status}] {}: {}", check.name, check.message)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Runs all checks, including autostart when the app is running
+pub fn run(app: Option<&AppHandle>) -> Report {
+    let config_path = get_config_path();
+    let (config_check, config) = check_config(&config_path);
+    let mut checks = vec![config_check];
+    checks.push(check_server(&config));
+    checks.push(check_modules(
+        &config,
+        &crate::manager::get_modules_in_path(),
+    ));
+    let folders = [
+        ("config folder", config_path.parent().map(Path::to_path_buf)),
+        ("data folder", Some(crate::data_dir(&config))),
+        ("log folder", Some(crate::logging::get_log_dir())),
+        ("app data folder", Some(crate::dirs::app_data_dir())),
+    ];
+    for (name, dir) in folders {
+        checks.push(check_writable(name, dir.as_deref()));
+    }
+    if let Some(app) = app {
+        checks.push(check_autostart(
+            config.defaults.autostart,
+            app.autolaunch().is_enabled().map_err(|e| e.to_string()),
+        ));
+    }
+    #[cfg(target_os = "macos")]
+    checks.extend(check_permissions(
+        &config,
+        crate::permissions::Permission::is_granted,
+    ));
+    Report { checks }
+}
+
+/// Runs the checks for `aw-tauri doctor`, returning the exit code
+pub fn run_cli(json: bool) -> i32 {
+    let report = run(None);
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(report) => println!("{report}"),
+            Err(e) => {
+                eprintln!("Failed to serialize the report: {e}");
+                return 1;
+            }
+        }
+    } else {
+        println!("{}", report.describe());
+    }
+    i32::from(report.status() == Status::Fail)
+}
+
+/// Loads the config, returning the defaults for the remaining checks if it doesn't load
+pub fn check_config(path: &Path) -> (Check, UserConfig) {
+    const NAME: &str = "config";
+    match UserConfig::load(path) {
+        Ok((config, report)) if report.first_run => (
+            Check::new(
+                NAME,
+                Status::Pass,
+                format!("{} doesn't exist yet, using the defaults", path.display()),
+            ),
+            config,
+        ),
+        Ok((config, report)) if !report.warnings.is_empty() => (
+            Check::new(NAME, Status::Warn, report.warnings.join(" ")),
+            config,
+        ),
+        Ok((config, _)) => (
+            Check::new(NAME, Status::Pass, format!("{} is valid", path.display())),
+            config,
+        ),
+        Err(e) => (
+            Check::new(
+                NAME,
+                Status::Fail,
+                format!("{} could not be loaded: {e}", path.display()),
+            ),
+            UserConfig::default(),
+        ),
+    }
+}
+
+/// Checks that the server port is free, or answered by an ActivityWatch server
+pub fn check_server(config: &UserConfig) -> Check {
+    const NAME: &str = "server";
+    let port = config.defaults.port;
+    let server = match config.server.server(PathBuf::new()) {
+        Ok(server) => server,
+        Err(e) => return Check::new(NAME, Status::Fail, e),
+    };
+    if let Server::Remote(url) = &server {
+        return if server.is_healthy(port) {
+            Check::new(NAME, Status::Pass, format!("{url} is reachable"))
+        } else {
+            Check::new(NAME, Status::Fail, format!("{url} is not reachable"))
+        };
+    }
+    match crate::is_port_available(port) {
+        Ok(true) => Check::new(NAME, Status::Pass, format!("Port {port} is free")),
+        Ok(false) if server.is_healthy(port) => Check::new(
+            NAME,
+            Status::Pass,
+            format!("An ActivityWatch server answers on port {port}"),
+        ),
+        Ok(false) => Check::new(
+            NAME,
+            Status::Fail,
+            format!("Port {port} is used by another program"),
+        ),
+        Err(e) => Check::new(
+            NAME,
+            Status::Fail,
+            format!("Could not check port {port}: {e}"),
+        ),
+    }
+}
+
+/// Checks that the modules to start were discovered
+pub fn check_modules(config: &UserConfig, discovered: &BTreeMap<String, PathBuf>) -> Check {
+    const NAME: &str = "modules";
+    let modules = config.profile_modules(config.active_profile.as_deref());
+    let missing: Vec<&str> = modules
+        .iter()
+        .map(|module| module.name.as_str())
+        .filter(|name| !discovered.contains_key(*name))
+        .collect();
+    if missing.is_empty() {
+        Check::new(
+            NAME,
+            Status::Pass,
+            format!(
+                "All {} modules to start were found, {} discovered in total",
+                modules.len(),
+                discovered.len()
+            ),
+        )
+    } else {
+        Check::new(
+            NAME,
+            Status::Warn,
+            format!(
+                "Not found in PATH or the discovery folder: {}",
+                missing.join(", ")
+            ),
+        )
+    }
+}
+
+/// Checks that a file can be created in `dir`, creating the folder if needed
+pub fn check_writable(name: &str, dir: Option<&Path>) -> Check {
+    let Some(dir) = dir else {
+        return Check::new(name, Status::Fail, "The folder could not be determined");
+    };
+    let probe = dir.join(".aw-tauri-doctor");
+    let result = crate::dirs::create_dir_with_retry(dir)
+        .and_then(|()| write(&probe, ""))
+        .and_then(|()| remove_file(&probe));
+    match result {
+        Ok(()) => Check::new(name, Status::Pass, format!("{} is writable", dir.display())),
+        Err(e) => Check::new(
+            name,
+            Status::Fail,
+            format!("{} is not writable: {e}", dir.display()),
+        ),
+    }
+}
+
+/// Checks that autostart is registered as configured
+pub fn check_autostart(configured: bool, registered: Result<bool, String>) -> Check {
+    const NAME: &str = "autostart";
+    match registered {
+        Ok(registered) if registered == configured => Check::new(
+            NAME,
+            Status::Pass,
+            if registered {
+                "Registered to start at login"
+            } else {
+                "Disabled in the config and not registered"
+            },
+        ),
+        Ok(true) => Check::new(
+            NAME,
+            Status::Warn,
+            "Registered to start at login although disabled in the config",
+        ),
+        Ok(false) => Check::new(
+            NAME,
+            Status::Warn,
+            "Enabled in the config but not registered to start at login",
+        ),
+        Err(e) => Check::new(
+            NAME,
+            Status::Warn,
+            format!("Could not read the registration: {e}"),
+        ),
+    }
+}
+
+/// Checks the permissions of the modules to start, `is_granted` telling if one is granted
+#[cfg(target_os = "macos")]
+pub fn check_permissions(
+    config: &UserConfig,
+    is_granted: impl Fn(crate::permissions::Permission) -> bool,
+) -> Vec<Check> {
+    config
+        .profile_modules(config.active_profile.as_deref())
+        .iter()
+        .filter(|module| !crate::permissions::required_permissions(&module.name).is_empty())
+        .map(|module| {
+            let name = format!("permissions of {}", module.name);
+            let missing = crate::permissions::missing_permissions(&module.name, &is_granted);
+            if missing.is_empty() {
+                Check::new(name, Status::Pass, "Granted")
+            } else {
+                let missing: Vec<&str> =
+                    missing.iter().map(|permission| permission.name()).collect();
+                Check::new(
+                    name,
+                    Status::Fail,
+                    format!("Missing: {}", missing.join(", ")),
+                )
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Returns an empty folder for the test `name`
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("aw-tauri-doctor-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config_on_port(port: u16) -> UserConfig {
+        let mut config = UserConfig::default();
+        config.defaults.port = port;
+        config
+    }
+
+    #[test]
+    fn config_check() {
+        let dir = temp_dir("config");
+        let path = dir.join("config.toml");
+        assert_eq!(check_config(&path).0.status, Status::Pass);
+
+        write(&path, "[defaults]\nport = 5700\n").unwrap();
+        let (check, config) = check_config(&path);
+        assert_eq!(check.status, Status::Pass);
+        assert_eq!(config.defaults.port, 5700);
+
+        write(&path, "[defaults]\nport = 0\n").unwrap();
+        let (check, config) = check_config(&path);
+        assert_eq!(check.status, Status::Warn);
+        assert_eq!(config.defaults.port, UserConfig::default().defaults.port);
+
+        write(&path, "[defaults\n").unwrap();
+        let (check, config) = check_config(&path);
+        assert_eq!(check.status, Status::Fail);
+        assert_eq!(config.defaults.port, UserConfig::default().defaults.port);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn server_check() {
+        crate::test_support::init();
+        let free = TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        assert_eq!(check_server(&config_on_port(free)).status, Status::Pass);
+
+        let activitywatch = crate::test_support::fake_activitywatch_server();
+        let check = check_server(&config_on_port(activitywatch));
+        assert_eq!(check.status, Status::Pass);
+        assert!(check.message.contains("ActivityWatch server"));
+
+        let other = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = other.local_addr().unwrap().port();
+        assert_eq!(check_server(&config_on_port(port)).status, Status::Fail);
+
+        let mut remote = config_on_port(free);
+        remote.server.mode = crate::server::ServerMode::Remote;
+        assert_eq!(check_server(&remote).status, Status::Fail);
+        remote.server.remote_url = Some(format!("http://127.0.0.1:{activitywatch}"));
+        assert_eq!(check_server(&remote).status, Status::Pass);
+        remote.server.remote_url = Some(format!("http://127.0.0.1:{free}"));
+        assert_eq!(check_server(&remote).status, Status::Fail);
+    }
+
+    #[test]
+    fn modules_check() {
+        let config = UserConfig::default();
+        let mut discovered: BTreeMap<String, PathBuf> = config
+            .autostart_modules
+            .iter()
+            .map(|module| (module.name.clone(), PathBuf::from(&module.name)))
+            .collect();
+        assert_eq!(check_modules(&config, &discovered).status, Status::Pass);
+
+        let removed = config.autostart_modules[0].name.clone();
+        discovered.remove(&removed);
+        let check = check_modules(&config, &discovered);
+        assert_eq!(check.status, Status::Warn);
+        assert!(check.message.ends_with(&removed));
+    }
+
+    #[test]
+    fn writable_check() {
+        let dir = temp_dir("writable");
+        assert_eq!(
+            check_writable("data folder", Some(&dir.join("new"))).status,
+            Status::Pass
+        );
+        assert!(dir.join("new").is_dir());
+        assert!(!dir.join("new/.aw-tauri-doctor").exists());
+
+        // A file where the folder should be
+        let file = dir.join("file");
+        write(&file, "").unwrap();
+        assert_eq!(
+            check_writable("data folder", Some(&file)).status,
+            Status::Fail
+        );
+        assert_eq!(check_writable("data folder", None).status, Status::Fail);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn autostart_check() {
+        let cases = [
+            (true, Ok(true), Status::Pass),
+            (false, Ok(false), Status::Pass),
+            (false, Ok(true), Status::Warn),
+            (true, Ok(false), Status::Warn),
+            (true, Err("no launch agent".to_string()), Status::Warn),
+        ];
+        for (configured, registered, status) in cases {
+            assert_eq!(
+                check_autostart(configured, registered.clone()).status,
+                status,
+                "configured {configured}, registered {registered:?}"
+            );
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn permissions_check() {
+        use crate::permissions::Permission;
+        let mut config = UserConfig::default();
+        config.autostart_modules = vec![
+            toml::from_str("name = \"aw-watcher-afk\"").unwrap(),
+            toml::from_str("name = \"aw-watcher-window\"").unwrap(),
+        ];
+        let checks = check_permissions(&config, |_| true);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].name, "permissions of aw-watcher-window");
+        assert_eq!(checks[0].status, Status::Pass);
+
+        let checks = check_permissions(&config, |permission| {
+            permission == Permission::Accessibility
+        });
+        assert_eq!(checks[0].status, Status::Fail);
+        assert_eq!(
+            checks[0].message,
+            format!("Missing: {}", Permission::ScreenRecording.name())
+        );
+    }
+
+    #[test]
+    fn report_takes_the_worst_status() {
+        let report = Report {
+            checks: vec![
+                Check::new("config", Status::Pass, "valid"),
+                Check::new("modules", Status::Warn, "missing"),
+            ],
+        };
+        assert_eq!(report.status(), Status::Warn);
+        assert_eq!(
+            report.describe(),
+            "[PASS] config: valid\n[WARN] modules: missing"
+        );
+        assert_eq!(Report { checks: Vec::new() }.status(), Status::Pass);
+    }
+}
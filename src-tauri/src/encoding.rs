@@ -0,0 +1,72 @@
+/// Decoding of module output
+///
+/// Module output is expected to be UTF-8, but some Windows watchers write in the system code
+/// page. With `module_output_encoding` set, output that isn't valid UTF-8 is converted from
+/// Latin-1 or the system code page instead of being mangled.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputEncoding {
+    /// UTF-8, replacing invalid sequences
+    #[default]
+    Utf8,
+    Latin1,
+    /// The ANSI code page on Windows, UTF-8 elsewhere
+    System,
+}
+
+impl OutputEncoding {
+    pub fn decode(self, bytes: &[u8]) -> String {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return text.to_string();
+        }
+        match self {
+            OutputEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            OutputEncoding::Latin1 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+            OutputEncoding::System => decode_system(bytes),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn decode_system(bytes: &[u8]) -> String {
+    use winapi::um::stringapiset::MultiByteToWideChar;
+    use winapi::um::winnls::CP_ACP;
+
+    let Ok(len) = i32::try_from(bytes.len()) else {
+        return String::from_utf8_lossy(bytes).into_owned();
+    };
+    if len == 0 {
+        return String::new();
+    }
+    // SAFETY: the buffers and their lengths match, the first call only measures
+    unsafe {
+        let wide_len = MultiByteToWideChar(
+            CP_ACP,
+            0,
+            bytes.as_ptr().cast(),
+            len,
+            std::ptr::null_mut(),
+            0,
+        );
+        if wide_len <= 0 {
+            return String::from_utf8_lossy(bytes).into_owned();
+        }
+        let mut wide = vec![0u16; wide_len as usize];
+        MultiByteToWideChar(
+            CP_ACP,
+            0,
+            bytes.as_ptr().cast(),
+            len,
+            wide.as_mut_ptr(),
+            wide_len,
+        );
+        String::from_utf16_lossy(&wide)
+    }
+}
+
+#[cfg(not(windows))]
+fn decode_system(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
@@ -0,0 +1,276 @@
+/// Exporting events to CSV or JSON
+///
+/// Events are read from the server's REST API, flattened into one row per
+/// event and written to a file picked with the save dialog.
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveTime, TimeZone, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::write;
+use std::time::Duration;
+use tauri::AppHandle;
+use tauri_plugin_dialog::DialogExt;
+
+use crate::send_notification;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ExportRange {
+    Today,
+    Custom {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+}
+
+impl ExportRange {
+    fn bounds(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        match self {
+            ExportRange::Today => {
+                let midnight = Local::now().date_naive().and_time(NaiveTime::MIN);
+                let start = Local
+                    .from_local_datetime(&midnight)
+                    .earliest()
+                    .unwrap_or_else(Local::now)
+                    .with_timezone(&Utc);
+                (start, start + ChronoDuration::days(1))
+            }
+            ExportRange::Custom { start, end } => (*start, *end),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketSelection {
+    #[default]
+    All,
+    /// Only the window and afk buckets
+    WindowAfk,
+}
+
+impl BucketSelection {
+    fn includes(self, bucket_type: &str) -> bool {
+        match self {
+            BucketSelection::All => true,
+            BucketSelection::WindowAfk => {
+                bucket_type == "currentwindow" || bucket_type == "afkstatus"
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    pub duration: f64,
+    #[serde(default)]
+    pub data: serde_json::Map<String, Value>,
+}
+
+/// One exported event
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRow {
+    pub bucket: String,
+    pub timestamp: DateTime<Utc>,
+    pub duration: f64,
+    pub data: serde_json::Map<String, Value>,
+}
+
+pub fn flatten(bucket: &str, events: Vec<Event>) -> Vec<EventRow> {
+    events
+        .into_iter()
+        .map(|event| EventRow {
+            bucket: bucket.to_string(),
+            timestamp: event.timestamp,
+            duration: event.duration,
+            data: event.data,
+        })
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes the rows as CSV, with the event data as a JSON column
+pub fn to_csv(rows: &[EventRow]) -> String {
+    let mut csv = String::from("bucket,timestamp,duration,data\n");
+    for row in rows {
+        let data = Value::Object(row.data.clone()).to_string();
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_field(&row.bucket),
+            row.timestamp.to_rfc3339(),
+            row.duration,
+            csv_field(&data)
+        ));
+    }
+    csv
+}
+
+pub fn to_json(rows: &[EventRow]) -> String {
+    serde_json::to_string_pretty(rows).expect("failed to serialize events")
+}
+
+fn fetch_rows(
+    base_url: &str,
+    range: &ExportRange,
+    buckets: BucketSelection,
+) -> Result<Vec<EventRow>, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let base = format!("{base_url}/api/0");
+    let get = |url: String| -> Result<Value, String> {
+        client
+            .get(url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .map_err(|e| format!("Failed to query the server: {e}"))
+    };
+
+    let bucket_list = get(format!("{base}/buckets/"))?;
+    let bucket_list = bucket_list
+        .as_object()
+        .ok_or("Unexpected bucket list from the server")?;
+    let (start, end) = range.bounds();
+    let mut rows = Vec::new();
+    for (id, bucket) in bucket_list {
+        let bucket_type = bucket.get("type").and_then(Value::as_str).unwrap_or("");
+        if !buckets.includes(bucket_type) {
+            continue;
+        }
+        let events = get(format!(
+            "{base}/buckets/{id}/events?start={}&end={}&limit=-1",
+            urlencode(&start.to_rfc3339()),
+            urlencode(&end.to_rfc3339())
+        ))?;
+        let events: Vec<Event> = serde_json::from_value(events)
+            .map_err(|e| format!("Unexpected events from the server: {e}"))?;
+        rows.extend(flatten(id, events));
+    }
+    rows.sort_by_key(|row| row.timestamp);
+    Ok(rows)
+}
+
+fn urlencode(value: &str) -> String {
+    value.replace('+', "%2B").replace(':', "%3A")
+}
+
+/// Exports the events of the range to a file picked by the user, returning the number of rows.
+///
+/// Blocks on the server requests and the dialog, so this must not be called from the main thread
+/// or the async runtime.
+pub fn export_events(
+    app: &AppHandle,
+    base_url: &str,
+    range: ExportRange,
+    format: ExportFormat,
+    buckets: BucketSelection,
+) -> Result<usize, String> {
+    let rows = fetch_rows(base_url, &range, buckets)?;
+    let (extension, contents) = match format {
+        ExportFormat::Csv => ("csv", to_csv(&rows)),
+        ExportFormat::Json => ("json", to_json(&rows)),
+    };
+    let file_name = format!(
+        "activitywatch-{}.{extension}",
+        range.bounds().0.with_timezone(&Local).format("%Y-%m-%d")
+    );
+    let Some(path) = app
+        .dialog()
+        .file()
+        .add_filter(extension.to_uppercase(), &[extension])
+        .set_file_name(file_name)
+        .blocking_save_file()
+    else {
+        return Err("Export cancelled".to_string());
+    };
+    let path = path.into_path().map_err(|e| e.to_string())?;
+    write(&path, contents).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+
+    info!("Exported {} events to {}", rows.len(), path.display());
+    send_notification(format!("Exported {} events", rows.len()));
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_events() -> Vec<Event> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/export/window-events.json");
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn events_are_flattened_with_their_bucket() {
+        let rows = flatten("aw-watcher-window_desk", fixture_events());
+        assert_eq!(rows.len(), 3);
+        assert!(rows
+            .iter()
+            .all(|row| row.bucket == "aw-watcher-window_desk"));
+        assert_eq!(rows[1].duration, 900.0);
+        assert_eq!(rows[1].data["app"], "Code");
+        // Events without data get an empty object
+        assert!(rows[2].data.is_empty());
+    }
+
+    #[test]
+    fn csv_quotes_the_data() {
+        let csv = to_csv(&flatten("aw-watcher-window_desk", fixture_events()));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "bucket,timestamp,duration,data",
+                r#"aw-watcher-window_desk,2024-03-04T09:15:00+00:00,42.5,"{""app"":""Firefox"",""title"":""Inbox, 3 unread - \""Mail\""""}""#,
+                r#"aw-watcher-window_desk,2024-03-04T09:00:00.250+00:00,900,"{""app"":""Code"",""title"":""main.rs""}""#,
+                "aw-watcher-window_desk,2024-03-04T08:59:00+00:00,0,{}",
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_field_quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn json_keeps_the_rows() {
+        let rows = flatten("aw-watcher-window_desk", fixture_events());
+        let json: Value = serde_json::from_str(&to_json(&rows)).unwrap();
+        assert_eq!(json[0]["bucket"], "aw-watcher-window_desk");
+        assert_eq!(json[0]["timestamp"], "2024-03-04T09:15:00Z");
+        assert_eq!(json[0]["data"]["app"], "Firefox");
+        assert_eq!(json.as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn bucket_selection() {
+        assert!(BucketSelection::All.includes("app.editor.activity"));
+        assert!(BucketSelection::WindowAfk.includes("currentwindow"));
+        assert!(BucketSelection::WindowAfk.includes("afkstatus"));
+        assert!(!BucketSelection::WindowAfk.includes("web.tab.current"));
+    }
+}
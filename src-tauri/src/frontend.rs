@@ -0,0 +1,147 @@
+/// What the manager shows the user
+///
+/// The manager reports module states, crashes and questions through a `Frontend` instead of
+/// reaching for the app handle, so it also runs without a tray or dialogs. `AppFrontend` shows
+/// them in the app, `HeadlessFrontend` only logs them and the tests record them.
+use log::info;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::get_app_handle;
+use crate::notifications::Notification;
+use crate::tray_menu::{self, TrayMenu};
+
+/// Run when the user agrees to what they were asked, e.g. to retry a crashed module
+pub type OnAccept = Box<dyn FnOnce() + Send>;
+
+/// What happens to a crashed module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashOutcome {
+    Restarting,
+    /// It crashed too often in a row to be restarted again
+    LimitReached,
+    /// `restart.limit` is 0
+    RestartsDisabled,
+}
+
+impl CrashOutcome {
+    pub fn is_restarting(self) -> bool {
+        self == CrashOutcome::Restarting
+    }
+
+    /// Tells the user what happened to the crashed module `name`
+    pub fn message(self, name: &str) -> String {
+        match self {
+            CrashOutcome::Restarting => format!("{name} crashed. Restarting..."),
+            CrashOutcome::LimitReached => {
+                format!("{name} keeps on crashing. Restart limit reached.")
+            }
+            CrashOutcome::RestartsDisabled => {
+                format!("{name} crashed and was not restarted, restarts are disabled.")
+            }
+        }
+    }
+}
+
+pub trait Frontend: Send + Sync + std::fmt::Debug {
+    /// Shows the module states, e.g. in the tray menu
+    fn update_modules(&self, menu: TrayMenu);
+    /// Changes the labels of the shown modules, given by id, leaving everything else as is
+    fn update_labels(&self, labels: Vec<(String, String)>);
+    /// Tells the user a module crashed. Unless it is restarting, `retry` starts it again.
+    fn module_crashed(&self, name: &str, outcome: CrashOutcome, retry: OnAccept);
+    /// Asks whether to replace a module process started outside of aw-tauri with `replace`
+    fn ask_to_replace(&self, name: &str, pid: u32, replace: OnAccept);
+    fn notify(&self, notification: Notification);
+}
+
+/// The tray, dialogs and notifications of the app
+#[derive(Debug)]
+pub struct AppFrontend;
+
+impl Frontend for AppFrontend {
+    fn update_modules(&self, menu: TrayMenu) {
+        tray_menu::request_update(menu);
+    }
+
+    fn update_labels(&self, labels: Vec<(String, String)>) {
+        tray_menu::request_labels(labels);
+    }
+
+    fn module_crashed(&self, name: &str, outcome: CrashOutcome, retry: OnAccept) {
+        if outcome.is_restarting() {
+            get_app_handle()
+                .dialog()
+                .message(outcome.message(name))
+                .kind(MessageDialogKind::Error)
+                .title("Aw-Tauri")
+                .show(|_| {});
+        } else if cfg!(mobile) {
+            // Mobile notifications offer restarting the module and opening its log
+            crate::send_crash_notification(name, outcome.message(name));
+        } else {
+            get_app_handle()
+                .dialog()
+                .message(outcome.message(name))
+                .kind(MessageDialogKind::Error)
+                .title("Warning")
+                .buttons(MessageDialogButtons::OkCancelCustom(
+                    "Retry".to_string(),
+                    "Close".to_string(),
+                ))
+                .show(move |accepted| {
+                    if accepted {
+                        retry();
+                    }
+                });
+        }
+    }
+
+    fn ask_to_replace(&self, name: &str, pid: u32, replace: OnAccept) {
+        get_app_handle()
+            .dialog()
+            .message(format!(
+                "{name} is already running (pid {pid}), maybe started by aw-qt. Stop it and let \
+                Aw-Tauri run {name}?"
+            ))
+            .kind(MessageDialogKind::Warning)
+            .title("Aw-Tauri")
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                "Replace".to_string(),
+                "Keep it".to_string(),
+            ))
+            .show(move |accepted| {
+                if accepted {
+                    replace();
+                }
+            });
+    }
+
+    fn notify(&self, notification: Notification) {
+        crate::send_notification(notification);
+    }
+}
+
+/// Logs what would be shown, for running without a display
+#[derive(Debug)]
+pub struct HeadlessFrontend;
+
+impl Frontend for HeadlessFrontend {
+    fn update_modules(&self, _menu: TrayMenu) {}
+
+    fn update_labels(&self, _labels: Vec<(String, String)>) {}
+
+    fn module_crashed(&self, _name: &str, _outcome: CrashOutcome, _retry: OnAccept) {
+        // The manager already logged the crash
+    }
+
+    fn ask_to_replace(&self, name: &str, pid: u32, _replace: OnAccept) {
+        info!("Keeping {name} (pid {pid}), there is no one to ask whether to replace it");
+    }
+
+    fn notify(&self, notification: Notification) {
+        match notification.title {
+            Some(title) => info!("{title}: {}", notification.body),
+            None => info!("{}", notification.body),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+/// Running without a tray or window
+///
+/// On servers and machines without a display, `--headless` (or `defaults.headless`) runs the
+/// embedded server and the modules without creating a tray, window or dialogs, none of which
+/// work there. Problems that would show a dialog are only logged. Runs until interrupted with
+/// Ctrl+C or SIGTERM, stopping the modules on the way out.
+use log::{error, info, warn};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::{get_config, is_address_available, manager, ports, server};
+
+/// Runs the server and the modules until interrupted
+pub fn run() {
+    info!("Running headless");
+    let user_config = get_config();
+    for warning in crate::take_config_warnings() {
+        warn!("{warning}");
+    }
+
+    let testing = true;
+    let mut aw_config = aw_server::config::create_config(testing);
+    aw_config.address = user_config.defaults.host.clone();
+    let own_db_path = aw_server::dirs::db_path(testing).expect("Failed to get db path");
+    let db_path = user_config.defaults.db_path.clone().unwrap_or(own_db_path);
+    let asset_path = std::env::var("AW_WEBUI_DIR").ok().map(PathBuf::from);
+
+    let server = user_config
+        .server
+        .server(db_path.clone())
+        .unwrap_or_else(|e| {
+            error!("Falling back to the embedded server: {e}");
+            server::Server::Embedded
+        });
+    if server.is_separate() {
+        // Health checks report to the tray and the dashboard, which don't exist here
+        info!(
+            "Using the server at {}",
+            server.base_url(user_config.defaults.port)
+        );
+    }
+
+    let port = match server.remote_url() {
+        Some(url) => url
+            .port_or_known_default()
+            .unwrap_or(user_config.defaults.port),
+        None => ports::preferred_port(user_config.defaults.port),
+    };
+    if server.remote_url().is_none()
+        && !is_address_available(&aw_config.address, port).unwrap_or(false)
+    {
+        error!("Port {port} is already in use, quitting");
+        std::process::exit(1);
+    }
+
+    if !server.is_separate() {
+        server::launch_embedded(
+            aw_config,
+            port,
+            db_path,
+            asset_path,
+            aw_server::device_id::get_device_id(),
+            false,
+        );
+    }
+    let manager_state = manager::start_manager(
+        true,
+        port,
+        server,
+        Arc::new(crate::frontend::HeadlessFrontend),
+    );
+    crate::power::start_battery_monitor(manager_state.clone());
+    crate::schedule::start_scheduler(manager_state.clone());
+    crate::config_reload::start_watching(manager_state.clone());
+
+    tauri::async_runtime::block_on(wait_for_shutdown());
+    info!("Shutting down");
+    crate::manager::stop_modules_and_wait(&manager_state, crate::modules_stop_timeout());
+    crate::shutdown::request();
+    crate::shutdown::join(crate::SHUTDOWN_TIMEOUT);
+    crate::crash::clear_session_marker();
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl+C: {e}");
+        std::future::pending::<()>().await;
+    }
+}
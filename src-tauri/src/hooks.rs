@@ -0,0 +1,224 @@
+/// User hooks on module lifecycle events
+///
+/// The `[hooks]` table maps events to commands, which are run with environment variables
+/// describing the event (`AW_EVENT`, `AW_MODULE`, `AW_EXIT_CODE`, ...). Hooks run in their own
+/// thread with a timeout and their output goes to the log; a failing hook never affects the
+/// manager.
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::get_config;
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module_started: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module_stopped: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module_crashed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking_paused: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking_resumed: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    ModuleStarted,
+    ModuleStopped,
+    ModuleCrashed,
+    TrackingPaused,
+    TrackingResumed,
+}
+
+impl HookEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::ModuleStarted => "module_started",
+            HookEvent::ModuleStopped => "module_stopped",
+            HookEvent::ModuleCrashed => "module_crashed",
+            HookEvent::TrackingPaused => "tracking_paused",
+            HookEvent::TrackingResumed => "tracking_resumed",
+        }
+    }
+}
+
+impl HooksConfig {
+    fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::ModuleStarted => self.module_started.as_deref(),
+            HookEvent::ModuleStopped => self.module_stopped.as_deref(),
+            HookEvent::ModuleCrashed => self.module_crashed.as_deref(),
+            HookEvent::TrackingPaused => self.tracking_paused.as_deref(),
+            HookEvent::TrackingResumed => self.tracking_resumed.as_deref(),
+        }
+    }
+}
+
+/// Builds the environment a hook runs with
+pub fn hook_env(event: HookEvent, vars: &[(&str, String)]) -> Vec<(String, String)> {
+    std::iter::once(("AW_EVENT".to_string(), event.name().to_string()))
+        .chain(
+            vars.iter()
+                .map(|(key, value)| (key.to_string(), value.clone())),
+        )
+        .collect()
+}
+
+/// Runs the hook configured for `event` in the background, if there is one
+pub fn run_hook(event: HookEvent, vars: &[(&str, String)]) {
+    let config = get_config();
+    let Some(command) = config.hooks.command(event) else {
+        return;
+    };
+    let command = command.to_string();
+    let env = hook_env(event, vars);
+    thread::spawn(move || {
+        if let Err(e) = run_command(&command, &env, HOOK_TIMEOUT) {
+            warn!("Hook for {} failed: {e}", event.name());
+        }
+    });
+}
+
+/// Runs a hook command, killing it if it doesn't finish within `timeout`
+fn run_command(command: &str, env: &[(String, String)], timeout: Duration) -> Result<(), String> {
+    let args = shell_words::split(command).map_err(|e| format!("invalid command: {e}"))?;
+    let Some((program, args)) = args.split_first() else {
+        return Err("empty command".to_string());
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to start {program}: {e}"))?;
+
+    // Read the output while waiting so the hook can't block on a full pipe
+    let readers = [
+        child.stdout.take().map(|mut stdout| {
+            thread::spawn(move || {
+                let mut output = String::new();
+                let _ = stdout.read_to_string(&mut output);
+                output
+            })
+        }),
+        child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut output = String::new();
+                let _ = stderr.read_to_string(&mut output);
+                output
+            })
+        }),
+    ];
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}s", timeout.as_secs()));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(format!("failed to wait for hook: {e}")),
+        }
+    };
+
+    let [stdout, stderr] =
+        readers.map(|reader| reader.and_then(|r| r.join().ok()).unwrap_or_default());
+    if !stdout.trim().is_empty() {
+        debug!("Hook {program} stdout: {}", stdout.trim_end());
+    }
+    if !stderr.trim().is_empty() {
+        debug!("Hook {program} stderr: {}", stderr.trim_end());
+    }
+    if !status.success() {
+        return Err(format!("{program} exited with {status}"));
+    }
+    info!("Hook {program} finished");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_are_parsed_per_event() {
+        let config: HooksConfig =
+            toml::from_str("module_crashed = \"notify-send crashed\"").unwrap();
+        assert_eq!(
+            config.command(HookEvent::ModuleCrashed),
+            Some("notify-send crashed")
+        );
+        assert_eq!(config.command(HookEvent::ModuleStarted), None);
+        assert_eq!(config.command(HookEvent::TrackingPaused), None);
+    }
+
+    #[test]
+    fn env_starts_with_the_event() {
+        let env = hook_env(
+            HookEvent::ModuleStopped,
+            &[("AW_MODULE", "aw-watcher-afk".to_string())],
+        );
+        assert_eq!(
+            env,
+            [
+                ("AW_EVENT".to_string(), "module_stopped".to_string()),
+                ("AW_MODULE".to_string(), "aw-watcher-afk".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_and_invalid_commands_fail() {
+        assert!(run_command("", &[], HOOK_TIMEOUT).is_err());
+        assert!(run_command("echo 'unterminated", &[], HOOK_TIMEOUT).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hook_runs_with_the_env() {
+        let script =
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/hooks/write-env");
+        let output = std::env::temp_dir().join(format!("aw-tauri-hook-{}", std::process::id()));
+        let command = shell_words::join([script.to_str().unwrap(), output.to_str().unwrap()]);
+        let env = hook_env(
+            HookEvent::ModuleCrashed,
+            &[
+                ("AW_MODULE", "aw-watcher-window".to_string()),
+                ("AW_EXIT_CODE", "3".to_string()),
+            ],
+        );
+        run_command(&command, &env, HOOK_TIMEOUT).unwrap();
+        let written = std::fs::read_to_string(&output).unwrap();
+        let _ = std::fs::remove_file(&output);
+        assert_eq!(written, "module_crashed aw-watcher-window 3\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_hook_is_an_error() {
+        assert!(run_command("false", &[], HOOK_TIMEOUT).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slow_hook_is_killed() {
+        let started = Instant::now();
+        let result = run_command("sleep 10", &[], Duration::from_millis(300));
+        assert!(result.unwrap_err().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
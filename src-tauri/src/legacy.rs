@@ -0,0 +1,266 @@
+/// Detection of a legacy ActivityWatch installation (aw-qt + aw-server)
+///
+/// Users migrating to aw-tauri often still have aw-qt set to start at login. Running both
+/// results in two servers and two sets of watchers, which doubles every event.
+///
+/// At startup we look for running aw-qt/aw-server processes and for the autostart entries
+/// aw-qt leaves behind, and offer to disable the latter.
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::{get_app_handle, get_config_path};
+
+const LEGACY_PROCESSES: [&str; 2] = ["aw-qt", "aw-server"];
+
+/// Returns the names of running processes belonging to a legacy installation
+pub fn find_legacy_processes() -> Vec<String> {
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+
+    let mut found: Vec<String> = system
+        .processes()
+        .values()
+        .filter_map(|process| {
+            let name = Path::new(process.name()).file_stem()?.to_str()?.to_string();
+            LEGACY_PROCESSES.contains(&name.as_str()).then_some(name)
+        })
+        .collect();
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Returns `true` if the program of a desktop entry `Exec=` line is aw-qt
+#[cfg(target_os = "linux")]
+pub fn is_legacy_desktop_entry(contents: &str) -> bool {
+    contents
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Exec="))
+        .filter_map(|exec| shell_words::split(exec).ok())
+        .filter_map(|words| words.into_iter().next())
+        .any(|program| is_aw_qt(&program))
+}
+
+/// Returns `true` if a launchd plist runs aw-qt
+#[cfg(target_os = "macos")]
+pub fn is_legacy_launch_agent(contents: &str) -> bool {
+    contents
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("<string>"))
+        .filter_map(|value| value.strip_suffix("</string>"))
+        .any(is_aw_qt)
+}
+
+/// Returns `true` if a Windows startup folder item belongs to aw-qt
+#[cfg(windows)]
+pub fn is_legacy_startup_item(filename: &str) -> bool {
+    let stem = Path::new(filename)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    stem == "aw-qt" || stem == "activitywatch"
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn is_aw_qt(program: &str) -> bool {
+    Path::new(program)
+        .file_stem()
+        .is_some_and(|stem| stem.eq_ignore_ascii_case("aw-qt"))
+}
+
+#[cfg(target_os = "linux")]
+pub fn find_legacy_autostart_entries() -> Vec<PathBuf> {
+    let Some(userdirs) = directories::UserDirs::new() else {
+        return Vec::new();
+    };
+    let autostart_dir = userdirs.home_dir().join(".config/autostart");
+    find_entries(&autostart_dir, |path| {
+        path.extension().is_some_and(|ext| ext == "desktop")
+            && fs::read_to_string(path).is_ok_and(|contents| is_legacy_desktop_entry(&contents))
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub fn find_legacy_autostart_entries() -> Vec<PathBuf> {
+    let Some(userdirs) = directories::UserDirs::new() else {
+        return Vec::new();
+    };
+    let launch_agents_dir = userdirs.home_dir().join("Library/LaunchAgents");
+    find_entries(&launch_agents_dir, |path| {
+        path.extension().is_some_and(|ext| ext == "plist")
+            && fs::read_to_string(path).is_ok_and(|contents| is_legacy_launch_agent(&contents))
+    })
+}
+
+#[cfg(windows)]
+pub fn find_legacy_autostart_entries() -> Vec<PathBuf> {
+    let Some(appdata) = std::env::var_os("APPDATA") else {
+        return Vec::new();
+    };
+    let startup_dir = PathBuf::from(appdata).join(r"Microsoft\Windows\Start Menu\Programs\Startup");
+    find_entries(&startup_dir, |path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(is_legacy_startup_item)
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn find_legacy_autostart_entries() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn find_entries(dir: &Path, is_legacy: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_legacy(path))
+        .collect()
+}
+
+/// Moves the given autostart entries into a backup folder next to our config.
+///
+/// Moving instead of deleting keeps the change reversible: users can put the file back.
+pub fn disable_legacy_autostart(entries: &[PathBuf]) -> std::io::Result<PathBuf> {
+    let backup_dir = get_config_path()
+        .parent()
+        .expect("config path has no parent")
+        .join("legacy-autostart");
+    fs::create_dir_all(&backup_dir)?;
+    for entry in entries {
+        let filename = entry.file_name().expect("autostart entry has no filename");
+        fs::rename(entry, backup_dir.join(filename))?;
+        info!("Disabled legacy autostart entry: {}", entry.display());
+    }
+    Ok(backup_dir)
+}
+
+/// Warns the user about a legacy installation, if one is found
+pub fn check_legacy_installation() {
+    let processes = find_legacy_processes();
+    let entries = find_legacy_autostart_entries();
+    if processes.is_empty() && entries.is_empty() {
+        return;
+    }
+    warn!(
+        "Legacy ActivityWatch installation detected (running: {:?}, autostart entries: {:?})",
+        processes, entries
+    );
+
+    let mut message = String::from(
+        "An existing ActivityWatch installation (aw-qt) was found. \
+        Running it alongside Aw-Tauri tracks your activity twice and causes duplicate events.",
+    );
+    if !processes.is_empty() {
+        message.push_str(&format!("\n\nRunning: {}", processes.join(", ")));
+    }
+
+    let app = &get_app_handle();
+    let dialog = app
+        .dialog()
+        .message(message)
+        .kind(MessageDialogKind::Warning)
+        .title("Aw-Tauri");
+    if entries.is_empty() {
+        dialog.show(|_| {});
+        return;
+    }
+    dialog
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Disable the old autostart for me".to_string(),
+            "Ignore".to_string(),
+        ))
+        .show(move |disable| {
+            if !disable {
+                info!("User chose to ignore the legacy installation");
+                return;
+            }
+            if let Err(e) = disable_legacy_autostart(&entries) {
+                error!("Failed to disable legacy autostart: {e}");
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn desktop_entries() {
+        let accepted = [
+            "[Desktop Entry]\nType=Application\nExec=aw-qt\n",
+            "[Desktop Entry]\nExec=/opt/activitywatch/aw-qt --testing\n",
+            "[Desktop Entry]\n  Exec=\"/home/me/Activity Watch/aw-qt\"\n",
+            "[Desktop Entry]\nExec=AW-QT\n",
+        ];
+        for contents in accepted {
+            assert!(is_legacy_desktop_entry(contents), "{contents:?}");
+        }
+        let rejected = [
+            "[Desktop Entry]\nExec=aw-tauri\n",
+            // aw-qt only as an argument
+            "[Desktop Entry]\nExec=env NAME=aw-qt firefox\n",
+            "[Desktop Entry]\nExec=/opt/aw-qt-tools/launcher\n",
+            "[Desktop Entry]\nName=aw-qt\n",
+            // Unbalanced quotes can't be split
+            "[Desktop Entry]\nExec=\"aw-qt\n",
+            "",
+        ];
+        for contents in rejected {
+            assert!(!is_legacy_desktop_entry(contents), "{contents:?}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn finds_only_the_legacy_entries() {
+        let dir = std::env::temp_dir().join(format!("aw-tauri-autostart-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("aw-qt.desktop")).unwrap();
+        fs::write(dir.join("activitywatch.desktop"), "Exec=aw-qt\n").unwrap();
+        fs::write(dir.join("aw-tauri.desktop"), "Exec=aw-tauri\n").unwrap();
+        fs::write(dir.join("aw-qt.sh"), "Exec=aw-qt\n").unwrap();
+        let entries = find_entries(&dir, |path| {
+            path.extension().is_some_and(|ext| ext == "desktop")
+                && fs::read_to_string(path).is_ok_and(|contents| is_legacy_desktop_entry(&contents))
+        });
+        assert_eq!(entries, [dir.join("activitywatch.desktop")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn launch_agents() {
+        let accepted = "<dict>\n  <key>ProgramArguments</key>\n  <array>\n    \
+            <string>/Applications/ActivityWatch.app/Contents/MacOS/aw-qt</string>\n  </array>\n\
+            </dict>\n";
+        assert!(is_legacy_launch_agent(accepted));
+        let rejected = [
+            "<string>/Applications/aw-tauri.app/Contents/MacOS/aw-tauri</string>",
+            "<key>aw-qt</key>",
+            "<string>aw-qt-helper</string>",
+        ];
+        for contents in rejected {
+            assert!(!is_legacy_launch_agent(contents), "{contents:?}");
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn startup_items() {
+        for filename in ["aw-qt.lnk", "AW-QT.exe", "ActivityWatch.lnk"] {
+            assert!(is_legacy_startup_item(filename), "{filename:?}");
+        }
+        for filename in ["aw-tauri.lnk", "aw-qt-backup.lnk", "desktop.ini", ""] {
+            assert!(!is_legacy_startup_item(filename), "{filename:?}");
+        }
+    }
+}
@@ -1,40 +1,65 @@
-use aw_server::endpoints::build_rocket;
-#[cfg(not(target_os = "linux"))]
-use directories::ProjectDirs;
-use directories::UserDirs;
 use lazy_static::lazy_static;
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
-use std::fs::{create_dir_all, read_to_string, remove_file, write, OpenOptions};
-use std::net::{SocketAddr, TcpListener};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, remove_file, write, OpenOptions};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener, ToSocketAddrs};
 use std::path::{Path, PathBuf};
-use std::sync::{mpsc, Condvar, Mutex, OnceLock};
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock, RwLock};
 use std::thread;
 use std::time::Duration;
 use tauri_plugin_autostart::{MacosLauncher, ManagerExt};
-use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_opener::OpenerExt;
 
+mod build_info;
+mod config_reload;
+mod crash;
+mod db_import;
+mod diagnostics;
+mod dirs;
+mod discovery_cache;
+mod doctor;
+mod encoding;
+mod export;
+mod frontend;
+mod headless;
+mod hooks;
+mod legacy;
 mod logging;
 mod manager;
+mod menu_id;
+mod notifications;
+#[cfg(target_os = "macos")]
+mod permissions;
+mod pid_file;
+mod ports;
+mod power;
+mod releases;
+mod schedule;
+mod server;
+mod settings_bundle;
+mod shutdown;
+#[cfg(windows)]
+mod taskbar;
+#[cfg(test)]
+mod test_support;
+mod timings;
+mod tray_menu;
+mod updater;
+mod webhook;
 
-use log::info;
+use log::{debug, error, info, warn};
+use menu_id::MenuId;
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{TrayIconBuilder, TrayIconId},
-    AppHandle, Manager,
+    AppHandle, Manager, Url,
 };
 
-pub struct AppHandleWrapper(Mutex<AppHandle>);
-
-impl Drop for AppHandleWrapper {
-    fn drop(&mut self) {
-        let (_lock, cvar) = &*HANDLE_CONDVAR;
-        cvar.notify_all();
-    }
-}
-
-static HANDLE: OnceLock<AppHandleWrapper> = OnceLock::new();
+/// The app handle, cloned by its users rather than locked since `AppHandle` is `Send + Sync`
+static HANDLE: OnceLock<AppHandle> = OnceLock::new();
 lazy_static! {
     static ref HANDLE_CONDVAR: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
 }
@@ -52,44 +77,135 @@ static TRAY_ID: OnceLock<TrayIdWrapper> = OnceLock::new();
 lazy_static! {
     static ref TRAY_CONDVAR: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
 }
-static CONFIG: OnceLock<UserConfig> = OnceLock::new();
+/// The config, loaded on first use and replaced by `config_reload` when the file changes
+static CONFIG: RwLock<Option<Arc<UserConfig>>> = RwLock::new(None);
 static FIRST_RUN: OnceLock<bool> = OnceLock::new();
+static SAFE_MODE: OnceLock<bool> = OnceLock::new();
+static HEADLESS: OnceLock<bool> = OnceLock::new();
+/// Problems found while loading the config, shown once the tray exists since loading the
+/// config must not depend on the app handle
+static CONFIG_WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Notifications sent before the app handle was initialized
+static PENDING_NOTIFICATIONS: Mutex<
+    Vec<(notifications::Notification, notifications::NotificationKind)>,
+> = Mutex::new(Vec::new());
 
 fn init_app_handle(handle: AppHandle) {
-    HANDLE.get_or_init(|| AppHandleWrapper(Mutex::new(handle)));
+    // Before flushing the pending notifications, so they are routed by the permission
+    notifications::check_permission(&handle);
+    HANDLE.get_or_init(|| handle);
     let (lock, cvar) = &*HANDLE_CONDVAR;
     let mut started = lock.lock().expect("failed to lock HANDLE_CONDVAR");
     *started = true;
     cvar.notify_all();
+    drop(started);
+
+    let pending = std::mem::take(
+        &mut *PENDING_NOTIFICATIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()),
+    );
+    for (notification, kind) in pending {
+        show_notification(&notification, &kind);
+    }
+}
+
+pub(crate) fn get_app_handle() -> AppHandle {
+    HANDLE.get().expect("HANDLE not initialized").clone()
 }
 
-pub(crate) fn get_app_handle() -> &'static Mutex<AppHandle> {
-    &HANDLE.get().expect("HANDLE not initialized").0
+/// How long exiting waits for the background threads to finish
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time after the stop timeout for killed modules to be gone
+const MODULES_KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// Returns the time the modules get to exit before aw-tauri quits or restarts without them,
+/// enough for the modules ignoring the stop request to be killed
+fn modules_stop_timeout() -> Duration {
+    manager::stop_timeout() + MODULES_KILL_GRACE
+}
+
+/// How long `wait_for_app_handle` waits for setup to initialize the handle
+const HANDLE_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the app handle, waiting for setup to initialize it.
+///
+/// Returns `None` if it isn't there after `HANDLE_WAIT_TIMEOUT`.
+pub(crate) fn wait_for_app_handle() -> Option<AppHandle> {
+    if !wait_for_flag(&HANDLE_CONDVAR, HANDLE_WAIT_TIMEOUT) {
+        warn!("Timed out waiting for the app handle");
+    }
+    HANDLE.get().cloned()
 }
 
 fn init_tray_id(id: TrayIconId) {
     TRAY_ID
         .set(TrayIdWrapper(id))
         .expect("failed to set TRAY_ID");
-    let (lock, cvar) = &*TRAY_CONDVAR;
-    let mut initialized = lock.lock().expect("failed to lock TRAY_CONDVAR");
-    *initialized = true;
+    tray_ready();
+}
+
+/// Wakes up the threads waiting for the tray, after it was created or failed to be
+fn tray_ready() {
+    set_flag(&TRAY_CONDVAR);
+}
+
+/// Sets a flag waited for with `wait_for_flag`, waking the waiting threads
+fn set_flag((lock, cvar): &(Mutex<bool>, Condvar)) {
+    *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
     cvar.notify_all();
 }
 
-pub(crate) fn get_tray_id() -> &'static TrayIconId {
-    let (lock, cvar) = &*TRAY_CONDVAR;
-    let mut initialized = lock.lock().expect("failed to lock TRAY_CONDVAR");
-    while !*initialized {
-        initialized = cvar.wait(initialized).expect("failed to wait for TRAY_ID");
+/// Waits up to `timeout` for a flag to be set, returning whether it was
+fn wait_for_flag((lock, cvar): &(Mutex<bool>, Condvar), timeout: Duration) -> bool {
+    let set = lock.lock().unwrap_or_else(|e| e.into_inner());
+    let (set, _) = cvar
+        .wait_timeout_while(set, timeout, |set| !*set)
+        .unwrap_or_else(|e| e.into_inner());
+    *set
+}
+
+/// How long `get_tray_id` waits for setup to create the tray
+const TRAY_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the id of the tray icon, waiting for it to be created.
+///
+/// Returns `None` if the tray couldn't be created or isn't there after `TRAY_WAIT_TIMEOUT`.
+pub(crate) fn get_tray_id() -> Option<&'static TrayIconId> {
+    if !wait_for_flag(&TRAY_CONDVAR, TRAY_WAIT_TIMEOUT) {
+        warn!("Timed out waiting for the tray");
     }
-    &TRAY_ID.get().expect("TRAY_ID not initialized").0
+    TRAY_ID.get().map(|wrapper| &wrapper.0)
 }
 
 pub fn is_port_available(port: u16) -> std::io::Result<bool> {
-    let addr = format!("127.0.0.1:{}", port)
-        .parse::<SocketAddr>()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    is_address_available(&get_config().defaults.host, port)
+}
+
+/// Returns the host to reach the server on from this machine, loopback if it listens on all
+/// addresses
+pub(crate) fn local_host() -> String {
+    let host = &get_config().defaults.host;
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) if ip.is_unspecified() => Ipv4Addr::LOCALHOST.to_string(),
+        Ok(IpAddr::V6(ip)) if ip.is_unspecified() => Ipv6Addr::LOCALHOST.to_string(),
+        _ => host.clone(),
+    }
+}
+
+/// Returns whether the server only listens on this machine
+fn is_loopback_host(host: &str) -> bool {
+    host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Returns whether `port` can be bound on `address`, the address the server listens on
+pub(crate) fn is_address_available(address: &str, port: u16) -> std::io::Result<bool> {
+    let addr = (address, port).to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{address} did not resolve to an address"),
+        )
+    })?;
 
     match TcpListener::bind(addr) {
         Ok(_) => Ok(true), // Port is available
@@ -103,116 +219,397 @@ pub fn is_port_available(port: u16) -> std::io::Result<bool> {
     }
 }
 
+/// Returns `true` when launched with `--safe-mode` or `AW_TAURI_SAFE_MODE=1`.
+///
+/// Safe mode brings up the server, tray and UI without starting any modules, to recover from a
+/// misbehaving module.
+pub(crate) fn is_safe_mode() -> bool {
+    *SAFE_MODE.get_or_init(|| {
+        std::env::args().any(|arg| arg == "--safe-mode")
+            || std::env::var("AW_TAURI_SAFE_MODE").is_ok_and(|value| value == "1")
+    })
+}
+
+/// Returns `true` when launched with `--headless`, `AW_TAURI_HEADLESS=1` or with
+/// `defaults.headless` set, in which case no tray, window or dialogs are created
+pub(crate) fn is_headless() -> bool {
+    *HEADLESS.get_or_init(|| {
+        std::env::args().any(|arg| arg == "--headless")
+            || std::env::var("AW_TAURI_HEADLESS").is_ok_and(|value| value == "1")
+            || get_config().defaults.headless
+    })
+}
+
+/// Returns the profile passed with `--profile <name>`, which wins over `active_profile`
+pub(crate) fn cli_profile() -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--profile");
+    args.next()?;
+    args.next()
+}
+
 pub(crate) fn is_first_run() -> &'static bool {
     FIRST_RUN.get().expect("FIRST_RUN not initialized")
 }
 
+fn show_notification(
+    notification: &notifications::Notification,
+    kind: &notifications::NotificationKind,
+) {
+    let Some(app) = HANDLE.get() else {
+        return;
+    };
+    let title = notification.title.as_deref().unwrap_or("Aw-Tauri");
+    let body = &notification.body;
+    match notifications::route_for(kind) {
+        notifications::Route::Notification => {}
+        notifications::Route::Dialog => {
+            app.dialog()
+                .message(body)
+                .kind(MessageDialogKind::Warning)
+                .title(title)
+                .show(|_| {});
+            return;
+        }
+        notifications::Route::Log => {
+            info!("Notification (not permitted): {title}: {body}");
+            return;
+        }
+    }
+    let mut builder = app.notification().builder().title(title).body(body);
+    if let notifications::NotificationKind::ModuleCrash { module } = kind {
+        builder = builder
+            .action_type_id(notifications::MODULE_CRASH_TYPE)
+            .extra(notifications::MODULE_EXTRA, module);
+    }
+    if let Err(e) = builder.show() {
+        error!("Failed to show notification: {e}");
+    }
+}
+
+fn queue_notification(
+    notification: notifications::Notification,
+    kind: notifications::NotificationKind,
+) {
+    if is_headless() {
+        match &notification.title {
+            Some(title) => info!("{title}: {}", notification.body),
+            None => info!("{}", notification.body),
+        }
+        return;
+    }
+    {
+        // The handle is checked under the queue lock so a concurrent flush can't miss it
+        let mut pending = PENDING_NOTIFICATIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if HANDLE.get().is_none() {
+            pending.push((notification, kind));
+            return;
+        }
+    }
+    show_notification(&notification, &kind);
+}
+
+/// Shows a notification, or queues it until the app handle is initialized
+pub(crate) fn send_notification(notification: impl Into<notifications::Notification>) {
+    queue_notification(notification.into(), notifications::NotificationKind::Info);
+}
+
+/// Shows a notification about a crashed module, offering to restart it where supported
+pub(crate) fn send_crash_notification(
+    module: &str,
+    notification: impl Into<notifications::Notification>,
+) {
+    queue_notification(
+        notification.into(),
+        notifications::NotificationKind::ModuleCrash {
+            module: module.to_string(),
+        },
+    );
+}
+
+/// Applies the tooltip, icon and click behavior shared by all tray icons
+fn configure_tray(
+    app: &AppHandle,
+    builder: TrayIconBuilder<tauri::Wry>,
+) -> TrayIconBuilder<tauri::Wry> {
+    let builder = builder
+        .tooltip(if is_safe_mode() {
+            "Aw-Tauri (safe mode)"
+        } else {
+            "Aw-Tauri"
+        })
+        .show_menu_on_left_click(true);
+    // Custom builds may come without icons, a tray without one beats not starting
+    match app.default_window_icon() {
+        Some(icon) => builder.icon(icon.clone()),
+        None => {
+            warn!("No window icon available, creating the tray without an icon");
+            builder
+        }
+    }
+}
+
+/// Registers the tray icon again after Explorer restarted, with the current module states
+#[cfg(windows)]
+fn recreate_tray(app: &AppHandle, manager_state: &Arc<Mutex<manager::ManagerState>>) {
+    let Some(id) = get_tray_id().cloned() else {
+        return;
+    };
+    info!("Taskbar recreated, registering the tray icon again");
+    let _ = app.remove_tray_by_id(&id);
+    if let Err(e) = configure_tray(app, TrayIconBuilder::with_id(id)).build(app) {
+        error!("Failed to recreate the tray: {e}");
+        return;
+    }
+    manager_state.lock().unwrap().update_tray_menu();
+    info!("Tray icon registered again");
+}
+
 pub fn handle_first_run() {
     let first_run = is_first_run();
     if *first_run {
-        thread::spawn(|| {
-            let app = &*get_app_handle().lock().expect("failed to get app handle");
-            app.notification()
-                .builder()
-                .title("Aw-Tauri")
-                .body("Aw-Tauri is running in the background")
-                .show()
-                .unwrap();
-        });
+        thread::spawn(|| send_notification("Aw-Tauri is running in the background"));
     }
 }
 
 pub fn listen_for_lockfile() {
-    thread::spawn(|| {
+    shutdown::spawn("lockfile listener", || {
         let config_path = get_config_path();
         let watcher =
             SpecificFileWatcher::new(config_path.parent().unwrap(), "single_instance.lock")
                 .expect("Failed to create file watcher");
-        loop {
-            if watcher.wait_for_file().is_ok() {
-                remove_file(config_path.parent().unwrap().join("single_instance.lock"))
-                    .expect("Failed to remove lock file");
-                let app = &*get_app_handle().lock().expect("failed to get app handle");
-                if let Some(window) = app.webview_windows().get("main") {
-                    window.show().unwrap();
+        let watcher_shutdown = watcher.shutdown_handle();
+        shutdown::on_shutdown(move || watcher_shutdown.shutdown());
+        while watcher.wait_for_file().is_some() {
+            remove_file(config_path.parent().unwrap().join("single_instance.lock"))
+                .expect("Failed to remove lock file");
+            let app = get_app_handle();
+            if let Some(window) = app.webview_windows().get("main") {
+                if let Err(e) = window.show() {
+                    error!("Failed to show the main window: {e}");
                 }
             }
         }
     });
 }
 
+enum WatchMessage {
+    Event(Result<Event, notify::Error>),
+    Shutdown,
+}
+
+/// Stops a `SpecificFileWatcher` waiting in another thread
+#[derive(Clone)]
+pub struct WatcherShutdown(mpsc::Sender<WatchMessage>);
+
+impl WatcherShutdown {
+    pub fn shutdown(&self) {
+        let _ = self.0.send(WatchMessage::Shutdown);
+    }
+}
+
+/// Watches a folder for a file, or any file, being created or changed
 pub struct SpecificFileWatcher {
     #[allow(dead_code)]
     watcher: RecommendedWatcher,
-    rx: mpsc::Receiver<Result<Event, notify::Error>>,
-    target_file: PathBuf,
+    tx: mpsc::Sender<WatchMessage>,
+    rx: mpsc::Receiver<WatchMessage>,
+    /// The file to wait for, `None` for any file in the folder
+    target_file: Option<PathBuf>,
 }
 
 impl SpecificFileWatcher {
     pub fn new<P: AsRef<Path>>(dir_path: P, filename: &str) -> Result<Self, notify::Error> {
-        let (tx, rx) = mpsc::channel();
+        Self::watch(dir_path.as_ref(), Some(dir_path.as_ref().join(filename)))
+    }
 
-        let target_file = dir_path.as_ref().join(filename);
+    /// Watches for changes to any file directly in `dir_path`
+    pub fn any_file<P: AsRef<Path>>(dir_path: P) -> Result<Self, notify::Error> {
+        Self::watch(dir_path.as_ref(), None)
+    }
+
+    fn watch(dir_path: &Path, target_file: Option<PathBuf>) -> Result<Self, notify::Error> {
+        let (tx, rx) = mpsc::channel();
 
         // Configure the watcher with minimal overhead
         let config = Config::default().with_poll_interval(Duration::from_secs(1));
 
         // Create a watcher
-        let mut watcher = RecommendedWatcher::new(tx, config)?;
+        let event_tx = tx.clone();
+        let mut watcher = RecommendedWatcher::new(
+            move |event| {
+                let _ = event_tx.send(WatchMessage::Event(event));
+            },
+            config,
+        )?;
 
-        watcher.watch(dir_path.as_ref(), RecursiveMode::NonRecursive)?;
+        watcher.watch(dir_path, RecursiveMode::NonRecursive)?;
 
         Ok(Self {
             watcher,
+            tx,
             rx,
             target_file,
         })
     }
 
-    pub fn wait_for_file(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Returns a handle to stop `wait_for_file` from another thread
+    pub fn shutdown_handle(&self) -> WatcherShutdown {
+        WatcherShutdown(self.tx.clone())
+    }
+
+    /// Blocks until the watched file is created or changed, returning its path.
+    ///
+    /// Returns `None` once the watcher is shut down.
+    pub fn wait_for_file(&self) -> Option<PathBuf> {
         loop {
-            // Check for events
-            if let Ok(result) = self.rx.try_recv() {
-                match result {
-                    Ok(event) => match event.kind {
-                        EventKind::Create(_) | EventKind::Modify(_) => {
-                            if event.paths.iter().any(|p| p == &self.target_file) {
-                                return Ok(());
-                            }
-                        }
-                        _ => {}
-                    },
-                    Err(e) => eprintln!("Watch error: {}", e),
+            match self.rx.recv() {
+                Ok(WatchMessage::Event(Ok(event))) => {
+                    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        continue;
+                    }
+                    let changed = event.paths.into_iter().find(|path| {
+                        self.target_file
+                            .as_ref()
+                            .is_none_or(|target_file| path == target_file)
+                    });
+                    if changed.is_some() {
+                        return changed;
+                    }
                 }
+                Ok(WatchMessage::Event(Err(e))) => error!("Watch error: {e}"),
+                Ok(WatchMessage::Shutdown) | Err(_) => return None,
             }
-
-            // Avoid busy waiting
-            std::thread::sleep(Duration::from_millis(300));
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModuleConfig {
     pub name: String,
+    /// Key of this instance, to run the same module several times with different arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     #[serde(default = "String::new")]
     pub args: String,
+    /// Overrides `low_priority_modules` for this module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_priority: Option<bool>,
+    /// Folder the module is started in, for modules that look for files next to them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Modules to wait for on autostart, e.g. `["aw-watcher-afk"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    /// Show blocks of stdout between lines of 50 dashes as notifications
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub emit_notifications: bool,
+}
+
+impl ModuleConfig {
+    /// Returns the key the manager tracks this instance by
+    pub fn key(&self) -> &str {
+        self.id.as_deref().unwrap_or(&self.name)
+    }
+    /// Returns the arguments to start the module with, `None` for the default arguments
+    pub fn parsed_args(&self) -> Option<Vec<String>> {
+        if self.args.is_empty() {
+            None
+        } else {
+            // Split args string on whitespace, preserving quoted arguments
+            Some(shell_words::split(&self.args).unwrap_or_default())
+        }
+    }
+}
+
+/// A named set of modules, e.g. for work and home
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub modules: Vec<ModuleConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Defaults {
     pub autostart: bool,
     pub autostart_minimized: bool,
     pub port: u16,
+    /// Seconds a module gets to exit after being asked to stop, before it is killed
+    #[serde(default = "default_stop_timeout_secs")]
+    pub stop_timeout_secs: u64,
+    /// Seconds between checks that the running modules' processes are alive, 0 to not check
+    #[serde(default = "default_health_check_interval_secs")]
+    pub health_check_interval_secs: u64,
+    /// What to do when a module to start is already running, e.g. started by aw-qt: "adopt",
+    /// "ask" or "ignore" (the default). Only the first start of a module in a session checks.
+    #[serde(default)]
+    pub existing_instance: manager::ExistingInstance,
+    /// Address the server listens on, e.g. `0.0.0.0` to reach it from other machines
+    #[serde(default = "default_host")]
+    pub host: String,
     pub discovery_path: PathBuf,
+    #[serde(default)]
+    pub display_server: DisplayServer,
+    /// Database to use instead of the default aw-server-rust location
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_path: Option<PathBuf>,
+    /// Bytes of a crashed module's stdout and stderr written to the log
+    #[serde(default = "default_crash_output_max_bytes")]
+    pub crash_output_max_bytes: usize,
+    /// Run modules at a lowered scheduling priority
+    #[serde(default)]
+    pub low_priority_modules: bool,
+    /// URL module crashes are POSTed to, for unattended machines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash_webhook_url: Option<String>,
+    /// Arguments aw-tauri is started with on login, e.g. `["--profile", "work"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub autostart_args: Vec<String>,
+    /// Glob patterns of the file names discovered as modules, e.g. `["aw-*", "aw-*.sh"]`.
+    ///
+    /// Without patterns, extensionless `aw*` executables are discovered on Unix and `.exe` files
+    /// on Windows. Any matching executable in PATH or the discovery folder can be started, so
+    /// keep the patterns narrow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable_patterns: Option<Vec<String>>,
+    /// Encoding of module output that isn't valid UTF-8: "utf8", "latin1" or "system"
+    #[serde(default)]
+    pub module_output_encoding: encoding::OutputEncoding,
+    /// Run only the server and the modules, without a tray or window
+    #[serde(default)]
+    pub headless: bool,
+}
+
+fn default_crash_output_max_bytes() -> usize {
+    4096
+}
+
+fn default_stop_timeout_secs() -> u64 {
+    5
+}
+
+fn default_health_check_interval_secs() -> u64 {
+    30
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayServer {
+    #[default]
+    Auto,
+    X11,
+    Wayland,
 }
 
 impl Default for Defaults {
     fn default() -> Self {
         let discovery_path = if cfg!(unix) {
-            UserDirs::new()
-                .map(|dirs| dirs.home_dir().join("aw-modules"))
-                .unwrap_or_default()
+            dirs::home_dir().join("aw-modules")
         } else if cfg!(windows) {
             let username = std::env::var("USERNAME").unwrap_or_default();
             PathBuf::from(format!(r"C:\Users\{}\aw-modules", username))
@@ -224,100 +621,1054 @@ impl Default for Defaults {
             autostart: true,
             autostart_minimized: true,
             port: 5699, // TODO: update before going stable
+            stop_timeout_secs: default_stop_timeout_secs(),
+            health_check_interval_secs: default_health_check_interval_secs(),
+            existing_instance: manager::ExistingInstance::default(),
+            host: default_host(),
             discovery_path,
+            display_server: DisplayServer::default(),
+            db_path: None,
+            crash_output_max_bytes: default_crash_output_max_bytes(),
+            low_priority_modules: false,
+            crash_webhook_url: None,
+            autostart_args: Vec::new(),
+            executable_patterns: None,
+            module_output_encoding: encoding::OutputEncoding::default(),
+            headless: false,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserConfig {
+    /// Profile whose modules are started instead of `autostart_modules`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
     #[serde(default)]
     pub defaults: Defaults,
     #[serde(default)]
     pub autostart_modules: Vec<ModuleConfig>,
+    #[serde(default)]
+    pub updates: updater::UpdatesConfig,
+    #[serde(default)]
+    pub power: power::PowerConfig,
+    #[serde(default)]
+    pub schedule: schedule::ScheduleConfig,
+    #[serde(default)]
+    pub hooks: hooks::HooksConfig,
+    #[serde(default)]
+    pub restart: manager::RestartConfig,
+    #[serde(default)]
+    pub aw_notify: manager::AwNotifyConfig,
+    #[serde(default)]
+    pub server: server::ServerConfig,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// What was noticed while loading the config
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// The config file didn't exist, the defaults were used
+    pub first_run: bool,
+    /// Settings that were replaced with their defaults, for the user to fix
+    pub warnings: Vec<String>,
+}
+
+impl UserConfig {
+    /// Loads the config at `path`, falling back to the defaults for a missing file.
+    ///
+    /// Invalid settings are replaced with their defaults and reported in the `LoadReport`. Only
+    /// reads `path`, so it can be used before the app starts.
+    pub fn load(path: &Path) -> Result<(UserConfig, LoadReport), String> {
+        if !path.exists() {
+            let report = LoadReport {
+                first_run: true,
+                ..LoadReport::default()
+            };
+            return Ok((UserConfig::default(), report));
+        }
+        let config_str = read_to_string(path).map_err(|e| e.to_string())?;
+        UserConfig::parse(&config_str, &path.display().to_string())
+    }
+
+    /// Parses a config file's contents, `source` naming it in the warnings
+    pub fn parse(config_str: &str, source: &str) -> Result<(UserConfig, LoadReport), String> {
+        let mut config = toml::from_str::<UserConfig>(config_str).map_err(|e| e.to_string())?;
+        let mut report = LoadReport::default();
+        // Without an explicit module list, pick the watchers matching the display server
+        let has_modules = config_str
+            .parse::<toml::Table>()
+            .is_ok_and(|table| table.contains_key("autostart_modules"));
+        if !has_modules {
+            config.autostart_modules = default_autostart_modules(config.defaults.display_server);
+        }
+        if let Err(e) = validate_port(config.defaults.port) {
+            let fallback = Defaults::default().port;
+            report.warnings.push(format!(
+                "The port in {source} can't be used: {e}. Using port {fallback} instead."
+            ));
+            config.defaults.port = fallback;
+        }
+        Ok((config, report))
+    }
+
+    /// Returns the modules of the given profile, or `autostart_modules` without a profile
+    pub fn profile_modules(&self, profile: Option<&str>) -> &[ModuleConfig] {
+        profile
+            .and_then(|name| self.profiles.get(name))
+            .map(|profile| profile.modules.as_slice())
+            .unwrap_or(&self.autostart_modules)
+    }
 }
 
 impl Default for UserConfig {
     fn default() -> Self {
+        let defaults = Defaults::default();
+        let autostart_modules = default_autostart_modules(defaults.display_server);
         UserConfig {
-            defaults: Defaults::default(),
-            autostart_modules: vec![
-                ModuleConfig {
-                    name: "aw-watcher-afk".to_string(),
-                    args: String::new(),
-                },
-                ModuleConfig {
-                    name: "aw-watcher-window".to_string(),
-                    args: String::new(),
-                },
-                ModuleConfig {
-                    name: "aw-awatcher".to_string(),
-                    args: String::new(),
-                },
-            ],
+            active_profile: None,
+            defaults,
+            autostart_modules,
+            updates: updater::UpdatesConfig::default(),
+            power: power::PowerConfig::default(),
+            schedule: schedule::ScheduleConfig::default(),
+            hooks: hooks::HooksConfig::default(),
+            restart: manager::RestartConfig::default(),
+            aw_notify: manager::AwNotifyConfig::default(),
+            server: server::ServerConfig::default(),
+            profiles: BTreeMap::new(),
         }
     }
 }
 
+const WAYLAND_WATCHERS: &[&str] = &["aw-awatcher"];
+const X11_WATCHERS: &[&str] = &["aw-watcher-afk", "aw-watcher-window"];
+
+/// Returns the watchers tracking windows and afk-state for the display server
+fn display_server_watchers(display_server: DisplayServer) -> &'static [&'static str] {
+    if cfg!(target_os = "linux") && is_wayland(display_server) {
+        WAYLAND_WATCHERS
+    } else {
+        X11_WATCHERS
+    }
+}
+
+fn is_display_server_watcher(name: &str) -> bool {
+    WAYLAND_WATCHERS.contains(&name) || X11_WATCHERS.contains(&name)
+}
+
+/// Replaces the watchers of any display server in `modules`, every instance of them, with those
+/// of `display_server`. A list without watchers only gets them with `add_missing`.
+fn swap_watchers(
+    modules: &mut Vec<ModuleConfig>,
+    display_server: DisplayServer,
+    add_missing: bool,
+) {
+    let count = modules.len();
+    modules.retain(|module| !is_display_server_watcher(&module.name));
+    if add_missing || modules.len() != count {
+        modules.extend(default_autostart_modules(display_server));
+    }
+}
+
+fn default_autostart_modules(display_server: DisplayServer) -> Vec<ModuleConfig> {
+    display_server_watchers(display_server)
+        .iter()
+        .map(|name| ModuleConfig {
+            name: name.to_string(),
+            id: None,
+            args: String::new(),
+            low_priority: None,
+            cwd: None,
+            after: Vec::new(),
+            emit_notifications: false,
+        })
+        .collect()
+}
+
+/// Returns `true` if window tracking should use the Wayland watchers.
+///
+/// An explicit `display_server` in the config wins over detection.
+pub fn is_wayland(display_server: DisplayServer) -> bool {
+    match display_server {
+        DisplayServer::X11 => false,
+        DisplayServer::Wayland => true,
+        DisplayServer::Auto => detect_wayland(
+            std::env::var("XDG_SESSION_TYPE").ok().as_deref(),
+            std::env::var("WAYLAND_DISPLAY").ok().as_deref(),
+        ),
+    }
+}
+
+/// `WAYLAND_DISPLAY` is also set under XWayland and in mixed setups, so `XDG_SESSION_TYPE` is
+/// preferred and `WAYLAND_DISPLAY` is only used when the session type is unknown (e.g. "tty" when
+/// the compositor was started from a console).
+fn detect_wayland(session_type: Option<&str>, wayland_display: Option<&str>) -> bool {
+    match session_type.map(str::trim) {
+        Some("wayland") => true,
+        Some("x11") => false,
+        _ => wayland_display.is_some_and(|display| !display.is_empty()),
+    }
+}
+
 #[cfg(not(target_os = "linux"))]
 fn get_config_path() -> PathBuf {
-    let project_dirs =
-        ProjectDirs::from("net", "ActivityWatch", "Aw-Tauri").expect("Failed to get project dirs");
-    project_dirs.config_dir().join("config.toml")
+    dirs::app_config_dir().join("config.toml")
 }
 #[cfg(target_os = "linux")]
 fn get_config_path() -> PathBuf {
-    let userdirs = UserDirs::new().expect("Failed to get user dirs");
-    let home = userdirs.home_dir();
-    let config_dir = home.join(".config/activitywatch/aw-tauri");
+    let config_dir = dirs::home_dir().join(".config/activitywatch/aw-tauri");
     config_dir.join("config.toml")
 }
-pub(crate) fn get_config() -> &'static UserConfig {
-    CONFIG.get_or_init(|| {
-        let config_path = get_config_path();
-        if config_path.exists() {
-            FIRST_RUN.set(false).expect("failed to set FIRST_RUN");
-            let config_str = read_to_string(config_path).expect("Failed to read config file");
-            toml::from_str(&config_str).expect("Failed to parse config file")
-        } else {
-            FIRST_RUN.set(true).expect("failed to set FIRST_RUN");
+/// Returns the folder holding the datastore
+pub(crate) fn get_data_dir() -> PathBuf {
+    data_dir(&get_config())
+}
 
-            let config = UserConfig::default();
-            let config_str = toml::to_string(&config).expect("Failed to serialize config");
-            create_dir_all(config_path.parent().unwrap()).expect("Failed to create config dir");
-            write(config_path, config_str).expect("Failed to write config file");
-            config
+fn data_dir(config: &UserConfig) -> PathBuf {
+    match &config.defaults.db_path {
+        Some(db_path) => db_path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        None => aw_server::dirs::get_data_dir().unwrap_or_else(|_| {
+            error!("Failed to get the aw-server data dir");
+            dirs::app_data_dir()
+        }),
+    }
+}
+
+/// Returns the current config.
+///
+/// The config may be reloaded at any time, so hold on to the returned config only as long as
+/// the settings read from it have to agree.
+pub(crate) fn get_config() -> Arc<UserConfig> {
+    if let Some(config) = CONFIG.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return config.clone();
+    }
+    CONFIG
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(|| Arc::new(load_config()))
+        .clone()
+}
+
+/// Replaces the config after it changed on disk
+fn set_config(config: UserConfig) {
+    *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(config));
+}
+
+fn load_config() -> UserConfig {
+    let config = read_config();
+    if !is_loopback_host(&config.defaults.host) {
+        warn!(
+            "The server listens on {}, anyone who can reach this machine on port {} can read \
+            and change your ActivityWatch data",
+            config.defaults.host, config.defaults.port
+        );
+    }
+    config
+}
+
+fn read_config() -> UserConfig {
+    let config_path = get_config_path();
+    let (config, first_run) = load_or_default(&config_path);
+    FIRST_RUN.set(first_run).expect("failed to set FIRST_RUN");
+    if first_run {
+        let config_str = toml::to_string(&config).expect("Failed to serialize config");
+        // Run with the defaults rather than not at all, the config is written next time
+        if let Err(e) = dirs::create_dir_with_retry(config_path.parent().unwrap())
+            .and_then(|()| write(&config_path, config_str))
+        {
+            error!("Failed to write {}: {e}", config_path.display());
+            config_warning(format!(
+                "Could not save the settings to {}: {e}",
+                config_path.display()
+            ));
         }
+    }
+    config
+}
+
+/// Loads the config, falling back to the defaults if it can't be loaded. The problems found are
+/// kept for `show_config_warnings`, since there is no app handle to show them with yet.
+///
+/// Returns the config and whether this is the first run.
+fn load_or_default(config_path: &Path) -> (UserConfig, bool) {
+    match UserConfig::load(config_path) {
+        Ok((config, report)) => {
+            for warning in report.warnings {
+                error!("Problem in {}: {warning}", config_path.display());
+                config_warning(warning);
+            }
+            (config, report.first_run)
+        }
+        Err(e) => {
+            // Leave the file alone so the user can fix it
+            error!("Failed to load {}: {e}", config_path.display());
+            config_warning(format!(
+                "The settings in {} could not be loaded, using the defaults until \
+                    the file is fixed.\n\n{e}",
+                config_path.display()
+            ));
+            (UserConfig::default(), false)
+        }
+    }
+}
+
+/// Checks a configured server port, returning why it can't be used
+fn validate_port(port: u16) -> Result<(), String> {
+    if port == 0 {
+        return Err(
+            "port 0 makes the system pick a random port the modules aren't told about".to_string(),
+        );
+    }
+    if cfg!(unix) && port < 1024 {
+        return Err(format!(
+            "port {port} is a privileged port only root can listen on, use 1024-65535"
+        ));
+    }
+    Ok(())
+}
+
+fn config_warning(warning: String) {
+    CONFIG_WARNINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(warning);
+}
+
+fn take_config_warnings() -> Vec<String> {
+    std::mem::take(&mut *CONFIG_WARNINGS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Shows the problems found while loading the config, once the tray exists
+fn show_config_warnings(app: &AppHandle) {
+    let warnings = take_config_warnings();
+    if warnings.is_empty() {
+        return;
+    }
+    app.dialog()
+        .message(warnings.join("\n\n"))
+        .kind(MessageDialogKind::Warning)
+        .title("Aw-Tauri")
+        .show(|_| {});
+}
+
+/// Returns the config as currently saved, falling back to the one loaded at startup
+pub(crate) fn read_current_config() -> UserConfig {
+    read_to_string(get_config_path())
+        .ok()
+        .and_then(|config_str| toml::from_str(&config_str).ok())
+        .unwrap_or_else(|| get_config().clone())
+}
+
+/// Applies a change to the config file on disk.
+///
+/// The file is re-read first so that changes made earlier in the session aren't lost, since the
+/// config returned by `get_config` is only reloaded once the file watcher notices a change.
+pub(crate) fn update_config(change: impl FnOnce(&mut UserConfig)) -> Result<(), String> {
+    let mut config = read_current_config();
+    change(&mut config);
+    save_config(&config)
+}
+
+pub(crate) fn save_config(config: &UserConfig) -> Result<(), String> {
+    let config_path = get_config_path();
+    let config_str =
+        toml::to_string(config).map_err(|e| format!("Failed to serialize config: {e}"))?;
+    write(&config_path, config_str)
+        .map_err(|e| format!("Failed to write config file {}: {e}", config_path.display()))
+}
+
+#[derive(Debug, Serialize)]
+struct AppInfo {
+    version: &'static str,
+    commit: &'static str,
+    server_port: u16,
+    server_url: String,
+    config_dir: PathBuf,
+    log_dir: PathBuf,
+    data_dir: PathBuf,
+    first_run: bool,
+    os: &'static str,
+    arch: &'static str,
+    display_server: DisplayServerInfo,
+}
+
+/// Returns the basic facts about aw-tauri the frontend needs
+#[tauri::command]
+fn get_app_info(state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>) -> AppInfo {
+    let (server_port, server_url) = {
+        let state = state.lock().unwrap();
+        (state.server_port, state.server_url())
+    };
+    AppInfo {
+        version: build_info::BUILD_INFO.version,
+        commit: build_info::BUILD_INFO.commit,
+        server_port,
+        server_url,
+        config_dir: get_config_path().parent().unwrap().to_path_buf(),
+        log_dir: logging::get_log_dir(),
+        data_dir: get_data_dir(),
+        first_run: *is_first_run(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        display_server: get_display_server(),
+    }
+}
+
+/// Creates the tray items opening the config, log and data folders
+pub(crate) fn folder_menu_items(app: &AppHandle) -> [MenuItem<tauri::Wry>; 3] {
+    [
+        (MenuId::ConfigFolder, "Open config folder"),
+        (MenuId::LogFolder, "Open log folder"),
+        (MenuId::DataFolder, "Open data folder"),
+    ]
+    .map(|(id, text)| {
+        MenuItem::with_id(app, id, text, true, None::<&str>)
+            .expect("failed to create folder menu item")
     })
 }
 
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+pub(crate) fn open_folder(app: &AppHandle, path: &Path) {
+    if let Err(e) = app.opener().open_path(path.to_string_lossy(), None::<&str>) {
+        error!("Failed to open folder {}: {e}", path.display());
+    }
+}
+
+/// Handles a click on a tray menu item
+fn handle_menu_event(app: &AppHandle, manager_state: &Arc<Mutex<manager::ManagerState>>, id: &str) {
+    let Some(menu_id) = MenuId::parse(id) else {
+        warn!("Ignoring click on unknown menu item {id:?}");
+        return;
+    };
+    match menu_id {
+        MenuId::Open => {
+            debug!("Tray menu: open");
+            match app.webview_windows().get("main") {
+                Some(window) => {
+                    if let Err(e) = window.show() {
+                        error!("Failed to show the main window: {e}");
+                    }
+                }
+                None => error!("Main window not found"),
+            }
+        }
+        MenuId::Quit => {
+            info!("Quit from the tray menu");
+            // Waiting for the modules here would block the event loop until they stopped
+            let app = app.clone();
+            let manager_state = Arc::clone(manager_state);
+            shutdown::spawn("quit", move || {
+                manager::stop_modules_and_wait(&manager_state, modules_stop_timeout());
+                shutdown::request();
+                app.exit(0);
+            });
+        }
+        MenuId::ConfigFolder => open_folder(app, get_config_path().parent().unwrap()),
+        MenuId::LogFolder => open_folder(app, &logging::get_log_dir()),
+        MenuId::DataFolder => open_folder(app, &get_data_dir()),
+        MenuId::ApplyUpdate => updater::apply_update(app),
+        MenuId::OpenRelease => releases::open_release_page(app),
+        MenuId::Settings => {
+            let base_url = manager_state.lock().unwrap().server_url();
+            if let Err(e) = show_dashboard(app, &base_url, Some("/#/settings")) {
+                error!("Failed to open the settings: {e}");
+            }
+        }
+        MenuId::About => {
+            app.dialog()
+                .message(build_info::BUILD_INFO.describe())
+                .kind(MessageDialogKind::Info)
+                .title("About Aw-Tauri")
+                .show(|_| {});
+        }
+        MenuId::ExportToday => {
+            let app = app.clone();
+            let base_url = manager_state.lock().unwrap().server_url();
+            thread::spawn(move || {
+                if let Err(e) = export::export_events(
+                    &app,
+                    &base_url,
+                    export::ExportRange::Today,
+                    export::ExportFormat::Csv,
+                    export::BucketSelection::All,
+                ) {
+                    error!("Failed to export events: {e}");
+                }
+            });
+        }
+        MenuId::ExportSettings => {
+            let app = app.clone();
+            let installed = manager_state.lock().unwrap().installed_modules();
+            thread::spawn(move || {
+                if let Err(e) = settings_bundle::export_settings(&app, installed) {
+                    error!("Failed to export settings: {e}");
+                }
+            });
+        }
+        MenuId::ImportSettings => {
+            let app = app.clone();
+            let installed = manager_state.lock().unwrap().installed_modules();
+            thread::spawn(
+                move || match settings_bundle::import_settings(&app, &installed) {
+                    Ok(true) => {
+                        manager::stop_modules_and_wait(
+                            &app.state::<Arc<Mutex<manager::ManagerState>>>(),
+                            modules_stop_timeout(),
+                        );
+                        app.restart();
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("Failed to import settings: {e}");
+                        app.dialog()
+                            .message(e)
+                            .kind(MessageDialogKind::Error)
+                            .title("Aw-Tauri")
+                            .blocking_show();
+                    }
+                },
+            );
+        }
+        MenuId::TogglePause => {
+            let mut state = manager_state.lock().unwrap();
+            match state.pause_reason {
+                Some(manager::PauseReason::Schedule) => state.override_schedule(),
+                Some(_) => state.resume_tracking(),
+                None => {
+                    state.pause_tracking(manager::PauseReason::User);
+                }
+            }
+        }
+        MenuId::RestartAll => {
+            let manager_state = Arc::clone(manager_state);
+            thread::spawn(move || {
+                manager::restart_all_modules(&manager_state, modules_stop_timeout());
+            });
+        }
+        MenuId::Profile(profile) => manager_state.lock().unwrap().switch_profile(&profile),
+        MenuId::ModuleLog(name) => open_module_log(app.clone(), name),
+        MenuId::ForceStop(name) => {
+            if let Err(e) = manager_state.lock().unwrap().force_stop_module(&name) {
+                error!("{e}");
+            }
+        }
+        MenuId::Module(name) => manager_state.lock().unwrap().handle_system_click(&name),
+        // Status items are disabled
+        MenuId::Status(_) => {}
+    }
+}
+
+/// Opens the log of a module's stdout and stderr
 #[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+fn open_module_log(app: AppHandle, name: String) {
+    let log_file = logging::module_log_path(&name);
+    if let Err(e) = app
+        .opener()
+        .open_path(log_file.to_string_lossy(), None::<&str>)
+    {
+        error!("Failed to open {}: {e}", log_file.display());
+    }
+}
+
+/// Opens the folder holding the datastore
+#[tauri::command]
+fn open_data_folder(app: AppHandle) {
+    open_folder(&app, &get_data_dir());
+}
+
+/// Opens the folder holding config.toml
+#[tauri::command]
+fn open_config_folder(app: AppHandle) {
+    open_folder(&app, get_config_path().parent().unwrap());
+}
+
+/// Opens the folder holding the logs
+#[tauri::command]
+fn open_log_folder(app: AppHandle) {
+    open_folder(&app, &logging::get_log_dir());
+}
+
+/// Kills a module without waiting for it to shut down gracefully
+#[tauri::command]
+fn force_stop_module(
+    name: String,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<(), String> {
+    state.lock().unwrap().force_stop_module(&name)
+}
+
+/// Starts a module, which is then started again automatically
+#[tauri::command]
+fn start_module(
+    name: String,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<(), String> {
+    state.lock().unwrap().user_start_module(&name)
+}
+
+/// Stops a module, which isn't started automatically until started again
+#[tauri::command]
+fn stop_module(
+    name: String,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<(), String> {
+    state.lock().unwrap().user_stop_module(&name)
+}
+
+/// Restarts a module right away, without the delay used after crashes
+#[tauri::command]
+fn restart_module(name: String, state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>) {
+    state.lock().unwrap().restart_module(&name);
+}
+
+/// Resets a module's restart limit and starts it again, after fixing why it kept crashing.
+///
+/// Returns `false` if the module isn't installed.
+#[tauri::command]
+fn retry_module(name: String, state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>) -> bool {
+    state.lock().unwrap().retry_module(&name)
+}
+
+/// Returns the most recent stdout lines of a module
+#[tauri::command]
+fn get_module_output(
+    name: String,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Vec<String> {
+    state.lock().unwrap().get_module_output(&name)
+}
+
+/// Forwards a request to the server's REST API, e.g. `GET /api/0/buckets/`
+#[tauri::command]
+async fn query_server(
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+    method: String,
+    path: String,
+    body: Option<serde_json::Value>,
+) -> Result<server::QueryResponse, String> {
+    let base_url = state.lock().unwrap().server_url();
+    server::query(&base_url, &method, &path, body).await
+}
+
+/// Navigates the main window to a dashboard route, e.g. `/#/timeline`, and shows it
+#[tauri::command]
+fn open_dashboard(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+    path: Option<String>,
+) -> Result<(), String> {
+    let base_url = state.lock().unwrap().server_url();
+    show_dashboard(&app, &base_url, path.as_deref())
+}
+
+pub(crate) fn show_dashboard(
+    app: &AppHandle,
+    base_url: &str,
+    path: Option<&str>,
+) -> Result<(), String> {
+    let url = server::dashboard_url(base_url, path).ok_or("Invalid server URL")?;
+    let window = app
+        .webview_windows()
+        .get("main")
+        .cloned()
+        .ok_or("Main window not found")?;
+    window.navigate(url).map_err(|e| e.to_string())?;
+    window.show().map_err(|e| e.to_string())?;
+    window.set_focus().map_err(|e| e.to_string())
+}
+
+/// Runs the `doctor` checks, returning the pass/warn/fail report
+#[tauri::command]
+async fn run_doctor(app: AppHandle) -> Result<doctor::Report, String> {
+    tauri::async_runtime::spawn_blocking(move || doctor::run(Some(&app)))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the version and build metadata
+#[tauri::command]
+fn get_build_info() -> build_info::BuildInfo {
+    build_info::BUILD_INFO
+}
+
+/// Returns the notification action types for the frontend to register
+#[tauri::command]
+fn notification_action_types() -> serde_json::Value {
+    notifications::action_types()
+}
+
+/// Runs the action picked on a notification, or shows the dashboard when it was clicked
+#[tauri::command]
+fn handle_notification_action(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+    action: String,
+    module: Option<String>,
+) {
+    let action = notifications::NotificationAction::parse(&action, module.as_deref());
+    notifications::handle_action(&app, &state, action);
+}
+
+/// Returns the configured modules that weren't found, e.g. to offer fixing the discovery path
+#[tauri::command]
+fn get_missing_modules(state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>) -> Vec<String> {
+    state.lock().unwrap().missing_modules()
+}
+
+/// Returns whether each module runs, its pid, restart count and arguments
+#[tauri::command]
+fn get_modules_status(
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Vec<manager::ModuleStatus> {
+    state.lock().unwrap().modules_status()
+}
+
+/// Returns when a module crashed recently and whether it was restarted
+#[tauri::command]
+fn get_crash_history(
+    name: String,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Vec<manager::CrashRecord> {
+    state.lock().unwrap().get_crash_history(&name)
+}
+
+#[derive(Debug, Serialize)]
+struct PortStatus {
+    available: bool,
+    /// Whether the program using the port answers like an ActivityWatch server
+    activitywatch: bool,
+    /// Free port to use instead, when the port is in use
+    suggested_port: Option<u16>,
+}
+
+/// Checks whether a port is free, e.g. to validate it in the settings before saving
+#[tauri::command]
+fn check_port(port: u16) -> Result<PortStatus, String> {
+    let available = is_port_available(port).map_err(|e| e.to_string())?;
+    if available {
+        return Ok(PortStatus {
+            available,
+            activitywatch: false,
+            suggested_port: None,
+        });
+    }
+    Ok(PortStatus {
+        available,
+        activitywatch: ports::probe_activitywatch_server(port),
+        suggested_port: ports::find_free_port(port),
+    })
+}
+
+/// Moves the server to another port, or to a free one if `port` is `None`, returning the port
+/// now in use.
+///
+/// The port is saved to the config, the embedded or spawned server is restarted on it and the
+/// running modules are restarted with the new `--port`.
+#[tauri::command]
+async fn set_port(
+    port: Option<u16>,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<u16, String> {
+    let (server, current_port) = {
+        let state = state.lock().unwrap();
+        (state.server.clone(), state.server_port)
+    };
+    if server.remote_url().is_some() {
+        return Err("The port of a remote server can't be changed from here".to_string());
+    }
+    let port = match port {
+        Some(port) if port == current_port => return Ok(port),
+        Some(port) => port,
+        None => ports::find_free_port(current_port)
+            .ok_or_else(|| format!("No free port found above {current_port}"))?,
+    };
+    // The next start would reject a port the config can't hold
+    validate_port(port).map_err(|e| format!("Port {port} can't be used: {e}"))?;
+    if !is_port_available(port).map_err(|e| e.to_string())? {
+        return Err(format!("Port {port} is already in use"));
+    }
+    info!("Moving the server from port {current_port} to {port}");
+    // Saved once the server runs on it, a failed move keeps the working port
+    if server == server::Server::Embedded {
+        server::relaunch_embedded(port).await?;
+    }
+    update_config(|config| config.defaults.port = port)?;
+    let state = Arc::clone(state.inner());
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut state = state.lock().unwrap();
+        // The embedded server moves on by itself if the port got taken since the check
+        let port = match server {
+            server::Server::Embedded => server::embedded_port().unwrap_or(port),
+            _ => port,
+        };
+        state.set_server_port(port);
+        port
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Resets the config to the defaults after confirmation, returning the path of the backup,
+/// `None` if there was no config file to back up.
+///
+/// The defaults are applied right away for autostart and the modules, which also drops the
+/// profiles and forgets the modules the user stopped; everything else takes effect on the next
+/// start.
+#[tauri::command]
+async fn reset_config(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<Option<PathBuf>, String> {
+    let dialog_app = app.clone();
+    let confirmed = tauri::async_runtime::spawn_blocking(move || {
+        dialog_app
+            .dialog()
+            .message(
+                "Reset the configuration to the defaults?\n\n\
+                A backup of the current configuration is kept next to it.",
+            )
+            .kind(MessageDialogKind::Warning)
+            .title("Aw-Tauri")
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                "Reset".to_string(),
+                "Cancel".to_string(),
+            ))
+            .blocking_show()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    if !confirmed {
+        return Err("Reset cancelled".to_string());
+    }
+
+    let config_path = get_config_path();
+    let backup_path = if config_path.exists() {
+        let backup_path = config_path.with_file_name(format!(
+            "config.toml.{}.bak",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        std::fs::copy(&config_path, &backup_path)
+            .map_err(|e| format!("Failed to back up the config: {e}"))?;
+        info!("Resetting config, backup at {}", backup_path.display());
+        Some(backup_path)
+    } else {
+        info!("Resetting config, there is no config file to back up");
+        None
+    };
+
+    let config = UserConfig::default();
+    save_config(&config)?;
+
+    let autostart_manager = app.autolaunch();
+    let autostart = if config.defaults.autostart {
+        autostart_manager.enable()
+    } else {
+        autostart_manager.disable()
+    };
+    if let Err(e) = autostart {
+        error!("Failed to apply the autostart setting: {e}");
+    }
+
+    state.lock().unwrap().reset_modules(&config);
+    Ok(backup_path)
+}
+
+/// Exports the events of a range to a CSV or JSON file picked by the user.
+///
+/// Returns the number of exported events.
+#[tauri::command]
+async fn export_events(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+    range: export::ExportRange,
+    format: export::ExportFormat,
+    buckets: Option<export::BucketSelection>,
+) -> Result<usize, String> {
+    let base_url = state.lock().unwrap().server_url();
+    tauri::async_runtime::spawn_blocking(move || {
+        export::export_events(&app, &base_url, range, format, buckets.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Writes a gzipped diagnostics file picked by the user, optionally with the log and the
+/// recent module output
+#[tauri::command]
+async fn export_diagnostics(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+    options: Option<diagnostics::DiagnosticsOptions>,
+) -> Result<PathBuf, String> {
+    let manager_state = state.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        diagnostics::export_diagnostics(&app, &manager_state, options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Writes the config and the installed modules to a settings bundle picked by the user
+#[tauri::command]
+async fn export_settings(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<PathBuf, String> {
+    let installed = state.lock().unwrap().installed_modules();
+    tauri::async_runtime::spawn_blocking(move || settings_bundle::export_settings(&app, installed))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Imports a settings bundle picked by the user, returning `false` if cancelled
+#[tauri::command]
+async fn import_settings(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<bool, String> {
+    let installed = state.lock().unwrap().installed_modules();
+    tauri::async_runtime::spawn_blocking(move || settings_bundle::import_settings(&app, &installed))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Lists the rotated logs with their sizes
+#[tauri::command]
+fn list_rotated_logs() -> Vec<logging::RotatedLog> {
+    logging::list_rotated_logs()
+}
+
+/// Deletes the rotated logs to reclaim space, returning the number of bytes freed
+#[tauri::command]
+fn delete_rotated_logs() -> u64 {
+    let freed = logging::delete_rotated_logs();
+    info!("Deleted rotated logs, freed {freed} bytes");
+    freed
+}
+
+/// Returns how long each phase of the startup took
+#[tauri::command]
+fn get_startup_timings() -> Vec<timings::StartupPhase> {
+    timings::get_startup_timings()
+}
+
+#[derive(Debug, Serialize)]
+struct DisplayServerInfo {
+    mode: DisplayServer,
+    wayland: bool,
+    watchers: Vec<String>,
+}
+
+/// Returns the configured display server and the watchers it maps to
+#[tauri::command]
+fn get_display_server() -> DisplayServerInfo {
+    let mode = read_current_config().defaults.display_server;
+    DisplayServerInfo {
+        mode,
+        wayland: is_wayland(mode),
+        watchers: display_server_watchers(mode)
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+/// Switches between the X11 and Wayland watchers and persists the choice.
+///
+/// The watchers of the other display server are stopped and replaced by the matching ones, in the
+/// autostart modules and in the profiles using watchers. Those of the active profile are started.
+#[tauri::command]
+fn set_display_server(
+    mode: DisplayServer,
+    state: tauri::State<'_, Arc<Mutex<manager::ManagerState>>>,
+) -> Result<(), String> {
+    if !cfg!(target_os = "linux") {
+        return Err("Selecting the display server is only supported on Linux".to_string());
+    }
+    let watchers = display_server_watchers(mode);
+    info!("Switching display server to {mode:?}, using {watchers:?}");
+
+    let mut state = state.lock().unwrap();
+    let active_profile = state.active_profile.clone();
+    let mut to_start = Vec::new();
+    update_config(|config| {
+        config.defaults.display_server = mode;
+        swap_watchers(&mut config.autostart_modules, mode, true);
+        for profile in config.profiles.values_mut() {
+            swap_watchers(&mut profile.modules, mode, false);
+        }
+        to_start = config
+            .profile_modules(active_profile.as_deref())
+            .iter()
+            .filter(|module| watchers.contains(&module.name.as_str()))
+            .cloned()
+            .collect();
+    })?;
+
+    // Running instances are keyed by their id, look at the module each runs
+    let stale: Vec<String> = state
+        .modules_running
+        .iter()
+        .filter(|(_, running)| **running)
+        .map(|(key, _)| key)
+        .filter(|key| {
+            let binary = state.binary(key);
+            is_display_server_watcher(binary) && !watchers.contains(&binary)
+        })
+        .cloned()
+        .collect();
+    for key in stale {
+        state.stop_module(&key);
+    }
+    for module in &to_start {
+        state.start_module_config(module);
+    }
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    if std::env::args().any(|arg| arg == "--version") {
+        println!("{}", build_info::BUILD_INFO.describe());
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("doctor") {
+        let json = std::env::args().any(|arg| arg == "--json");
+        std::process::exit(doctor::run_cli(json));
+    }
+    // Before logging, so a crash report for the previous session gets its log tail
+    let previous_crash = crash::install();
+
     // Initialize logging
     if let Err(e) = logging::setup_logging() {
         eprintln!("Failed to initialize logging: {}", e);
     }
+    if is_headless() {
+        headless::run();
+        return;
+    }
 
-    tauri::Builder::default()
+    let mut builder = tauri::Builder::default()
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_dialog::init())
-        .plugin(tauri_plugin_autostart::init(
-            MacosLauncher::LaunchAgent,
-            Some(vec![]),
-        ))
+        .plugin(tauri_plugin_opener::init())
+        // The entry is rewritten on every start when autostart is enabled, so changed arguments
+        // are picked up on the next launch
+        .plugin(
+            tauri_plugin_autostart::Builder::new()
+                .macos_launcher(MacosLauncher::LaunchAgent)
+                .args(get_config().defaults.autostart_args.clone())
+                .build(),
+        )
         .plugin(tauri_plugin_single_instance::init(|_app, _args, _cwd| {
             let lock_path = get_config_path()
                 .parent()
                 .unwrap()
                 .join("single_instance.lock");
-            if !lock_path.parent().unwrap().exists() {
-                create_dir_all(lock_path.parent().unwrap()).expect("Failed to create lock dir");
+            if let Err(e) = dirs::create_dir_with_retry(lock_path.parent().unwrap()) {
+                error!("Failed to create the lock folder: {e}");
+                return;
             }
             let _lock_file = OpenOptions::new()
                 .create(true)
@@ -326,45 +1677,50 @@ pub fn run() {
                 .open(lock_path)
                 .expect("Failed to open lock file");
             info!("Another instance is running, quitting!");
-        }))
-        .setup(|app| {
+        }));
+    if let Some(pubkey) = updater::UPDATER_PUBKEY {
+        builder = builder.plugin(tauri_plugin_updater::Builder::new().pubkey(pubkey).build());
+    }
+
+    builder
+        .setup(move |app| {
+            let mut timer = timings::PhaseTimer::start();
             {
                 init_app_handle(app.handle().clone());
+                if let Some(report) = previous_crash {
+                    crash::offer_report_folder(app.handle().clone(), report);
+                }
+                timer.phase("app handle");
                 let user_config = get_config();
+                timer.phase("config");
                 // Get the autostart manager
                 let autostart_manager = app.autolaunch();
 
                 match user_config.defaults.autostart {
                     true => {
-                        autostart_manager
-                            .enable()
-                            .expect("Unable to enable autostart");
+                        if let Err(e) = autostart_manager.enable() {
+                            error!("Unable to enable autostart: {e}");
+                        }
                     }
                     false => {
-                        autostart_manager
-                            .disable()
-                            .expect("Unable to disable autosart");
+                        if let Err(e) = autostart_manager.disable() {
+                            error!("Unable to disable autostart: {e}");
+                        }
                     }
                 }
 
                 // Check enable state
-                info!(
-                    "Registered for autostart: {}",
-                    autostart_manager
-                        .is_enabled()
-                        .expect("failed to get autostart state")
-                );
+                match autostart_manager.is_enabled() {
+                    Ok(enabled) => info!("Registered for autostart: {enabled}"),
+                    Err(e) => error!("Failed to get autostart state: {e}"),
+                }
+                timer.phase("autostart");
 
                 let testing = true;
-                let legacy_import = false;
 
                 let mut aw_config = aw_server::config::create_config(testing);
-                aw_config.port = user_config.defaults.port;
-                let db_path = aw_server::dirs::db_path(testing)
-                    .expect("Failed to get db path")
-                    .to_str()
-                    .unwrap()
-                    .to_string();
+                aw_config.address = user_config.defaults.host.clone();
+                let own_db_path = aw_server::dirs::db_path(testing).expect("Failed to get db path");
                 let device_id = aw_server::device_id::get_device_id();
 
                 let webui_var = std::env::var("AW_WEBUI_DIR");
@@ -375,73 +1731,239 @@ pub fn run() {
                         info!("Using webui path: {}", path_str);
                         Some(asset_path)
                     } else {
-                        panic!("Path set via env var AW_WEBUI_DIR does not exist");
+                        error!("AW_WEBUI_DIR {path_str} does not exist, using bundled assets");
+                        app.dialog()
+                            .message(format!(
+                                "The web UI folder {path_str} set via AW_WEBUI_DIR does not \
+                                exist, using the bundled web UI instead."
+                            ))
+                            .kind(MessageDialogKind::Warning)
+                            .title("Aw-Tauri")
+                            .show(|_| {});
+                        None
                     }
                 } else {
                     println!("Using bundled assets");
                     None
                 };
 
-                let server_state = aw_server::endpoints::ServerState {
-                    // Even if legacy_import is set to true it is disabled on Android so
-                    // it will not happen there
-                    datastore: Mutex::new(aw_datastore::Datastore::new(db_path, legacy_import)),
-                    asset_resolver: aw_server::endpoints::AssetResolver::new(asset_path_opt),
-                    device_id,
+                let db_path = user_config
+                    .defaults
+                    .db_path
+                    .clone()
+                    .unwrap_or_else(|| own_db_path.clone());
+                let server = match user_config.server.server(db_path.clone()) {
+                    Ok(server) => server,
+                    Err(e) => {
+                        error!("Falling back to the embedded server: {e}");
+                        app.dialog()
+                            .message(format!(
+                                "Invalid server config, using the embedded server instead.\n\n{e}"
+                            ))
+                            .kind(MessageDialogKind::Warning)
+                            .title("Aw-Tauri")
+                            .show(|_| {});
+                        server::Server::Embedded
+                    }
                 };
-                if !is_port_available(user_config.defaults.port)
-                    .expect("Failed to check port availability")
-                {
+                #[cfg(no_webui)]
+                if asset_path_opt.is_none() && !server.is_separate() {
+                    error!("This build has no web UI bundled");
                     app.dialog()
-                        .message(format!(
-                            "Port {} is already in use",
-                            user_config.defaults.port
-                        ))
-                        .kind(MessageDialogKind::Error)
+                        .message(
+                            "The web UI is not bundled with this build, so the dashboard is \
+                            empty. Set AW_WEBUI_DIR to the folder of a web UI build or install \
+                            the aw-webui package.",
+                        )
+                        .kind(MessageDialogKind::Warning)
                         .title("Aw-Tauri")
                         .show(|_| {});
-                    panic!("Port {} is already in use", user_config.defaults.port);
                 }
-                tauri::async_runtime::spawn(build_rocket(server_state, aw_config).launch());
 
-                let manager_state = manager::start_manager();
+                let port = match server.remote_url() {
+                    Some(url) => url
+                        .port_or_known_default()
+                        .unwrap_or(user_config.defaults.port),
+                    None => ports::preferred_port(user_config.defaults.port),
+                };
+                // A port that can't be checked is treated as taken, so the user gets to choose
+                let port_in_use = server.remote_url().is_none()
+                    && !is_address_available(&aw_config.address, port).unwrap_or_else(|e| {
+                        error!("Failed to check port availability: {e}");
+                        false
+                    });
+
+                let launch_server = move |port: u16, db_path: PathBuf, legacy_import: bool| {
+                    server::launch_embedded(
+                        aw_config,
+                        port,
+                        db_path,
+                        asset_path_opt,
+                        device_id,
+                        legacy_import,
+                    );
+                };
+
+                // On first run, offer to reuse the data of a previous installation before the
+                // datastore gets created
+                let db_candidates = if *is_first_run() && server == server::Server::Embedded {
+                    aw_server::dirs::get_data_dir()
+                        .ok()
+                        .and_then(|dir| dir.parent().map(Path::to_path_buf))
+                        .map(|dir| db_import::find_database_candidates(&dir, &own_db_path))
+                        .unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+
+                timer.phase("server config");
+
+                // Modules are held back until a port conflict is resolved
+                let separate_server = server.is_separate();
+                let manager_state = manager::start_manager(
+                    !port_in_use,
+                    port,
+                    server,
+                    Arc::new(frontend::AppFrontend),
+                );
+                timer.phase("module discovery");
+
+                if separate_server {
+                    info!(
+                        "Using the server at {}",
+                        manager_state.lock().unwrap().server_url()
+                    );
+                    server::start_health_checks(app.handle().clone(), manager_state.clone());
+                }
+                if !port_in_use && db_candidates.is_empty() {
+                    if !separate_server {
+                        launch_server(port, db_path, false);
+                    }
+                } else {
+                    let manager_state = manager_state.clone();
+                    let app_handle = app.handle().clone();
+                    thread::spawn(move || {
+                        let port = if port_in_use {
+                            match ports::resolve_port_conflict(port, *is_first_run()) {
+                                ports::PortResolution::UseExisting => {
+                                    info!("Using the existing server on port {port}");
+                                    let mut state = manager_state.lock().unwrap();
+                                    if separate_server {
+                                        // The existing server isn't ours to supervise
+                                        state.server = server::Server::Remote(
+                                            Url::parse(&state.server_url())
+                                                .expect("invalid local server url"),
+                                        );
+                                    }
+                                    state.start_autostart_modules();
+                                    return;
+                                }
+                                ports::PortResolution::Port(new_port) => {
+                                    if new_port != port {
+                                        info!("Using free port {new_port} instead of {port}");
+                                    }
+                                    // Later runs prefer the remembered port, see `preferred_port`
+                                    if *is_first_run() {
+                                        if let Err(e) =
+                                            update_config(|config| config.defaults.port = new_port)
+                                        {
+                                            error!("Failed to remember port {new_port}: {e}");
+                                        }
+                                    }
+                                    let mut state = manager_state.lock().unwrap();
+                                    state.server_port = new_port;
+                                    state.start_autostart_modules();
+                                    new_port
+                                }
+                                ports::PortResolution::Quit => {
+                                    app_handle.exit(0);
+                                    return;
+                                }
+                            }
+                        } else {
+                            port
+                        };
+                        if separate_server {
+                            return;
+                        }
+
+                        let choice = db_import::choose_database(&db_candidates);
+                        db_import::remember_choice(&choice);
+                        match choice {
+                            db_import::DatabaseChoice::UseExisting(path) => {
+                                launch_server(port, path, false)
+                            }
+                            db_import::DatabaseChoice::LegacyImport => {
+                                launch_server(port, own_db_path, true)
+                            }
+                            db_import::DatabaseChoice::Fresh => {
+                                launch_server(port, own_db_path, false)
+                            }
+                        }
+                    });
+                }
+
+                timer.phase("server launch");
 
-                let open = MenuItem::with_id(app, "open", "Open", true, None::<&str>)
+                app.manage(manager_state.clone());
+                power::start_battery_monitor(manager_state.clone());
+                schedule::start_scheduler(manager_state.clone());
+                config_reload::start_watching(manager_state.clone());
+
+                let open = MenuItem::with_id(app, MenuId::Open, "Open", true, None::<&str>)
                     .expect("failed to create open menu item");
-                let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)
+                let quit = MenuItem::with_id(app, MenuId::Quit, "Quit", true, None::<&str>)
                     .expect("failed to create quit menu item");
 
-                let menu =
-                    Menu::with_items(app, &[&open, &quit]).expect("failed to create tray menu");
+                let [config_folder, log_folder, data_folder] = folder_menu_items(app.handle());
 
-                let tray = TrayIconBuilder::new()
-                    .icon(
-                        app.default_window_icon()
-                            .expect("failed to get window icon")
-                            .clone(),
-                    )
-                    .menu(&menu)
-                    .show_menu_on_left_click(true)
-                    .build(app)
-                    .expect("failed to create tray");
+                let menu = Menu::with_items(
+                    app,
+                    &[&open, &config_folder, &log_folder, &data_folder, &quit],
+                )
+                .expect("failed to create tray menu");
 
-                init_tray_id(tray.id().clone());
-                app.on_menu_event(move |app, event| {
-                    if event.id() == open.id() {
-                        println!("system tray received a open click");
-                        let windows = app.webview_windows();
-                        let window = windows.get("main").expect("main window not found");
-                        window.show().unwrap();
-                    } else if event.id() == quit.id() {
-                        println!("quit clicked!");
-                        let state = manager_state.lock().unwrap();
-                        state.stop_modules();
-                        app.exit(0);
-                    } else {
-                        // Modules menu clicks
-                        let mut state = manager_state.lock().unwrap();
-                        state.handle_system_click(&event.id().0);
+                let tray = configure_tray(app.handle(), TrayIconBuilder::new())
+                    .menu(&menu)
+                    .build(app);
+                match tray {
+                    Ok(tray) => init_tray_id(tray.id().clone()),
+                    Err(e) => {
+                        // Without a tray the window is the only way to reach the app
+                        error!("Failed to create the tray: {e}");
+                        tray_ready();
+                        send_notification(
+                            "The tray icon is not available on this desktop. After closing the \
+                            window, start Aw-Tauri again to bring it back.",
+                        );
+                        if let Some(window) = app.webview_windows().get("main") {
+                            if let Err(e) = window.show() {
+                                error!("Failed to show the main window: {e}");
+                            }
+                        }
                     }
+                }
+                show_config_warnings(app.handle());
+                #[cfg(windows)]
+                if TRAY_ID.get().is_some() {
+                    let app_handle = app.handle().clone();
+                    let manager_state = manager_state.clone();
+                    taskbar::on_taskbar_created(move || {
+                        let app = app_handle.clone();
+                        let manager_state = manager_state.clone();
+                        // Tray icons have to be created on the main thread
+                        if let Err(e) = app_handle
+                            .run_on_main_thread(move || recreate_tray(&app, &manager_state))
+                        {
+                            error!("Failed to recreate the tray: {e}");
+                        }
+                    });
+                }
+                timer.phase("tray");
+                app.on_menu_event(move |app, event| {
+                    crash::guard("tray menu event", || {
+                        handle_menu_event(app, &manager_state, &event.id().0)
+                    })
                 });
                 if user_config.defaults.autostart && user_config.defaults.autostart_minimized {
                     if let Some(window) = app.webview_windows().get("main") {
@@ -451,17 +1973,391 @@ pub fn run() {
             }
 
             handle_first_run();
+            if is_safe_mode() {
+                send_notification("Running in safe mode, no modules were started");
+            }
             listen_for_lockfile();
+            thread::spawn(legacy::check_legacy_installation);
+            updater::start_update_checks(app.handle().clone());
+            releases::start_release_checks(app.handle().clone());
+            timer.phase("background tasks");
+            timer.finish();
             Ok(())
         })
         .on_window_event(|window, event| {
-            if let tauri::WindowEvent::CloseRequested { api, .. } = &event {
-                api.prevent_close();
-                window.hide().unwrap();
-            };
+            crash::guard("window event", || {
+                if let tauri::WindowEvent::CloseRequested { api, .. } = &event {
+                    api.prevent_close();
+                    window.hide().unwrap();
+                };
+            })
         })
         .plugin(tauri_plugin_shell::init())
-        .invoke_handler(tauri::generate_handler![greet])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .invoke_handler(tauri::generate_handler![
+            get_app_info,
+            force_stop_module,
+            start_module,
+            stop_module,
+            restart_module,
+            retry_module,
+            get_module_output,
+            open_module_log,
+            get_crash_history,
+            get_modules_status,
+            get_missing_modules,
+            handle_notification_action,
+            notification_action_types,
+            get_build_info,
+            run_doctor,
+            open_dashboard,
+            query_server,
+            get_display_server,
+            set_display_server,
+            get_startup_timings,
+            check_port,
+            set_port,
+            reset_config,
+            export_events,
+            export_diagnostics,
+            export_settings,
+            import_settings,
+            list_rotated_logs,
+            delete_rotated_logs,
+            open_config_folder,
+            open_log_folder,
+            open_data_folder
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                shutdown::request();
+                shutdown::join(SHUTDOWN_TIMEOUT);
+                crash::clear_session_marker();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/configs")
+            .join(name)
+    }
+
+    #[test]
+    fn load_valid_config() {
+        let (config, report) = UserConfig::load(&fixture("valid.toml")).unwrap();
+        assert_eq!(report, LoadReport::default());
+        assert!(!config.defaults.autostart);
+        assert_eq!(config.defaults.port, 5700);
+        assert_eq!(config.defaults.host, "0.0.0.0");
+        assert_eq!(
+            config.defaults.discovery_path,
+            PathBuf::from("/opt/aw-modules")
+        );
+        // Settings missing from the table keep their defaults
+        assert_eq!(
+            config.defaults.stop_timeout_secs,
+            default_stop_timeout_secs()
+        );
+        let modules: Vec<_> = config
+            .autostart_modules
+            .iter()
+            .map(|module| (module.name.as_str(), module.args.as_str()))
+            .collect();
+        assert_eq!(
+            modules,
+            [
+                ("aw-watcher-afk", ""),
+                ("aw-watcher-window", "--poll-time 2")
+            ]
+        );
+        assert_eq!(config.restart.limit, 5);
+    }
+
+    #[test]
+    fn load_partial_config_uses_the_defaults() {
+        let (config, report) = UserConfig::load(&fixture("partial.toml")).unwrap();
+        assert_eq!(report, LoadReport::default());
+        assert_eq!(config.restart.limit, 1);
+        assert_eq!(config.defaults.port, Defaults::default().port);
+        assert!(config.aw_notify.output_only);
+        let defaults = UserConfig::default();
+        let names = |modules: &[ModuleConfig]| -> Vec<String> {
+            modules.iter().map(|module| module.name.clone()).collect()
+        };
+        assert_eq!(
+            names(&config.autostart_modules),
+            names(&defaults.autostart_modules)
+        );
+    }
+
+    #[test]
+    fn load_malformed_config_fails() {
+        assert!(UserConfig::load(&fixture("malformed.toml")).is_err());
+    }
+
+    #[test]
+    fn load_missing_config_is_a_first_run() {
+        let (config, report) = UserConfig::load(&fixture("missing.toml")).unwrap();
+        assert!(report.first_run);
+        assert!(report.warnings.is_empty());
+        assert_eq!(config.defaults.port, Defaults::default().port);
+    }
+
+    #[test]
+    fn aw_notify_output_only_can_be_turned_off() {
+        let (config, _) = UserConfig::parse("[aw_notify]\noutput_only = false", "test").unwrap();
+        assert!(!config.aw_notify.output_only);
+    }
+
+    #[test]
+    fn validate_port_boundaries() {
+        assert!(validate_port(0).is_err());
+        assert_eq!(validate_port(1023).is_err(), cfg!(unix));
+        assert!(validate_port(1024).is_ok());
+        assert!(validate_port(5600).is_ok());
+        assert!(validate_port(65535).is_ok());
+    }
+
+    #[test]
+    fn malformed_config_loads_without_an_app_handle() {
+        assert!(HANDLE.get().is_none());
+        let path = fixture("malformed.toml");
+        let (config, first_run) = load_or_default(&path);
+        assert!(!first_run);
+        assert_eq!(config.defaults.port, Defaults::default().port);
+        // Kept to be shown once the tray exists
+        let warnings = take_config_warnings();
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.contains(&path.display().to_string())));
+    }
+
+    #[test]
+    fn detect_wayland_from_the_session() {
+        let cases = [
+            (Some("wayland"), None, true),
+            (Some("wayland"), Some("wayland-0"), true),
+            (Some(" wayland\n"), None, true),
+            // XWayland sets WAYLAND_DISPLAY in X11 sessions too
+            (Some("x11"), Some("wayland-0"), false),
+            (Some("x11"), None, false),
+            (Some("tty"), Some("wayland-0"), true),
+            (Some("tty"), None, false),
+            (Some(""), Some("wayland-0"), true),
+            (None, Some("wayland-0"), true),
+            (None, Some(""), false),
+            (None, None, false),
+        ];
+        for (session_type, wayland_display, expected) in cases {
+            assert_eq!(
+                detect_wayland(session_type, wayland_display),
+                expected,
+                "XDG_SESSION_TYPE={session_type:?} WAYLAND_DISPLAY={wayland_display:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn explicit_display_server_wins_over_detection() {
+        assert!(is_wayland(DisplayServer::Wayland));
+        assert!(!is_wayland(DisplayServer::X11));
+    }
+
+    #[test]
+    fn waiting_for_the_tray_times_out() {
+        let flag = (Mutex::new(false), Condvar::new());
+        let started = std::time::Instant::now();
+        assert!(!wait_for_flag(&flag, Duration::from_millis(100)));
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_millis(100));
+        assert!(waited < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn waiting_for_the_tray_ends_when_it_is_ready() {
+        let flag = Arc::new((Mutex::new(false), Condvar::new()));
+        let setter = Arc::clone(&flag);
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            set_flag(&setter);
+        });
+        let started = std::time::Instant::now();
+        assert!(wait_for_flag(&flag, Duration::from_secs(30)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // Set flags don't block
+        assert!(wait_for_flag(&flag, Duration::ZERO));
+    }
+
+    #[test]
+    fn app_info_keeps_the_field_names_the_frontend_reads() {
+        let info = AppInfo {
+            version: "0.1.0",
+            commit: "abc1234",
+            server_port: 5600,
+            server_url: "http://127.0.0.1:5600".to_string(),
+            config_dir: PathBuf::from("/config"),
+            log_dir: PathBuf::from("/log"),
+            data_dir: PathBuf::from("/data"),
+            first_run: true,
+            os: "linux",
+            arch: "x86_64",
+            display_server: DisplayServerInfo {
+                mode: DisplayServer::Auto,
+                wayland: false,
+                watchers: vec!["aw-watcher-window".to_string()],
+            },
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        let mut fields: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            [
+                "arch",
+                "commit",
+                "config_dir",
+                "data_dir",
+                "display_server",
+                "first_run",
+                "log_dir",
+                "os",
+                "server_port",
+                "server_url",
+                "version",
+            ]
+        );
+        assert_eq!(json["server_port"], 5600);
+        assert_eq!(json["server_url"], "http://127.0.0.1:5600");
+        assert_eq!(json["config_dir"], "/config");
+        assert_eq!(json["first_run"], true);
+        assert_eq!(json["display_server"]["mode"], "auto");
+        assert_eq!(json["display_server"]["wayland"], false);
+        assert_eq!(
+            json["display_server"]["watchers"],
+            serde_json::json!(["aw-watcher-window"])
+        );
+    }
+
+    #[test]
+    fn profile_modules_fall_back_to_the_autostart_modules() {
+        let config_str = "autostart_modules = [{ name = \"aw-watcher-afk\" }]\n\
+            [profiles.work]\nmodules = [{ name = \"aw-watcher-window\" }, \
+            { name = \"aw-watcher-input\" }]\n\
+            [profiles.empty]\n";
+        let (config, _) = UserConfig::parse(config_str, "config.toml").unwrap();
+        let names = |profile| {
+            config
+                .profile_modules(profile)
+                .iter()
+                .map(|module| module.name.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(names(None), ["aw-watcher-afk"]);
+        assert_eq!(
+            names(Some("work")),
+            ["aw-watcher-window", "aw-watcher-input"]
+        );
+        assert!(names(Some("empty")).is_empty());
+        assert_eq!(names(Some("unknown")), ["aw-watcher-afk"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn display_server_switch_replaces_every_watcher_instance() {
+        let config_str = "autostart_modules = [{ name = \"aw-watcher-afk\" }, \
+            { name = \"aw-watcher-window\", id = \"window-slow\", args = \"--poll-time 5\" }, \
+            { name = \"aw-watcher-input\" }]\n\
+            [profiles.work]\nmodules = [{ name = \"aw-watcher-window\" }]\n\
+            [profiles.input]\nmodules = [{ name = \"aw-watcher-input\" }]\n";
+        let (mut config, _) = UserConfig::parse(config_str, "config.toml").unwrap();
+        let keys = |modules: &[ModuleConfig]| {
+            modules
+                .iter()
+                .map(|module| module.key().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        swap_watchers(&mut config.autostart_modules, DisplayServer::Wayland, true);
+        for profile in config.profiles.values_mut() {
+            swap_watchers(&mut profile.modules, DisplayServer::Wayland, false);
+        }
+        assert_eq!(
+            keys(&config.autostart_modules),
+            ["aw-watcher-input", "aw-awatcher"]
+        );
+        assert_eq!(keys(&config.profiles["work"].modules), ["aw-awatcher"]);
+        assert_eq!(
+            keys(&config.profiles["input"].modules),
+            ["aw-watcher-input"]
+        );
+
+        swap_watchers(&mut config.autostart_modules, DisplayServer::X11, true);
+        assert_eq!(
+            keys(&config.autostart_modules),
+            ["aw-watcher-input", "aw-watcher-afk", "aw-watcher-window"]
+        );
+        let mut empty = Vec::new();
+        swap_watchers(&mut empty, DisplayServer::X11, true);
+        assert_eq!(keys(&empty), ["aw-watcher-afk", "aw-watcher-window"]);
+    }
+
+    #[test]
+    fn invalid_port_falls_back_to_the_default() {
+        let config_str = "[defaults]\nautostart = true\nautostart_minimized = true\nport = 0\n\
+            discovery_path = \"/opt/aw-modules\"\n";
+        let (config, report) = UserConfig::parse(config_str, "config.toml").unwrap();
+        assert_eq!(config.defaults.port, Defaults::default().port);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("config.toml"));
+    }
+
+    /// Returns a new empty folder, resolved so it matches the paths in the watch events
+    fn watched_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aw-tauri-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn watcher_wakes_up_for_the_file() {
+        let dir = watched_dir("watch");
+        let watcher = SpecificFileWatcher::new(&dir, "single_instance.lock").unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(watcher.wait_for_file());
+        });
+        // Other files don't wake it up
+        write(dir.join("other.lock"), "").unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+        write(dir.join("single_instance.lock"), "").unwrap();
+        let changed = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(changed, Some(dir.join("single_instance.lock")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn watcher_stops_on_shutdown() {
+        let dir = watched_dir("watch-shutdown");
+        let watcher = SpecificFileWatcher::any_file(&dir).unwrap();
+        let shutdown = watcher.shutdown_handle();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(watcher.wait_for_file());
+        });
+        shutdown.shutdown();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -1,13 +1,151 @@
-use directories::ProjectDirs;
 use fern::colors::{Color, ColoredLevelConfig};
 use log::LevelFilter;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub(crate) const LOG_FILE: &str = "aw-tauri.log";
+/// Size at which a module log is rotated, keeping the previous one as `<name>.log.1`
+const MODULE_LOG_MAX_BYTES: u64 = 5 * 1024 * 1024;
+
+pub fn get_log_dir() -> PathBuf {
+    crate::dirs::app_data_dir().join("logs")
+}
+
+/// Returns the log holding a module's stdout and stderr
+pub fn module_log_path(name: &str) -> PathBuf {
+    // Instance ids come from the config, keep them from escaping the folder
+    let file_name: String = name
+        .chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+    get_log_dir()
+        .join("modules")
+        .join(format!("{file_name}.log"))
+}
+
+/// A module's log, appended to as the module writes output
+pub struct ModuleLog {
+    path: PathBuf,
+    /// `None` if the log couldn't be opened, the output is then only buffered in memory
+    file: Option<File>,
+    size: u64,
+}
+
+impl ModuleLog {
+    pub fn open(name: &str) -> ModuleLog {
+        let path = module_log_path(name);
+        let mut log = ModuleLog {
+            path,
+            file: None,
+            size: 0,
+        };
+        log.reopen();
+        log
+    }
+
+    fn reopen(&mut self) {
+        let file = crate::dirs::create_dir_with_retry(self.path.parent().expect("no parent"))
+            .and_then(|()| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+            });
+        match file {
+            Ok(file) => {
+                self.size = file.metadata().map(|m| m.len()).unwrap_or(0);
+                self.file = Some(file);
+            }
+            Err(e) => {
+                log::error!("Failed to open {}: {e}", self.path.display());
+                self.file = None;
+            }
+        }
+    }
+
+    /// Appends a line of the module's `stream`, rotating the log once it is too large
+    pub fn write_line(&mut self, stream: &str, line: &str) {
+        if self.size >= MODULE_LOG_MAX_BYTES {
+            self.rotate();
+        }
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let entry = format!(
+            "[{}][{stream}] {line}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+        );
+        match file.write_all(entry.as_bytes()) {
+            Ok(()) => self.size += entry.len() as u64,
+            Err(e) => {
+                log::error!("Failed to write {}: {e}", self.path.display());
+                self.file = None;
+            }
+        }
+    }
+
+    fn rotate(&mut self) {
+        self.file = None;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        if let Err(e) = std::fs::rename(&self.path, &rotated) {
+            log::warn!("Failed to rotate {}: {e}", self.path.display());
+        }
+        self.reopen();
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RotatedLog {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Returns `true` for old aw-tauri logs, e.g. `aw-tauri.log.1`, but never the active log
+fn is_rotated_log(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    name != LOG_FILE && name.starts_with("aw-tauri") && name.contains(".log")
+}
+
+/// Lists the rotated logs in the log dir
+pub fn list_rotated_logs() -> Vec<RotatedLog> {
+    let Ok(entries) = std::fs::read_dir(get_log_dir()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .map(|entry| entry.path())
+        .filter(|path| is_rotated_log(path))
+        .map(|path| RotatedLog {
+            size: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            path,
+        })
+        .collect()
+}
+
+/// Deletes the rotated logs, keeping the active one. Returns the number of bytes freed.
+pub fn delete_rotated_logs() -> u64 {
+    list_rotated_logs()
+        .into_iter()
+        .filter_map(|log| match std::fs::remove_file(&log.path) {
+            Ok(()) => Some(log.size),
+            Err(e) => {
+                log::warn!("Failed to delete {}: {e}", log.path.display());
+                None
+            }
+        })
+        .sum()
+}
 
 pub fn setup_logging() -> Result<(), fern::InitError> {
-    let project_dirs =
-        ProjectDirs::from("net", "ActivityWatch", "Aw-Tauri").expect("Failed to get project dirs");
-    let log_path = project_dirs.data_dir().join("logs");
-    std::fs::create_dir_all(&log_path)?;
-    let log_file = log_path.join("aw-tauri.log");
+    let log_path = get_log_dir();
+    crate::dirs::create_dir_with_retry(&log_path)?;
+    let log_file = log_path.join(LOG_FILE);
 
     // Configure colors for log levels
     let colors = ColoredLevelConfig::new()
@@ -7,32 +7,48 @@
 /// their state.
 ///
 /// If a module crashes, the manager will notify the user and ask if they want to restart it.
-use log::{debug, error, info};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 
 #[cfg(unix)]
 use nix::sys::signal::{self, Signal};
 #[cfg(unix)]
 use nix::unistd::Pid;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::{
-    mpsc::{channel, Receiver, Sender},
+    mpsc::{channel, Receiver, RecvTimeoutError, Sender},
     Arc, Mutex,
 };
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{env, fs, thread};
-use tauri::menu::{CheckMenuItem, Menu, MenuItem, SubmenuBuilder};
-use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use tauri::Url;
+use tokio::io::{AsyncBufReadExt, AsyncRead};
 
 #[cfg(windows)]
-use winapi::shared::minwindef::DWORD;
+use winapi::shared::minwindef::{DWORD, FALSE};
+#[cfg(windows)]
+use winapi::um::handleapi::CloseHandle;
+#[cfg(windows)]
+use winapi::um::processthreadsapi::{OpenProcess, TerminateProcess};
 #[cfg(windows)]
 use winapi::um::wincon::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+#[cfg(windows)]
+use winapi::um::winnt::PROCESS_TERMINATE;
 
-use crate::{get_app_handle, get_config, get_tray_id, HANDLE_CONDVAR};
+use crate::discovery_cache;
+use crate::frontend::{CrashOutcome, Frontend};
+use crate::hooks::{run_hook, HookEvent};
+use crate::notifications::Notification;
+use crate::pid_file;
+use crate::server::{Server, SERVER_MODULE};
+use crate::tray_menu::{MenuModule, TrayMenu};
+use crate::webhook;
+use crate::{get_config, ModuleConfig, UserConfig};
 
 #[derive(Debug)]
 pub enum ModuleMessage {
@@ -43,134 +59,1284 @@ pub enum ModuleMessage {
     },
     Stopped {
         name: String,
+        pid: u32,
         output: std::process::Output,
     },
+    /// Time to check the recorded pids against the live processes
+    Reconcile {},
+    /// A module process started outside of aw-tauri and adopted by it has exited
+    AdoptedExited {
+        name: String,
+        pid: u32,
+    },
+    /// The user chose to replace an adopted module process with one started by aw-tauri
+    ReplaceAdopted {
+        name: String,
+        pid: u32,
+    },
     Init {},
+    /// Time to update the uptimes shown for the running modules
+    RefreshUptimes {},
+    /// The permissions a held back module was waiting for have been granted
+    PermissionsGranted {
+        name: String,
+    },
+    /// A module we asked to stop is still running after the stop timeout
+    StopTimedOut {
+        name: String,
+        pid: u32,
+    },
+    /// The background discovery found other modules than the cached ones
+    Discovered {
+        modules: BTreeMap<String, PathBuf>,
+    },
+    /// A module process couldn't be started
+    SpawnFailed {
+        name: String,
+    },
+    /// The user chose to start a module again that crashed and wasn't restarted
+    Retry {
+        name: String,
+    },
+}
+
+/// Lines of module output kept per module
+const OUTPUT_MAX_LINES: usize = 200;
+/// Bytes of module output kept per module
+const OUTPUT_MAX_BYTES: usize = 64 * 1024;
+/// Bytes of module output kept across all modules
+const OUTPUT_MAX_TOTAL_BYTES: usize = 1024 * 1024;
+
+/// Bounded buffer of the most recent stdout and stderr lines of each module
+#[derive(Debug, Default)]
+pub struct ModuleOutput {
+    lines: HashMap<String, VecDeque<String>>,
+    total_bytes: usize,
+}
+
+impl ModuleOutput {
+    fn push(&mut self, name: &str, mut line: String) {
+        if line.len() > OUTPUT_MAX_BYTES {
+            let mut end = OUTPUT_MAX_BYTES;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+        self.total_bytes += line.len();
+        let lines = self.lines.entry(name.to_string()).or_default();
+        lines.push_back(line);
+
+        let mut bytes: usize = lines.iter().map(String::len).sum();
+        while lines.len() > OUTPUT_MAX_LINES || bytes > OUTPUT_MAX_BYTES {
+            let removed = lines.pop_front().map_or(0, |line| line.len());
+            bytes -= removed;
+            self.total_bytes -= removed;
+        }
+
+        // Evict from the largest buffer until we are within the global cap
+        while self.total_bytes > OUTPUT_MAX_TOTAL_BYTES {
+            let Some(largest) = self
+                .lines
+                .values_mut()
+                .max_by_key(|lines| lines.iter().map(String::len).sum::<usize>())
+            else {
+                break;
+            };
+            let Some(removed) = largest.pop_front() else {
+                break;
+            };
+            self.total_bytes -= removed.len();
+        }
+    }
+
+    /// Returns the buffered lines of a module, oldest first
+    pub fn get(&self, name: &str) -> Vec<String> {
+        self.lines
+            .get(name)
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Time an autostart module waits for the modules it starts `after`
+const AFTER_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval of updating the uptimes in the labels of the running modules
+const TRAY_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// Interval of checking whether an adopted module process is still running
+const ADOPTED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Time between health checks while they are disabled, to notice them being enabled
+const RECONCILE_DISABLED_POLL: Duration = Duration::from_secs(30);
+/// Time after which `stop_modules_and_wait` tells the user it is still stopping modules
+const SLOW_STOP_NOTICE: Duration = Duration::from_secs(1);
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Crashes remembered per module
+const CRASH_HISTORY_MAX: usize = 50;
+
+/// How modules that crash are restarted, the `[restart]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestartConfig {
+    /// Restarts after crashes before giving up, 0 to never restart
+    #[serde(default = "default_restart_limit")]
+    pub limit: u32,
+    /// Delay before the first restart
+    #[serde(default = "default_restart_delay_secs")]
+    pub delay_secs: u64,
+    /// Factor the delay grows by with each restart, e.g. 1 s, 2 s, 4 s with the defaults
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+    /// Uptime after which a crash no longer counts as crashing repeatedly, resetting the
+    /// restart count
+    #[serde(default = "default_stable_secs")]
+    pub stable_secs: u64,
+}
+
+fn default_restart_limit() -> u32 {
+    3
+}
+
+fn default_restart_delay_secs() -> u64 {
+    1
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_stable_secs() -> u64 {
+    5 * 60
+}
+
+/// Longest delay before a restart, however many times the module crashed
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60 * 60);
+
+impl RestartConfig {
+    /// Returns the delay before the `attempt`th restart, counting from 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self
+            .backoff_multiplier
+            .max(1.0)
+            .powi(attempt.saturating_sub(1) as i32);
+        let secs = self.delay_secs as f64 * factor;
+        if secs.is_finite() {
+            Duration::from_secs_f64(secs.min(MAX_RESTART_DELAY.as_secs_f64()))
+        } else {
+            MAX_RESTART_DELAY
+        }
+    }
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        RestartConfig {
+            limit: default_restart_limit(),
+            delay_secs: default_restart_delay_secs(),
+            backoff_multiplier: default_backoff_multiplier(),
+            stable_secs: default_stable_secs(),
+        }
+    }
+}
+
+/// Module whose notifications aw-tauri shows unless `aw_notify.output_only` is off
+const AW_NOTIFY: &str = "aw-notify";
+
+/// How aw-notify is run, the `[aw_notify]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AwNotifyConfig {
+    /// Start aw-notify with `--output-only` and show the notifications it writes, `false` to
+    /// run it like any other module and let it show its own
+    #[serde(default = "default_output_only")]
+    pub output_only: bool,
+}
+
+fn default_output_only() -> bool {
+    true
+}
+
+impl Default for AwNotifyConfig {
+    fn default() -> Self {
+        AwNotifyConfig {
+            output_only: default_output_only(),
+        }
+    }
+}
+
+/// What to do when a module to start is already running outside of aw-tauri, e.g. started by
+/// aw-qt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingInstance {
+    /// Track the running process instead of starting another
+    Adopt,
+    /// Adopt the running process and ask whether to replace it with one started by aw-tauri
+    Ask,
+    /// Start another instance anyway
+    #[default]
+    Ignore,
+}
+
+/// One crash of a module and what the manager did about it
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashRecord {
+    pub timestamp: DateTime<Utc>,
+    /// Exit code, `None` when the module was killed by a signal
+    pub exit_code: Option<i32>,
+    /// Whether the module was restarted, `false` once the restart limit was reached
+    pub restarted: bool,
+}
+
+/// State of a module, for the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleStatus {
+    pub name: String,
+    pub running: bool,
+    pub pid: Option<u32>,
+    pub restart_count: u32,
+    /// Arguments the module was last started with
+    pub args: Option<Vec<String>>,
+}
+
+/// Why tracking is paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// Paused from the tray by the user
+    User,
+    /// Paused by the battery monitor
+    LowBattery,
+    /// Paused outside the tracking hours
+    Schedule,
+}
+
+/// Why we asked a module to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The module should stay stopped
+    Stop,
+    /// The module should be started again as soon as it has exited
+    Restart,
 }
 
 #[derive(Debug)]
 pub struct ManagerState {
     tx: Sender<ModuleMessage>,
+    /// Where module states, crashes and questions are shown
+    frontend: Arc<dyn Frontend>,
     pub modules_running: BTreeMap<String, bool>,
     pub modules_in_path: BTreeMap<String, PathBuf>,
     pub modules_pid: HashMap<String, u32>,
     pub modules_restart_count: HashMap<String, u32>,
+    /// When each module was last started, kept after it stops
+    pub modules_started_at: HashMap<String, Instant>,
+    /// Autostart modules waiting for the modules they start `after`, and when to stop waiting
+    pub modules_deferred: BTreeMap<String, (ModuleConfig, Instant)>,
+    /// Most recent crashes of each module, oldest first
+    pub modules_crash_history: HashMap<String, VecDeque<CrashRecord>>,
     pub modules_args: HashMap<String, Option<Vec<String>>>,
+    /// Module run by each instance with an id of its own, keyed by the id
+    pub modules_binary: HashMap<String, String>,
+    /// Modules we asked to stop, so their exit isn't treated as a crash
+    pub modules_pending_shutdown: HashMap<String, StopReason>,
+    /// Crashed modules waiting to be restarted, with when the restart is due
+    pub pending_restarts: BTreeMap<String, Instant>,
+    /// Modules that didn't exit within the stop timeout and couldn't be killed
+    pub modules_stop_failed: BTreeSet<String>,
+    /// Modules whose process was gone on the last reconciliation, given until the next one to
+    /// report their exit
+    pub modules_vanishing: BTreeSet<String>,
+    pub modules_output: Arc<Mutex<ModuleOutput>>,
     pub modules_menu_set: bool,
+    /// Port of the server the modules should report to
+    pub server_port: u16,
+    /// Where the server runs
+    pub server: Server,
+    /// Whether a spawned or remote server answered the last health check
+    pub server_online: bool,
+    pub pause_reason: Option<PauseReason>,
+    /// Watchers stopped by the pause, started again on resume
+    pub modules_paused: Vec<String>,
+    /// Modules held back until the user grants a permission, with their arguments
+    pub modules_waiting_permission: HashMap<String, Option<Vec<String>>>,
+    /// Profile whose modules are started on autostart
+    pub active_profile: Option<String>,
+    /// Until when the tracking schedule is suspended by "Track anyway"
+    pub schedule_override_until: Option<Instant>,
+    /// Modules the user stopped from the tray, not autostarted until they start them again
+    pub modules_user_stopped: BTreeSet<String>,
+    /// File keeping `modules_user_stopped` across restarts
+    user_stopped_file: PathBuf,
+    /// Modules whose process is being started, until it reports its pid
+    pub modules_spawning: BTreeSet<String>,
+    /// Modules already checked for an instance running outside of aw-tauri this session
+    pub modules_scanned: BTreeSet<String>,
 }
 
 impl ManagerState {
-    fn new(tx: Sender<ModuleMessage>) -> ManagerState {
+    fn new(
+        tx: Sender<ModuleMessage>,
+        server_port: u16,
+        server: Server,
+        frontend: Arc<dyn Frontend>,
+    ) -> ManagerState {
+        let modules_in_path = discovery_cache::load().unwrap_or_else(|| {
+            let modules = get_modules_in_path();
+            discovery_cache::save(&modules);
+            modules
+        });
+        ManagerState::with_modules(tx, server_port, server, frontend, modules_in_path)
+    }
+    /// Creates the state with the given discovered modules, e.g. the mock modules of the tests
+    pub(crate) fn with_modules(
+        tx: Sender<ModuleMessage>,
+        server_port: u16,
+        server: Server,
+        frontend: Arc<dyn Frontend>,
+        modules_in_path: BTreeMap<String, PathBuf>,
+    ) -> ManagerState {
         ManagerState {
             tx,
+            frontend,
             modules_running: BTreeMap::new(),
-            modules_in_path: get_modules_in_path(),
+            modules_in_path,
             modules_pid: HashMap::new(),
             modules_restart_count: HashMap::new(),
+            modules_started_at: HashMap::new(),
+            modules_deferred: BTreeMap::new(),
+            modules_crash_history: HashMap::new(),
             modules_args: HashMap::new(),
+            modules_binary: HashMap::new(),
+            modules_pending_shutdown: HashMap::new(),
+            pending_restarts: BTreeMap::new(),
+            modules_stop_failed: BTreeSet::new(),
+            modules_vanishing: BTreeSet::new(),
+            modules_output: Arc::new(Mutex::new(ModuleOutput::default())),
             modules_menu_set: false,
+            server_port,
+            server,
+            server_online: true,
+            pause_reason: None,
+            modules_paused: Vec::new(),
+            modules_waiting_permission: HashMap::new(),
+            active_profile: crate::cli_profile().or_else(|| get_config().active_profile.clone()),
+            schedule_override_until: None,
+            modules_user_stopped: load_user_stopped(&user_stopped_path()),
+            user_stopped_file: user_stopped_path(),
+            modules_spawning: BTreeSet::new(),
+            modules_scanned: BTreeSet::new(),
         }
     }
     fn started_module(&mut self, name: &str, pid: u32, args: Option<Vec<String>>) {
         info!("Started module: {name}");
+        self.modules_spawning.remove(name);
         self.modules_running.insert(name.to_string(), true);
         self.modules_pid.insert(name.to_string(), pid);
+        self.modules_started_at
+            .insert(name.to_string(), Instant::now());
         self.modules_args.insert(name.to_string(), args);
         debug!("Running modules: {:?}", self.modules_running);
+        self.save_pids();
+        self.start_deferred_modules();
         self.update_tray_menu();
+        run_hook(
+            HookEvent::ModuleStarted,
+            &[("AW_MODULE", name.to_string()), ("AW_PID", pid.to_string())],
+        );
     }
     fn stopped_module(&mut self, name: &str) {
         info!("Stopped module: {name}");
         self.modules_running.insert(name.to_string(), false);
         self.modules_pid.remove(name);
+        self.modules_vanishing.remove(name);
+        self.save_pids();
         self.update_tray_menu();
     }
-    fn update_tray_menu(&mut self) {
-        let (lock, cvar) = &*HANDLE_CONDVAR;
-        let mut state = lock.lock().unwrap();
-
-        debug!("Attempting to get app handle");
-        while !*state {
-            state = cvar.wait(state).unwrap();
+    /// Saves the pids of the running modules, to clean up after them if aw-tauri crashes
+    fn save_pids(&self) {
+        let processes = self
+            .modules_pid
+            .iter()
+            .filter_map(|(name, pid)| {
+                let exe = self.modules_in_path.get(self.binary(name))?.clone();
+                Some((name.clone(), pid_file::ModuleProcess { pid: *pid, exe }))
+            })
+            .collect();
+        pid_file::save(&processes);
+    }
+    /// Adopts the modules a crashed session left running, instead of starting them again.
+    ///
+    /// Modules that are neither configured nor discovered anymore are stopped. A saved pid is only
+    /// used if its process still runs the saved executable, since the pid may have been reused.
+    fn recover_orphans(&mut self) {
+        let config = get_config();
+        for (name, process) in pid_file::load() {
+            if !runs_executable(process.pid, &process.exe) {
+                continue;
+            }
+            let pid = process.pid;
+            if let Some(module_config) = find_module_config(&config, &name) {
+                if module_config.id.is_some() {
+                    self.modules_binary
+                        .insert(name.clone(), module_config.name.clone());
+                }
+            }
+            if self.modules_in_path.contains_key(self.binary(&name)) {
+                info!("{name} (pid {pid}) was left running by a previous session");
+                self.adopt_module(&name, pid, process.exe, None);
+            } else {
+                warn!("Stopping {name} (pid {pid}) left running by a previous session");
+                if let Err(e) = send_sigterm(pid) {
+                    error!("Failed to stop {name} (pid {pid}): {e}");
+                }
+            }
         }
-        debug!("Condition variable set");
-        let app = &*get_app_handle().lock().expect("failed to get app handle");
-        debug!("App handle acquired");
-
-        let open = MenuItem::with_id(app, "open", "Open", true, None::<&str>)
-            .expect("failed to create open menu item");
-        let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)
-            .expect("failed to create quit menu item");
-
-        let mut modules_submenu_builder = SubmenuBuilder::new(app, "Modules");
+    }
+    /// Shows the current module states in the frontend, e.g. by rebuilding the tray menu
+    pub(crate) fn update_tray_menu(&self) {
+        let mut modules = Vec::new();
         for (module, running) in self.modules_running.iter() {
-            let label = module;
-            let module_menu =
-                CheckMenuItem::with_id(app, module, label, true, *running, None::<&str>)
-                    .expect("failed to create module menu item");
-            modules_submenu_builder = modules_submenu_builder.item(&module_menu);
+            let crashed = self
+                .modules_crash_history
+                .get(module)
+                .and_then(VecDeque::back)
+                .is_some_and(|crash| !crash.restarted);
+            let name = self.label(module);
+            let label = if *running && self.modules_stop_failed.contains(module) {
+                format!("{name} (stop failed)")
+            } else if *running {
+                self.uptime_label(module).unwrap_or(name)
+            } else if self.modules_user_stopped.contains(module) {
+                format!("{name} (stopped by you)")
+            } else if crashed {
+                format!("{name} (crashed)")
+            } else {
+                name
+            };
+            modules.push(MenuModule {
+                id: module.clone(),
+                label,
+                running: Some(*running),
+            });
         }
-
         for module_name in self.modules_in_path.keys() {
             if !self.modules_running.contains_key(module_name) {
-                let module_menu =
-                    MenuItem::with_id(app, module_name, module_name, true, None::<&str>)
-                        .expect("failed to create module menu item");
-                modules_submenu_builder = modules_submenu_builder.item(&module_menu);
+                let label = if self.modules_waiting_permission.contains_key(module_name) {
+                    format!("{module_name} (waiting for permission)")
+                } else if self.modules_user_stopped.contains(module_name) {
+                    format!("{module_name} (stopped by you)")
+                } else {
+                    module_name.clone()
+                };
+                modules.push(MenuModule {
+                    id: module_name.clone(),
+                    label,
+                    running: None,
+                });
             }
         }
+        let server_status = if !self.server_online {
+            Some(format!("Server offline: {}", self.server_url()))
+        } else if self.server.remote_url().is_some() {
+            Some(format!("Server: {}", self.server_url()))
+        } else {
+            None
+        };
+        self.frontend.update_modules(TrayMenu {
+            modules,
+            running: self
+                .modules_pid
+                .keys()
+                .map(|module| (module.clone(), self.label(module)))
+                .collect(),
+            pause_reason: self.pause_reason,
+            active_profile: self.active_profile.clone(),
+            server_status,
+        });
+    }
+    /// Returns the label of a running module with its pid and uptime, `None` if they're unknown
+    fn uptime_label(&self, module: &str) -> Option<String> {
+        let pid = self.modules_pid.get(module)?;
+        let started_at = self.modules_started_at.get(module)?;
+        Some(format!(
+            "{} (pid {pid}, up {})",
+            self.label(module),
+            format_uptime(started_at.elapsed())
+        ))
+    }
+    /// Updates the uptimes in the labels of the running modules, without rebuilding the menu
+    pub(crate) fn refresh_uptimes(&self) {
+        let labels: Vec<(String, String)> = self
+            .modules_running
+            .iter()
+            .filter(|(module, running)| **running && !self.modules_stop_failed.contains(*module))
+            .filter_map(|(module, _)| Some((module.clone(), self.uptime_label(module)?)))
+            .collect();
+        // Nothing shows an uptime while no module runs
+        if !labels.is_empty() {
+            self.frontend.update_labels(labels);
+        }
+    }
+    /// Starts the modules of the active profile, or those listed in the config without one
+    pub fn start_autostart_modules(&mut self) {
+        // The server is needed even in safe mode
+        if let Some(args) = self.server.process_args(self.server_port) {
+            self.start_module(SERVER_MODULE, Some(&args));
+        }
+        if crate::is_safe_mode() {
+            info!("Safe mode, not starting any modules");
+            return;
+        }
+        let profile = self.active_profile.clone();
+        let config = get_config();
+        let modules: Vec<&ModuleConfig> = config
+            .profile_modules(profile.as_deref())
+            .iter()
+            .filter(|module_config| {
+                let stopped = self.modules_user_stopped.contains(module_config.key());
+                if stopped {
+                    info!(
+                        "Not starting {}, it was stopped by the user",
+                        module_config.key()
+                    );
+                }
+                !stopped
+            })
+            .collect();
+        let (ordered, cycle) = order_modules(&modules);
+        if !cycle.is_empty() {
+            error!(
+                "The modules {} wait for each other in a cycle, starting them without waiting",
+                cycle.join(", ")
+            );
+        }
+        for module_config in ordered {
+            // Waiting for another module of the cycle would always time out
+            let mut module_config = module_config.clone();
+            if cycle.contains(&module_config.key()) {
+                module_config
+                    .after
+                    .retain(|after| !cycle.contains(&after.as_str()));
+            }
+            let waiting_for: Vec<&str> = module_config
+                .after
+                .iter()
+                .map(String::as_str)
+                .filter(|after| !self.is_module_running(after))
+                .collect();
+            if waiting_for.is_empty() {
+                self.start_module_config(&module_config);
+            } else {
+                info!(
+                    "Starting {} once {} started",
+                    module_config.key(),
+                    waiting_for.join(", ")
+                );
+                self.modules_deferred.insert(
+                    module_config.key().to_string(),
+                    (module_config, Instant::now() + AFTER_TIMEOUT),
+                );
+            }
+        }
+    }
+    /// Starts the deferred modules whose `after` modules all run
+    fn start_deferred_modules(&mut self) {
+        let ready: Vec<String> = self
+            .modules_deferred
+            .iter()
+            .filter(|(_, (module_config, _))| {
+                module_config
+                    .after
+                    .iter()
+                    .all(|after| self.is_module_running(after))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in ready {
+            if let Some((module_config, _)) = self.modules_deferred.remove(&name) {
+                self.start_module_config(&module_config);
+            }
+        }
+    }
+    /// Switches to another profile, stopping the modules it doesn't include and starting the
+    /// missing ones. The profile is saved so the next autostart uses it.
+    pub fn switch_profile(&mut self, profile: &str) {
+        let config = get_config();
+        if !config.profiles.contains_key(profile) {
+            error!("Unknown profile: {profile}");
+            return;
+        }
+        info!("Switching to profile {profile}");
+        self.run_profile_modules(config.profile_modules(Some(profile)));
 
-        let module_submenu = modules_submenu_builder
-            .build()
-            .expect("failed to create module submenu");
-        let menu = Menu::with_items(app, &[&open, &module_submenu, &quit])
-            .expect("failed to create tray menu");
-
-        let tray_id = get_tray_id();
-        app.tray_by_id(tray_id)
-            .expect("failed to get tray by id")
-            .set_menu(Some(menu))
-            .unwrap();
-        println!("set tray menu");
+        self.active_profile = Some(profile.to_string());
+        if let Err(e) =
+            crate::update_config(|config| config.active_profile = Some(profile.to_string()))
+        {
+            error!("Failed to remember profile {profile}: {e}");
+        }
+        self.update_tray_menu();
+    }
+    /// Stops the running modules `modules` leaves out, except the server, and starts the others
+    fn run_profile_modules(&mut self, modules: &[ModuleConfig]) {
+        let running: Vec<String> = self.modules_pid.keys().cloned().collect();
+        for name in running {
+            if name != SERVER_MODULE && !modules.iter().any(|module| module.key() == name) {
+                self.stop_module(&name);
+            }
+        }
+        for module_config in modules {
+            self.start_module_config(module_config);
+        }
+    }
+    /// Runs the modules of a config reset to the defaults: no profile, no modules held back
+    /// because the user stopped them, and only the autostart modules besides the server
+    pub fn reset_modules(&mut self, config: &UserConfig) {
+        self.active_profile = None;
+        if !self.modules_user_stopped.is_empty() {
+            self.modules_user_stopped.clear();
+            save_user_stopped(&self.user_stopped_file, &self.modules_user_stopped);
+        }
+        let running: Vec<String> = self.modules_pid.keys().cloned().collect();
+        for name in running {
+            if name != SERVER_MODULE
+                && !config
+                    .autostart_modules
+                    .iter()
+                    .any(|module| module.key() == name)
+            {
+                self.stop_module(&name);
+            }
+        }
+        for module in &config.autostart_modules {
+            self.start_module_config(module);
+        }
+        self.update_tray_menu();
     }
-    pub fn start_module(&self, name: &str, args: Option<&Vec<String>>) {
+    /// Starts the modules added to the config and stops the removed ones.
+    ///
+    /// Changed arguments apply the next time a module starts.
+    pub fn apply_module_changes(&mut self, old: &[ModuleConfig], new: &[ModuleConfig]) {
+        for module in old {
+            let removed = !new.iter().any(|new| new.key() == module.key());
+            if removed && self.is_module_running(module.key()) {
+                info!("{} was removed from the config, stopping it", module.key());
+                self.stop_module(module.key());
+            }
+        }
+        if crate::is_safe_mode() {
+            return;
+        }
+        for module in new {
+            let added = !old.iter().any(|old| old.key() == module.key());
+            if !added || self.modules_user_stopped.contains(module.key()) {
+                continue;
+            }
+            if self.pause_reason.is_some() && is_watcher(&module.name) {
+                info!(
+                    "{} was added to the config, starting it when tracking resumes",
+                    module.key()
+                );
+                if module.id.is_some() {
+                    self.modules_binary
+                        .insert(module.key().to_string(), module.name.clone());
+                }
+                self.modules_args
+                    .insert(module.key().to_string(), module.parsed_args());
+                self.modules_paused.push(module.key().to_string());
+                continue;
+            }
+            info!("{} was added to the config, starting it", module.key());
+            self.start_module_config(module);
+        }
+    }
+    /// Returns the module an instance runs
+    pub(crate) fn binary<'a>(&'a self, name: &'a str) -> &'a str {
+        self.modules_binary.get(name).map_or(name, String::as_str)
+    }
+    /// Returns the tray label of an instance, e.g. "aw-watcher-input (keyboard)"
+    fn label(&self, name: &str) -> String {
+        match self.modules_binary.get(name) {
+            Some(binary) => format!("{binary} ({name})"),
+            None => name.to_string(),
+        }
+    }
+    /// Starts a module as configured, keyed by its instance id if it has one
+    pub fn start_module_config(&mut self, module: &ModuleConfig) {
+        if module.id.is_some() {
+            self.modules_binary
+                .insert(module.key().to_string(), module.name.clone());
+        }
+        self.start_module(module.key(), module.parsed_args().as_ref());
+    }
+    pub fn start_module(&mut self, name: &str, args: Option<&Vec<String>>) {
+        // Started by other means before a scheduled restart was due, or before the modules it
+        // waited for
+        self.pending_restarts.remove(name);
+        self.modules_deferred.remove(name);
+        #[cfg(target_os = "macos")]
+        {
+            if self.modules_waiting_permission.contains_key(name) {
+                return;
+            }
+            let missing = crate::permissions::check_module(self.binary(name));
+            if !missing.is_empty() {
+                self.modules_waiting_permission
+                    .insert(name.to_string(), args.cloned());
+                crate::permissions::wait_for_permissions(
+                    name.to_string(),
+                    missing,
+                    self.tx.clone(),
+                );
+                // Refreshed through the handler, this may run before the tray exists
+                let _ = self.tx.send(ModuleMessage::Init {});
+                return;
+            }
+        }
+        if self.modules_spawning.contains(name) {
+            debug!("Module {name} is already starting");
+            return;
+        }
         if !self.is_module_running(name) {
-            if let Some(path) = self.modules_in_path.get(name) {
-                start_module_thread(
+            if let Some(path) = self.modules_in_path.get(self.binary(name)).cloned() {
+                let existing_instance = get_config().defaults.existing_instance;
+                if existing_instance != ExistingInstance::Ignore && self.should_scan(name) {
+                    let own_pids: BTreeSet<u32> = self.modules_pid.values().copied().collect();
+                    if let Some(pid) = find_external_instance(&path, &own_pids) {
+                        self.adopt_module(name, pid, path, args.cloned());
+                        if existing_instance == ExistingInstance::Ask {
+                            self.ask_to_replace(name, pid);
+                        }
+                        return;
+                    }
+                }
+                self.modules_spawning.insert(name.to_string());
+                // aw-notify shows its notifications through us only with --output-only
+                let (output_only, emit_notifications) = if self.binary(name) == AW_NOTIFY {
+                    let output_only = get_config().aw_notify.output_only;
+                    (output_only, output_only)
+                } else {
+                    (false, emits_notifications(name))
+                };
+                start_module_task(
                     name.to_string(),
-                    path.clone(),
+                    path,
                     args.cloned(),
+                    output_only,
+                    emit_notifications,
+                    is_low_priority(&get_config(), name),
+                    module_cwd(name),
+                    self.default_module_args(),
                     self.tx.clone(),
+                    Arc::clone(&self.modules_output),
+                    Arc::clone(&self.frontend),
                 );
             } else {
-                error!("Module {name} not found in PATH");
+                error!("Module {} not found in PATH", self.binary(name));
+            }
+        }
+    }
+    pub fn stop_module(&mut self, name: &str) {
+        self.stop_module_with_reason(name, StopReason::Stop);
+    }
+    /// Returns whether to look for an instance of the module running outside of aw-tauri.
+    ///
+    /// Only the first start of a module in a session looks, and only while aw-tauri neither runs
+    /// nor starts another instance of the same module. A process whose pid aw-tauri doesn't know
+    /// yet would otherwise look like someone else's.
+    fn should_scan(&mut self, name: &str) -> bool {
+        if !self.modules_scanned.insert(name.to_string()) {
+            return false;
+        }
+        let binary = self.binary(name);
+        !self
+            .modules_pid
+            .keys()
+            .chain(&self.modules_spawning)
+            .any(|other| self.binary(other) == binary)
+    }
+    /// Tracks a module process started outside of aw-tauri as if it had been started by it.
+    ///
+    /// Its output isn't captured. Its exit is noticed by polling, since it isn't our child.
+    fn adopt_module(&mut self, name: &str, pid: u32, path: PathBuf, args: Option<Vec<String>>) {
+        info!("{name} is already running (pid {pid}), adopting it instead of starting another");
+        self.started_module(name, pid, args);
+        let tx = self.tx.clone();
+        let name = name.to_string();
+        crate::shutdown::spawn(&format!("adopted {name}"), move || {
+            while !crate::shutdown::sleep(ADOPTED_POLL_INTERVAL) {
+                if !is_process_alive(pid, Some(&path)) {
+                    let _ = tx.send(ModuleMessage::AdoptedExited { name, pid });
+                    return;
+                }
             }
+        });
+    }
+    /// Asks the user whether to replace an adopted module process with one started by aw-tauri
+    fn ask_to_replace(&self, name: &str, pid: u32) {
+        let tx = self.tx.clone();
+        let replaced = name.to_string();
+        self.frontend.ask_to_replace(
+            name,
+            pid,
+            Box::new(move || {
+                let _ = tx.send(ModuleMessage::ReplaceAdopted {
+                    name: replaced,
+                    pid,
+                });
+            }),
+        );
+    }
+    /// Restarts a running module without the delay used after crashes
+    pub fn restart_module(&mut self, name: &str) {
+        if self.is_module_running(name) {
+            self.stop_module_with_reason(name, StopReason::Restart);
+        } else {
+            let stored_args = self.modules_args.get(name).cloned().flatten();
+            self.start_module(name, stored_args.as_ref());
+        }
+    }
+    /// Starts a module again after its cause of crashing was fixed, with a fresh restart limit.
+    ///
+    /// Returns `false` if the module isn't installed.
+    pub fn retry_module(&mut self, name: &str) -> bool {
+        if !self.modules_in_path.contains_key(self.binary(name)) {
+            error!("Cannot retry {name}, it is not installed");
+            return false;
         }
+        info!("Retrying module {name}");
+        self.modules_restart_count.remove(name);
+        self.modules_pending_shutdown.remove(name);
+        let stored_args = self.modules_args.get(name).cloned().flatten();
+        self.start_module(name, stored_args.as_ref());
+        true
     }
-    pub fn stop_module(&self, name: &str) {
+    fn stop_module_with_reason(&mut self, name: &str, reason: StopReason) {
+        if reason == StopReason::Stop && self.pending_restarts.remove(name).is_some() {
+            info!("Cancelled the pending restart of {name}");
+        }
         if let Some(pid) = self.modules_pid.get(name) {
             if let Err(e) = send_sigterm(*pid) {
                 error!("Failed to send SIGTERM to module {name}: {e}");
             } else {
                 debug!("Sent SIGTERM to module: {name}");
+                self.modules_pending_shutdown
+                    .insert(name.to_string(), reason);
+                let tx = self.tx.clone();
+                let (name, pid) = (name.to_string(), *pid);
+                tauri::async_runtime::spawn(async move {
+                    tokio::time::sleep(stop_timeout()).await;
+                    let _ = tx.send(ModuleMessage::StopTimedOut { name, pid });
+                });
             }
         }
     }
-    pub fn stop_modules(&self) {
-        for (name, _pid) in self.modules_pid.iter() {
-            self.stop_module(name);
+    /// Counts a crash against the configured restart limit and schedules a restart, unless the
+    /// limit is reached. The delay grows with the number of restarts so far.
+    ///
+    /// A module that ran for `stable_secs` before crashing starts over with a restart count of 0,
+    /// without this crash counting, so occasional crashes don't add up to the limit.
+    ///
+    /// The user is told what happened to the module. Returns whether it will be restarted.
+    fn module_crashed(
+        &mut self,
+        name: &str,
+        exit_code: Option<i32>,
+        stderr_tail: String,
+    ) -> CrashOutcome {
+        let config = get_config();
+        let restart_config = &config.restart;
+        let stable = Duration::from_secs(restart_config.stable_secs);
+        let ran_stable = self
+            .modules_started_at
+            .get(name)
+            .is_some_and(|started_at| started_at.elapsed() >= stable);
+        if ran_stable {
+            debug!("Module {name} ran for over {stable:?}, resetting its restart count");
+            self.modules_restart_count.remove(name);
+        }
+        let restart_count = self
+            .modules_restart_count
+            .entry(name.to_string())
+            .or_insert(0);
+        let restart_limit_reached = *restart_count >= restart_config.limit;
+        if !restart_limit_reached && !ran_stable {
+            *restart_count += 1;
+        }
+        let restart_count = *restart_count;
+        self.record_crash(
+            name,
+            CrashRecord {
+                timestamp: Utc::now(),
+                exit_code,
+                restarted: !restart_limit_reached,
+            },
+        );
+        webhook::report_crash(webhook::CrashPayload::new(
+            name,
+            exit_code,
+            restart_count,
+            restart_limit_reached,
+            stderr_tail,
+        ));
+        let outcome = if restart_config.limit == 0 {
+            error!("Module {name} crashed, restarts are disabled");
+            CrashOutcome::RestartsDisabled
+        } else if restart_limit_reached {
+            error!("Module {name} exceeded crash restart limit");
+            CrashOutcome::LimitReached
+        } else {
+            error!("Module {name} crashed and is being restarted");
+            self.pending_restarts.insert(
+                name.to_string(),
+                Instant::now() + restart_config.delay(restart_count),
+            );
+            CrashOutcome::Restarting
+        };
+        let tx = self.tx.clone();
+        let retry_name = name.to_string();
+        self.frontend.module_crashed(
+            name,
+            outcome,
+            Box::new(move || {
+                let _ = tx.send(ModuleMessage::Retry { name: retry_name });
+            }),
+        );
+        outcome
+    }
+    /// Returns when the next pending restart is due
+    fn next_restart_due(&self) -> Option<Instant> {
+        self.pending_restarts
+            .values()
+            .chain(self.modules_deferred.values().map(|(_, deadline)| deadline))
+            .min()
+            .copied()
+    }
+    /// Restarts the crashed modules whose delay has passed
+    pub(crate) fn run_due_restarts(&mut self) {
+        let now = Instant::now();
+        let due: Vec<String> = self
+            .pending_restarts
+            .iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in due {
+            self.pending_restarts.remove(&name);
+            let stored_args = self.modules_args.get(&name).cloned().flatten();
+            self.start_module(&name, stored_args.as_ref());
+        }
+        let timed_out: Vec<String> = self
+            .modules_deferred
+            .iter()
+            .filter(|(_, (_, deadline))| *deadline <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in timed_out {
+            if let Some((module_config, _)) = self.modules_deferred.remove(&name) {
+                warn!(
+                    "{name} waited {AFTER_TIMEOUT:?} for {}, starting it anyway",
+                    module_config.after.join(", ")
+                );
+                self.start_module_config(&module_config);
+            }
+        }
+    }
+    /// Checks the recorded pids against the live processes, for modules killed without their
+    /// exit being noticed, e.g. if their task died.
+    ///
+    /// A module whose process is gone on two passes in a row is stopped, or handled as crashed
+    /// if it wasn't asked to stop.
+    fn reconcile(&mut self, is_alive: impl Fn(u32, Option<&Path>) -> bool) {
+        let vanished: Vec<String> = self
+            .modules_pid
+            .iter()
+            .filter(|(name, pid)| {
+                !is_alive(
+                    **pid,
+                    self.modules_in_path
+                        .get(self.binary(name))
+                        .map(PathBuf::as_path),
+                )
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        let suspects = std::mem::replace(
+            &mut self.modules_vanishing,
+            vanished.iter().cloned().collect(),
+        );
+        for name in vanished.iter().filter(|name| suspects.contains(*name)) {
+            warn!("Module {name} is no longer running");
+            self.module_vanished(name);
+        }
+        // Stop requests for modules that aren't running anymore
+        let modules_pid = &self.modules_pid;
+        self.modules_pending_shutdown
+            .retain(|name, _| modules_pid.contains_key(name));
+    }
+    /// Handles the exit of a module noticed without its exit status, restarting it if it was
+    /// asked to restart and handling it as crashed if it wasn't asked to stop
+    fn module_vanished(&mut self, name: &str) {
+        self.modules_vanishing.remove(name);
+        let stop_reason = self.modules_pending_shutdown.remove(name);
+        let stop_failed = self.modules_stop_failed.remove(name);
+        self.stopped_module(name);
+        match stop_reason {
+            Some(StopReason::Restart) => {
+                let stored_args = self.modules_args.get(name).cloned().flatten();
+                self.start_module(name, stored_args.as_ref());
+            }
+            Some(StopReason::Stop) => {}
+            None if stop_failed => {}
+            None => {
+                run_hook(
+                    HookEvent::ModuleCrashed,
+                    &[
+                        ("AW_MODULE", name.to_string()),
+                        ("AW_EXIT_CODE", String::new()),
+                    ],
+                );
+                self.module_crashed(name, None, String::new());
+            }
+        }
+    }
+    /// Gives up on a stop request the module ignored, so the user can try again or intervene
+    fn stop_timed_out(&mut self, name: &str, pid: u32) {
+        // The module may have exited, or been started again, in the meantime
+        if self.modules_pid.get(name) != Some(&pid)
+            || !self.modules_pending_shutdown.contains_key(name)
+        {
+            return;
+        }
+        let timeout = stop_timeout();
+        // Its exit is then handled like that of a module that stopped by itself
+        match send_sigkill(pid) {
+            Ok(()) => {
+                warn!("Module {name} (pid {pid}) did not stop within {timeout:?}, killed it");
+                return;
+            }
+            Err(e) => error!(
+                "Module {name} (pid {pid}) did not stop within {timeout:?} and could not be \
+                killed: {e}"
+            ),
+        }
+        self.modules_pending_shutdown.remove(name);
+        self.modules_stop_failed.insert(name.to_string());
+        self.update_tray_menu();
+        self.frontend.notify(
+            format!(
+                "{name} (pid {pid}) did not stop. Use Force stop in the tray or end it from your \
+                task manager."
+            )
+            .into(),
+        );
+    }
+    /// Kills a module immediately, for modules that ignore the graceful stop
+    pub fn force_stop_module(&mut self, name: &str) -> Result<(), String> {
+        let Some(pid) = self.modules_pid.get(name) else {
+            return Err(format!("Module {name} is not running"));
+        };
+        if let Err(e) = send_sigkill(*pid) {
+            error!("Failed to kill module {name}: {e}");
+            return Err(format!("Failed to kill module {name}: {e}"));
+        }
+        info!("Force stopped module: {name}");
+        self.modules_pending_shutdown
+            .insert(name.to_string(), StopReason::Stop);
+        Ok(())
+    }
+    pub fn stop_modules(&mut self) {
+        let names: Vec<String> = self.modules_pid.keys().cloned().collect();
+        for name in names {
+            self.stop_module(&name);
         }
     }
     pub fn handle_system_click(&mut self, name: &str) {
+        let result = if self.is_module_running(name) {
+            self.user_stop_module(name)
+        } else {
+            self.user_start_module(name)
+        };
+        if let Err(e) = result {
+            error!("{e}");
+        }
+    }
+    /// Starts a module at the user's request, undoing an earlier `user_stop_module`
+    pub fn user_start_module(&mut self, name: &str) -> Result<(), String> {
+        if !self.modules_in_path.contains_key(self.binary(name)) {
+            return Err(format!("Module {name} is not installed"));
+        }
+        if !self.is_module_running(name) {
+            let stored_args = self.modules_args.get(name).cloned().flatten();
+            self.start_module(name, stored_args.as_ref());
+        }
+        if self.modules_user_stopped.remove(name) {
+            save_user_stopped(&self.user_stopped_file, &self.modules_user_stopped);
+        }
+        Ok(())
+    }
+    /// Stops a module at the user's request, so it isn't started again automatically
+    pub fn user_stop_module(&mut self, name: &str) -> Result<(), String> {
+        if !self.modules_in_path.contains_key(self.binary(name)) {
+            return Err(format!("Module {name} is not installed"));
+        }
         if self.is_module_running(name) {
             self.stop_module(name);
-        } else {
-            self.start_module(name, None);
         }
+        if self.modules_user_stopped.insert(name.to_string()) {
+            save_user_stopped(&self.user_stopped_file, &self.modules_user_stopped);
+        }
+        Ok(())
+    }
+    /// Keeps the modules the user stopped in `path` instead, loading the ones saved there
+    #[cfg(test)]
+    pub(crate) fn use_user_stopped_file(&mut self, path: PathBuf) {
+        self.modules_user_stopped = load_user_stopped(&path);
+        self.user_stopped_file = path;
     }
     fn is_module_running(&self, name: &str) -> bool {
         *self.modules_running.get(name).unwrap_or(&false)
     }
+    /// Stops the running watchers until `resume_tracking` is called.
+    ///
+    /// Returns `false` if tracking was already paused, in which case nothing changes.
+    pub fn pause_tracking(&mut self, reason: PauseReason) -> bool {
+        if self.pause_reason.is_some() {
+            return false;
+        }
+        info!("Pausing tracking: {reason:?}");
+        let watchers: Vec<String> = self
+            .modules_running
+            .iter()
+            .filter(|(name, running)| **running && is_watcher(self.binary(name)))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &watchers {
+            self.stop_module(name);
+        }
+        self.modules_paused = watchers;
+        self.pause_reason = Some(reason);
+        self.update_tray_menu();
+        run_hook(
+            HookEvent::TrackingPaused,
+            &[("AW_PAUSE_REASON", format!("{reason:?}"))],
+        );
+        true
+    }
+    /// Starts the watchers stopped by the pause again
+    pub fn resume_tracking(&mut self) {
+        if self.pause_reason.take().is_none() {
+            return;
+        }
+        info!("Resuming tracking");
+        run_hook(HookEvent::TrackingResumed, &[]);
+        for name in std::mem::take(&mut self.modules_paused) {
+            let stored_args = self.modules_args.get(&name).cloned().flatten();
+            self.start_module(&name, stored_args.as_ref());
+        }
+        self.update_tray_menu();
+    }
+    /// Suspends the tracking schedule for a while and resumes tracking
+    pub fn override_schedule(&mut self) {
+        info!("Tracking outside the schedule");
+        self.schedule_override_until = Some(Instant::now() + crate::schedule::OVERRIDE_DURATION);
+        self.resume_tracking();
+    }
+    /// Returns the base URL of the server the modules report to
+    pub fn server_url(&self) -> String {
+        self.server.base_url(self.server_port)
+    }
+    /// Returns the arguments modules are started with when none are configured
+    fn default_module_args(&self) -> Vec<String> {
+        let port = self.server_port.to_string();
+        match self.server.remote_url().and_then(Url::host_str) {
+            Some(host) => vec![
+                "--host".to_string(),
+                host.to_string(),
+                "--port".to_string(),
+                port,
+            ],
+            None if crate::local_host() != "127.0.0.1" => vec![
+                "--host".to_string(),
+                crate::local_host(),
+                "--port".to_string(),
+                port,
+            ],
+            None => vec!["--port".to_string(), port],
+        }
+    }
+    /// Points the modules at the server on another port, restarting the running ones so they
+    /// pick up the new `--port`. In process mode the server module is restarted on it too.
+    pub fn set_server_port(&mut self, port: u16) {
+        if port == self.server_port {
+            return;
+        }
+        self.server_port = port;
+        if let Some(args) = self.server.process_args(port) {
+            self.modules_args
+                .insert(SERVER_MODULE.to_string(), Some(args));
+        }
+        let running: Vec<String> = self.modules_pid.keys().cloned().collect();
+        for name in running {
+            self.restart_module(&name);
+        }
+        self.update_tray_menu();
+    }
+    /// Returns the modules of the active profile that weren't found in PATH or the discovery
+    /// folder, which is why they aren't running
+    pub fn missing_modules(&self) -> Vec<String> {
+        get_config()
+            .profile_modules(self.active_profile.as_deref())
+            .iter()
+            .filter(|module| !self.modules_in_path.contains_key(&module.name))
+            .map(|module| module.name.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+    /// Returns the names of the modules found in PATH and the discovery folder
+    pub fn installed_modules(&self) -> BTreeSet<String> {
+        self.modules_in_path.keys().cloned().collect()
+    }
+    /// Returns the state of the installed modules and the modules started this session
+    pub fn modules_status(&self) -> Vec<ModuleStatus> {
+        self.modules_in_path
+            .keys()
+            .chain(self.modules_running.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| ModuleStatus {
+                name: name.clone(),
+                running: self.is_module_running(name),
+                pid: self.modules_pid.get(name).copied(),
+                restart_count: self.modules_restart_count.get(name).copied().unwrap_or(0),
+                args: self.modules_args.get(name).cloned().flatten(),
+            })
+            .collect()
+    }
+    /// Returns the recorded crashes of a module, oldest first
+    pub fn get_crash_history(&self, name: &str) -> Vec<CrashRecord> {
+        self.modules_crash_history
+            .get(name)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+    fn record_crash(&mut self, name: &str, record: CrashRecord) {
+        let history = self
+            .modules_crash_history
+            .entry(name.to_string())
+            .or_default();
+        if history.len() >= CRASH_HISTORY_MAX {
+            history.pop_front();
+        }
+        history.push_back(record);
+    }
+    /// Returns the most recent stdout lines of a module
+    pub fn get_module_output(&self, name: &str) -> Vec<String> {
+        self.modules_output.lock().unwrap().get(name)
+    }
+}
+
+/// Returns `true` for modules that track activity, as opposed to e.g. aw-notify
+fn is_watcher(name: &str) -> bool {
+    name.starts_with("aw-watcher") || name == "aw-awatcher"
 }
 
 #[cfg(unix)]
@@ -184,6 +1350,29 @@ fn send_sigterm(pid: u32) -> Result<(), nix::Error> {
     }
 }
 
+#[cfg(unix)]
+fn send_sigkill(pid: u32) -> Result<(), nix::Error> {
+    signal::kill(Pid::from_raw(pid as i32), Signal::SIGKILL)
+}
+
+#[cfg(windows)]
+fn send_sigkill(pid: u32) -> Result<(), std::io::Error> {
+    unsafe {
+        let handle = OpenProcess(PROCESS_TERMINATE, FALSE, pid as DWORD);
+        if handle.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+        let result = TerminateProcess(handle, 1);
+        let error = std::io::Error::last_os_error();
+        CloseHandle(handle);
+        if result == 0 {
+            Err(error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 #[cfg(windows)]
 fn send_sigterm(pid: u32) -> Result<(), std::io::Error> {
     // Get the process ID of the child process
@@ -196,23 +1385,31 @@ fn send_sigterm(pid: u32) -> Result<(), std::io::Error> {
         return Ok(());
     }
 }
-pub fn start_manager() -> Arc<Mutex<ManagerState>> {
+/// Starts the manager, and the autostart modules unless `autostart` is false.
+///
+/// Without autostart the caller is expected to call `start_autostart_modules` later, e.g. once
+/// the server port has been decided.
+pub fn start_manager(
+    autostart: bool,
+    server_port: u16,
+    server: Server,
+    frontend: Arc<dyn Frontend>,
+) -> Arc<Mutex<ManagerState>> {
     let (tx, rx) = channel();
-    let state = Arc::new(Mutex::new(ManagerState::new(tx.clone())));
+    let state = Arc::new(Mutex::new(ManagerState::new(
+        tx.clone(),
+        server_port,
+        server,
+        frontend,
+    )));
 
-    // Start the modules
-    let config = get_config();
-    for module_config in config.autostart_modules.iter() {
-        let args = if module_config.args.is_empty() {
-            None
-        } else {
-            // Split args string on whitespace, preserving quoted arguments
-            Some(shell_words::split(&module_config.args).unwrap_or_default())
-        };
-        state
-            .lock()
-            .unwrap()
-            .start_module(&module_config.name, args.as_ref());
+    if state.lock().unwrap().server == Server::Embedded {
+        let moved_state = Arc::clone(&state);
+        crate::server::on_port_moved(move |port| moved_state.lock().unwrap().set_server_port(port));
+    }
+    state.lock().unwrap().recover_orphans();
+    if autostart {
+        state.lock().unwrap().start_autostart_modules();
     }
 
     // populate the tray menu if not yet already done
@@ -221,6 +1418,49 @@ pub fn start_manager() -> Arc<Mutex<ManagerState>> {
         tx.send(ModuleMessage::Init {}).unwrap();
     }
 
+    // The modules may have come from the cache, rediscover them in case they changed
+    let cached_modules = state.lock().unwrap().modules_in_path.clone();
+    thread::spawn(move || {
+        let modules = get_modules_in_path();
+        discovery_cache::save(&modules);
+        if modules != cached_modules {
+            info!("Discovered modules changed since they were cached");
+            let _ = tx.send(ModuleMessage::Discovered { modules });
+        }
+    });
+
+    if !crate::is_headless() {
+        let refresh_tx = state.lock().unwrap().tx.clone();
+        crate::shutdown::spawn("tray refresh", move || {
+            while !crate::shutdown::sleep(TRAY_REFRESH_INTERVAL) {
+                if refresh_tx.send(ModuleMessage::RefreshUptimes {}).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    let reconcile_tx = state.lock().unwrap().tx.clone();
+    crate::shutdown::spawn("reconciliation", move || {
+        // Read on every round, so a reloaded config applies
+        loop {
+            let interval = get_config().defaults.health_check_interval_secs;
+            let wait = match interval {
+                0 => RECONCILE_DISABLED_POLL,
+                secs => Duration::from_secs(secs),
+            };
+            if crate::shutdown::sleep(wait) {
+                return;
+            }
+            if interval == 0 {
+                continue;
+            }
+            if reconcile_tx.send(ModuleMessage::Reconcile {}).is_err() {
+                return;
+            }
+        }
+    });
+
     let state_clone = Arc::clone(&state);
     thread::spawn(move || {
         handle(rx, state_clone);
@@ -228,195 +1468,1609 @@ pub fn start_manager() -> Arc<Mutex<ManagerState>> {
     state
 }
 
-fn handle(rx: Receiver<ModuleMessage>, state: Arc<Mutex<ManagerState>>) {
+/// Stops the running modules and starts them again from the current config, e.g. after
+/// changing the config or updating the modules.
+///
+/// The modules are stopped like by the user, so their exit isn't handled as a crash, and each is
+/// started once its exit was handled. Modules still running after `timeout` are left alone.
+pub fn restart_all_modules(state: &Arc<Mutex<ManagerState>>, timeout: Duration) {
+    let stopping: Vec<(String, u32)> = {
+        let mut state = state.lock().unwrap();
+        let stopping = state
+            .modules_pid
+            .iter()
+            .map(|(name, pid)| (name.clone(), *pid))
+            .collect();
+        state.stop_modules();
+        stopping
+    };
+    if stopping.is_empty() {
+        return;
+    }
+    info!(
+        "Restarting modules: {}",
+        stopping
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let started = Instant::now();
+    let mut state = loop {
+        let state = state.lock().unwrap();
+        let running: Vec<&str> = stopping
+            .iter()
+            .filter(|(name, pid)| state.modules_pid.get(name) == Some(pid))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if running.is_empty() {
+            break state;
+        }
+        if started.elapsed() >= timeout {
+            warn!(
+                "Not restarting modules still running after {timeout:?}: {}",
+                running.join(", ")
+            );
+            break state;
+        }
+        drop(state);
+        thread::sleep(STOP_POLL_INTERVAL);
+    };
+    let config = get_config();
+    for (name, _) in stopping {
+        if state.is_module_running(&name) {
+            continue;
+        }
+        match find_module_config(&config, &name) {
+            Some(module_config) => state.start_module_config(module_config),
+            None => {
+                let stored_args = state.modules_args.get(&name).cloned().flatten();
+                state.start_module(&name, stored_args.as_ref());
+            }
+        }
+    }
+}
+
+/// Returns the time a module gets to exit after being asked to stop, before it is killed
+pub fn stop_timeout() -> Duration {
+    Duration::from_secs(get_config().defaults.stop_timeout_secs)
+}
+
+/// Stops all modules and waits up to `timeout` for their processes to exit.
+///
+/// Takes the shared state rather than `&mut self`, since the handler thread needs the lock to
+/// record the exits. Tells the user if stopping takes longer than `SLOW_STOP_NOTICE`. Returns
+/// `false` if modules were still running at the timeout.
+pub fn stop_modules_and_wait(state: &Arc<Mutex<ManagerState>>, timeout: Duration) -> bool {
+    let started = Instant::now();
+    let (stopping, frontend): (Vec<(String, u32)>, _) = {
+        let mut state = state.lock().unwrap();
+        state.stop_modules();
+        let stopping = state
+            .modules_pid
+            .iter()
+            .map(|(name, pid)| (name.clone(), *pid))
+            .collect();
+        (stopping, Arc::clone(&state.frontend))
+    };
+    let mut noticed = false;
     loop {
-        let msg = rx.recv().unwrap();
-        let state_clone = Arc::clone(&state);
-        let state = &mut state.lock().unwrap();
-        match msg {
-            ModuleMessage::Started { name, pid, args } => {
-                state.started_module(&name, pid, args);
-            }
-            ModuleMessage::Stopped { name, output } => {
-                state.stopped_module(&name);
-                let name_clone = name.clone();
-                if output.status.success() {
-                    info!("Module {name} exited successfully");
-                } else {
-                    error!("Module {name} exited with error status");
-                    thread::spawn(move || {
-                        thread::sleep(Duration::from_secs(1));
-                        let state = &mut state_clone.lock().unwrap();
-                        let restart_count = state
-                            .modules_restart_count
-                            .entry(name_clone.clone())
-                            .or_insert(0);
-                        if *restart_count < 3 {
-                            *restart_count += 1;
-                            // Get the stored arguments for this module
-                            let stored_args =
-                                state.modules_args.get(&name_clone).cloned().flatten();
-                            state.start_module(&name_clone, stored_args.as_ref());
-                            let app = &*get_app_handle().lock().expect("failed to get app handle");
-
-                            app.dialog()
-                                .message(format!("{name_clone} crashed. Restarting..."))
-                                .kind(MessageDialogKind::Error)
-                                .title("Aw-Tauri")
-                                .show(|_| {});
-                            error!("Module {name_clone} crashed and is being restarted");
-                        } else {
-                            let app = &*get_app_handle().lock().expect("failed to get app handle");
-
-                            app.dialog()
-                                .message(format!(
-                                    "{name_clone} keeps on crashing. Restart limit reached."
-                                ))
-                                .kind(MessageDialogKind::Error)
-                                .title("Warning")
-                                .show(|_| {});
-                            error!("Module {name_clone} exceeded crash restart limit");
-                        }
-                    });
+        let running: Vec<&str> = {
+            let state = state.lock().unwrap();
+            stopping
+                .iter()
+                .filter(|(name, pid)| {
+                    state.modules_pid.get(name) == Some(pid) && is_process_alive(*pid, None)
+                })
+                .map(|(name, _)| name.as_str())
+                .collect()
+        };
+        if running.is_empty() {
+            return true;
+        }
+        if started.elapsed() >= timeout {
+            warn!(
+                "Modules still running after {timeout:?}: {}",
+                running.join(", ")
+            );
+            return false;
+        }
+        if !noticed && started.elapsed() >= SLOW_STOP_NOTICE {
+            noticed = true;
+            frontend.notify("Shutting down watchers…".into());
+        }
+        thread::sleep(STOP_POLL_INTERVAL);
+    }
+}
 
-                    debug!(
-                        "Module {name} stdout: {}",
-                        String::from_utf8_lossy(&output.stdout)
-                    );
-                    error!(
-                        "Module {name} stderr: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    );
+/// Returns the pid of a process running `path` that isn't one of `own_pids`, e.g. a watcher
+/// started by aw-qt.
+///
+/// Processes are matched by executable file name like in `is_process_alive`, falling back to
+/// the process name for processes whose executable can't be read.
+fn find_external_instance(path: &Path, own_pids: &BTreeSet<u32>) -> Option<u32> {
+    let file_name = path.file_name()?;
+    let own_pid = std::process::id();
+    let mut system = sysinfo::System::new();
+    system.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
+    system
+        .processes()
+        .iter()
+        .filter(|(pid, process)| {
+            let pid = pid.as_u32();
+            pid != own_pid
+                && !own_pids.contains(&pid)
+                && process.status() != sysinfo::ProcessStatus::Zombie
+                // Threads are listed as processes on Linux
+                && process.thread_kind().is_none()
+                && match process.exe().and_then(Path::file_name) {
+                    Some(exe) => exe == file_name,
+                    None => process.name() == file_name,
                 }
+        })
+        .map(|(pid, _)| pid.as_u32())
+        .min()
+}
+
+/// Formats how long a module has been running for the tray, e.g. `45s`, `2h13m` or `3d4h`
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{days}d{hours}h")
+    } else if hours > 0 {
+        format!("{hours}h{minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Exit status reported for a module whose real one couldn't be read, exit code 1
+fn failed_exit_status() -> std::process::ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        // Unix keeps the exit code in the second byte of the wait status
+        std::process::ExitStatus::from_raw(1 << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        std::process::ExitStatus::from_raw(1)
+    }
+}
+
+/// Returns whether a module exited by itself or was stopped from outside of aw-tauri, e.g. from
+/// its own UI or by systemd, as opposed to crashing.
+///
+/// On Unix, termination by SIGTERM or SIGINT is a clean stop, other signals such as SIGSEGV or
+/// SIGABRT are crashes. On Windows, the exit code of a console program ended by Ctrl+C or
+/// Ctrl+Break is a clean stop. The exit code 1 our own `TerminateProcess` uses can't be told
+/// apart from a failure, so only the stops aw-tauri asked for are clean.
+fn exited_cleanly(status: &std::process::ExitStatus) -> bool {
+    if status.success() {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return signal == Signal::SIGTERM as i32 || signal == Signal::SIGINT as i32;
+        }
+    }
+    #[cfg(windows)]
+    {
+        /// STATUS_CONTROL_C_EXIT
+        const CONTROL_C_EXIT: u32 = 0xC000_013A;
+        if status.code().map(|code| code as u32) == Some(CONTROL_C_EXIT) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns whether `pid` is a live process running `exe`.
+///
+/// Unlike `is_process_alive`, a process whose executable and name can't be read doesn't count,
+/// so an unrelated process that got the pid is never adopted or stopped.
+fn runs_executable(pid: u32, exe: &Path) -> bool {
+    let Some(file_name) = exe.file_name() else {
+        return false;
+    };
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut system = sysinfo::System::new();
+    system.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), true);
+    system.process(pid).is_some_and(|process| {
+        process.status() != sysinfo::ProcessStatus::Zombie
+            && match process.exe().and_then(Path::file_name) {
+                Some(actual) => actual == file_name,
+                None => process.name() == file_name,
             }
-            ModuleMessage::Init {} => state.update_tray_menu(),
+    })
+}
+
+/// Returns whether `pid` is a live process running `path`.
+///
+/// The executable is compared by file name, since a shim or symlink may run it from elsewhere.
+fn is_process_alive(pid: u32, path: Option<&Path>) -> bool {
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut system = sysinfo::System::new();
+    system.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), true);
+    let Some(process) = system.process(pid) else {
+        return false;
+    };
+    if process.status() == sysinfo::ProcessStatus::Zombie {
+        return false;
+    }
+    match (
+        path.and_then(Path::file_name),
+        process.exe().and_then(Path::file_name),
+    ) {
+        (Some(expected), Some(actual)) => expected == actual,
+        // Without both names, e.g. without access to the process, trust the pid
+        _ => true,
+    }
+}
+
+fn handle(rx: Receiver<ModuleMessage>, state: Arc<Mutex<ManagerState>>) {
+    loop {
+        // Wake up for the next due restart, if any
+        let next_restart = state.lock().unwrap().next_restart_due();
+        let msg = match next_restart {
+            Some(due) => match rx.recv_timeout(due.saturating_duration_since(Instant::now())) {
+                Ok(msg) => Some(msg),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            },
+            None => match rx.recv() {
+                Ok(msg) => Some(msg),
+                Err(_) => return,
+            },
+        };
+        let mut guard = state.lock().unwrap();
+        guard.run_due_restarts();
+        if let Some(msg) = msg {
+            handle_message(&mut guard, msg);
         }
     }
 }
 
-fn start_module_thread(
+/// Applies a message to the state
+pub(crate) fn handle_message(state: &mut ManagerState, msg: ModuleMessage) {
+    match msg {
+        ModuleMessage::Started { name, pid, args } => {
+            state.started_module(&name, pid, args);
+        }
+        ModuleMessage::Stopped { name, pid, output } => {
+            // Already handled by reconciliation, or an earlier run reporting late
+            if state.modules_pid.get(&name) != Some(&pid) {
+                debug!("Ignoring the exit of {name} (pid {pid}), it is no longer tracked");
+                return;
+            }
+            // A module that stopped late was still asked to stop
+            let stop_reason = state.modules_pending_shutdown.remove(&name).or_else(|| {
+                state
+                    .modules_stop_failed
+                    .remove(&name)
+                    .then_some(StopReason::Stop)
+            });
+            state.stopped_module(&name);
+            let crashed = stop_reason.is_none() && !exited_cleanly(&output.status);
+            run_hook(
+                if crashed {
+                    HookEvent::ModuleCrashed
+                } else {
+                    HookEvent::ModuleStopped
+                },
+                &[
+                    ("AW_MODULE", name.clone()),
+                    (
+                        "AW_EXIT_CODE",
+                        output
+                            .status
+                            .code()
+                            .map(|code| code.to_string())
+                            .unwrap_or_default(),
+                    ),
+                ],
+            );
+            if stop_reason == Some(StopReason::Stop) {
+                info!("Module {name} was stopped");
+            } else if stop_reason == Some(StopReason::Restart) {
+                info!("Module {name} was stopped, restarting");
+                let stored_args = state.modules_args.get(&name).cloned().flatten();
+                state.start_module(&name, stored_args.as_ref());
+            } else if exited_cleanly(&output.status) {
+                info!("Module {name} exited cleanly ({})", output.status);
+            } else {
+                error!("Module {name} exited with error status");
+                let config = get_config();
+                let defaults = &config.defaults;
+                let stderr = truncate_output(
+                    &defaults.module_output_encoding.decode(&output.stderr),
+                    defaults.crash_output_max_bytes,
+                );
+                state.module_crashed(&name, output.status.code(), stderr.clone());
+                debug!(
+                    "Module {name} output: {}",
+                    truncate_output(
+                        &state.get_module_output(&name).join("\n"),
+                        defaults.crash_output_max_bytes,
+                    )
+                );
+                error!("Module {name} stderr: {stderr}");
+            }
+        }
+        ModuleMessage::Retry { name } => {
+            state.retry_module(&name);
+        }
+        ModuleMessage::Init {} => state.update_tray_menu(),
+        ModuleMessage::RefreshUptimes {} => state.refresh_uptimes(),
+        ModuleMessage::PermissionsGranted { name } => {
+            if let Some(args) = state.modules_waiting_permission.remove(&name) {
+                state.start_module(&name, args.as_ref());
+            }
+        }
+        ModuleMessage::StopTimedOut { name, pid } => state.stop_timed_out(&name, pid),
+        ModuleMessage::Reconcile {} => state.reconcile(is_process_alive),
+        ModuleMessage::AdoptedExited { name, pid } => {
+            if state.modules_pid.get(&name) == Some(&pid) {
+                info!("Adopted module {name} (pid {pid}) exited");
+                state.module_vanished(&name);
+            }
+        }
+        ModuleMessage::ReplaceAdopted { name, pid } => {
+            if state.modules_pid.get(&name) == Some(&pid) {
+                info!("Replacing adopted module {name} (pid {pid})");
+                state.restart_module(&name);
+            }
+        }
+        ModuleMessage::Discovered { modules } => {
+            state.modules_in_path = modules;
+            state.update_tray_menu();
+        }
+        ModuleMessage::SpawnFailed { name } => {
+            state.modules_spawning.remove(&name);
+        }
+    }
+}
+
+/// Keeps the last `max_bytes` of a crashed module's output for the log, since the latest output
+/// is the most relevant
+fn truncate_output(output: &str, max_bytes: usize) -> String {
+    if output.len() <= max_bytes {
+        return output.to_string();
+    }
+    let mut start = output.len() - max_bytes;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("[... {start} bytes truncated ...]{}", &output[start..])
+}
+
+/// Orders the modules so each comes after the listed modules it starts `after`.
+///
+/// Returns the ordered modules and the keys of the modules waiting for each other in a cycle,
+/// which keep their place in the list.
+fn order_modules<'a>(modules: &[&'a ModuleConfig]) -> (Vec<&'a ModuleConfig>, Vec<&'a str>) {
+    let listed = |key: &str| modules.iter().any(|module| module.key() == key);
+    let mut remaining: Vec<&ModuleConfig> = modules.to_vec();
+    let mut ordered = Vec::with_capacity(modules.len());
+    loop {
+        let (ready, waiting): (Vec<&ModuleConfig>, Vec<&ModuleConfig>) =
+            remaining.into_iter().partition(|module| {
+                module.after.iter().all(|after| {
+                    !listed(after)
+                        || ordered
+                            .iter()
+                            .any(|done: &&ModuleConfig| done.key() == after)
+                })
+            });
+        remaining = waiting;
+        if ready.is_empty() {
+            break;
+        }
+        ordered.extend(ready);
+    }
+    let cycle = remaining.iter().map(|module| module.key()).collect();
+    ordered.extend(remaining);
+    (ordered, cycle)
+}
+
+/// Returns the config of a module instance, from the autostart modules or any profile
+fn find_module_config<'a>(config: &'a UserConfig, name: &str) -> Option<&'a ModuleConfig> {
+    config
+        .autostart_modules
+        .iter()
+        .chain(
+            config
+                .profiles
+                .values()
+                .flat_map(|profile| &profile.modules),
+        )
+        .find(|module| module.key() == name)
+}
+
+/// Returns whether the module should run at lowered priority, the module's own setting wins
+fn is_low_priority(config: &UserConfig, name: &str) -> bool {
+    find_module_config(config, name)
+        .and_then(|module| module.low_priority)
+        .unwrap_or(config.defaults.low_priority_modules)
+}
+
+fn emits_notifications(name: &str) -> bool {
+    let config = get_config();
+    find_module_config(&config, name).is_some_and(|module| module.emit_notifications)
+}
+
+/// Returns the configured working directory of a module, `None` to inherit aw-tauri's
+fn module_cwd(name: &str) -> Option<PathBuf> {
+    let config = get_config();
+    let cwd = find_module_config(&config, name)?.cwd.clone()?;
+    if cwd.is_dir() {
+        Some(cwd)
+    } else {
+        error!(
+            "Working directory {} of {name} doesn't exist, using the default",
+            cwd.display()
+        );
+        None
+    }
+}
+
+/// Niceness of modules started with low priority
+#[cfg(unix)]
+const LOW_PRIORITY_NICENESS: libc::c_int = 10;
+
+#[cfg(unix)]
+fn set_low_priority(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    // SAFETY: setpriority is async-signal-safe, and only affects the forked child
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, LOW_PRIORITY_NICENESS) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+fn set_low_priority(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    use winapi::um::winbase::BELOW_NORMAL_PRIORITY_CLASS;
+    command.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+}
+
+/// Runs a module in a task on the async runtime, reporting its start and exit to the manager.
+///
+/// With `output_only`, the module is aw-notify started with `--output-only`.
+#[allow(clippy::too_many_arguments)]
+fn start_module_task(
     name: String,
     path: PathBuf,
     custom_args: Option<Vec<String>>,
+    output_only: bool,
+    emit_notifications: bool,
+    low_priority: bool,
+    cwd: Option<PathBuf>,
+    default_args: Vec<String>,
     tx: Sender<ModuleMessage>,
+    output_buffer: Arc<Mutex<ModuleOutput>>,
+    frontend: Arc<dyn Frontend>,
 ) {
-    thread::spawn(move || {
-        // Start the child process
-        let port_string = get_config().defaults.port.to_string();
+    tauri::async_runtime::spawn(async move {
         let mut command = Command::new(&path);
 
-        // Use custom args if provided, otherwise use default port arg
-        if let Some(ref args) = custom_args {
-            command.args(args);
-        } else {
-            command.args(["--port", port_string.as_str()]);
+        // Use custom args if provided, otherwise point the module at the server
+        let args = custom_args.as_ref().unwrap_or(&default_args);
+        command.args(args);
+        if output_only && !args.iter().any(|arg| arg == "--output-only") {
+            command.arg("--output-only");
         }
-
-        let child = command.stdout(std::process::Stdio::piped()).spawn();
-
-        if let Err(e) = child {
-            error!("Failed to start module {name}: {e}");
-            return;
+        if low_priority {
+            set_low_priority(&mut command);
+        }
+        if let Some(cwd) = cwd {
+            command.current_dir(cwd);
         }
+        command.stdout(std::process::Stdio::piped());
+        command.stderr(std::process::Stdio::piped());
+
+        let mut child = match tokio::process::Command::from(command).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to start module {name}: {e}");
+                let _ = tx.send(ModuleMessage::SpawnFailed { name });
+                return;
+            }
+        };
 
         // Send a message to the manager that the module has started
+        let pid = child.id().unwrap_or_default();
         tx.send(ModuleMessage::Started {
             name: name.to_string(),
-            pid: child.as_ref().unwrap().id(),
+            pid,
             args: custom_args,
         })
         .unwrap();
 
-        // Wait for the child to exit
-        let output = child
-            .unwrap()
-            .wait_with_output()
-            .expect("failed to wait on child");
+        // Read both streams while the module runs so it can't block on a full pipe, they end
+        // when the module exits
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let module_log = Mutex::new(crate::logging::ModuleLog::open(&name));
+        let mut notifications = NotificationBlocks::default();
+        let (stdout, stderr) = tokio::join!(
+            read_output(
+                &name,
+                stdout,
+                "stdout",
+                &output_buffer,
+                &module_log,
+                emit_notifications.then_some((&mut notifications, &*frontend))
+            ),
+            read_output(&name, stderr, "stderr", &output_buffer, &module_log, None),
+        );
+
+        // The module is gone either way, report it as failed so it isn't tracked forever
+        let status = child.wait().await.unwrap_or_else(|e| {
+            error!("Failed to wait on module {name} (pid {pid}): {e}");
+            failed_exit_status()
+        });
 
         // Send the process output to the manager
         tx.send(ModuleMessage::Stopped {
             name: name.to_string(),
-            output,
+            pid,
+            output: std::process::Output {
+                status,
+                stdout,
+                stderr,
+            },
         })
         .unwrap();
     });
 }
 
-#[cfg(unix)]
-fn get_modules_in_path() -> BTreeMap<String, PathBuf> {
-    let excluded = ["awk", "aw-tauri", "aw-client", "aw-cli", "aw-qt"];
-    let config = crate::get_config();
+/// Line separating the notifications a module writes to stdout
+const NOTIFICATION_DELIMITER: &str = "--------------------------------------------------";
+/// Lines kept of a notification, the ones after being dropped
+const NOTIFICATION_MAX_LINES: usize = 50;
 
-    let path = env::var_os("PATH").unwrap_or_default();
-    let mut paths = env::split_paths(&path).collect::<Vec<_>>();
+/// Collects the lines a module writes between `NOTIFICATION_DELIMITER` lines into notifications
+#[derive(Debug, Default)]
+struct NotificationBlocks {
+    lines: VecDeque<String>,
+}
 
-    if !paths.contains(&config.defaults.discovery_path) {
-        // add to the front of the path list
-        paths.insert(0, config.defaults.discovery_path.to_owned());
+impl NotificationBlocks {
+    /// Adds a line of output, returning the finished notification at a delimiter
+    fn push(&mut self, line: &str) -> Option<Notification> {
+        if line.trim() == NOTIFICATION_DELIMITER {
+            let lines: Vec<String> = self.lines.drain(..).collect();
+            return parse_notification(&lines);
+        }
+        // Keeping the first lines keeps the title
+        if self.lines.len() < NOTIFICATION_MAX_LINES {
+            self.lines.push_back(line.to_string());
+        }
+        None
     }
+}
 
-    // Create new PATH-like string
-    let new_paths = env::join_paths(paths).unwrap_or_default();
+/// Turns the lines of a block into a notification, `None` for a blank block.
+///
+/// The first non-empty line is the title and the rest the body. A single line is the body, shown
+/// under the app's name.
+fn parse_notification(lines: &[String]) -> Option<Notification> {
+    let mut lines = lines
+        .iter()
+        .map(|line| line.trim_end())
+        .skip_while(|line| line.trim().is_empty());
+    let first = lines.next()?.trim().to_string();
+    let body = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+    if body.is_empty() {
+        Some(first.into())
+    } else {
+        Some(Notification {
+            title: Some(first),
+            body,
+        })
+    }
+}
 
-    env::split_paths(&new_paths)
-        .flat_map(|path| fs::read_dir(path).ok())
-        .flatten()
-        .filter_map(Result::ok)
-        .filter_map(|entry| {
-            let metadata = entry.metadata().ok()?;
-            let is_executable = (metadata.is_file() || metadata.is_symlink())
-                && metadata.permissions().mode() & 0o111 != 0;
-            if !is_executable {
-                return None;
+/// Reads a stream of a module into its output buffer and log until it closes, showing the
+/// notifications in it if `notifications` is given.
+///
+/// Returns the last `OUTPUT_MAX_BYTES` read, for the crash log.
+async fn read_output(
+    name: &str,
+    stream: Option<impl AsyncRead + Unpin>,
+    stream_name: &str,
+    output_buffer: &Mutex<ModuleOutput>,
+    module_log: &Mutex<crate::logging::ModuleLog>,
+    mut notifications: Option<(&mut NotificationBlocks, &dyn Frontend)>,
+) -> Vec<u8> {
+    let mut tail = Vec::new();
+    let Some(stream) = stream else {
+        return tail;
+    };
+    let mut reader = tokio::io::BufReader::new(stream);
+    let encoding = get_config().defaults.module_output_encoding;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) => break,
+            Ok(_) => {
+                let line = encoding.decode(&buf).trim_end().to_string();
+                module_log.lock().unwrap().write_line(stream_name, &line);
+                if let Some((blocks, frontend)) = notifications.as_mut() {
+                    if let Some(notification) = blocks.push(&line) {
+                        frontend.notify(notification);
+                    }
+                }
+                output_buffer.lock().unwrap().push(name, line);
+                tail.extend_from_slice(&buf);
+                if tail.len() > OUTPUT_MAX_BYTES {
+                    tail.drain(..tail.len() - OUTPUT_MAX_BYTES);
+                }
             }
-
-            let path = entry.path();
-            let name = entry.file_name().to_str()?.to_string();
-            if name.starts_with("aw") && !name.contains(".") && !excluded.contains(&name.as_str()) {
-                Some((name, path))
-            } else {
-                None
+            Err(e) => {
+                debug!("Failed to read output of module {name}: {e}");
+                break;
             }
-        })
-        .collect()
+        }
+    }
+    tail
+}
+
+/// Names that are never discovered as modules
+const EXCLUDED_MODULES: &[&str] = &["awk", "aw-tauri", "aw-client", "aw-cli", "aw-qt"];
+
+/// Compiles the configured `executable_patterns`, skipping invalid ones
+fn executable_patterns() -> Option<Vec<glob::Pattern>> {
+    let patterns = get_config().defaults.executable_patterns.clone()?;
+    Some(
+        patterns
+            .iter()
+            .filter_map(|pattern| match glob::Pattern::new(pattern) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    error!("Ignoring invalid executable pattern {pattern}: {e}");
+                    None
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Returns the module name of an executable, or `None` if it isn't a module
+fn module_name(file_name: &str, patterns: Option<&[glob::Pattern]>) -> Option<String> {
+    let matches = match patterns {
+        Some(patterns) => patterns.iter().any(|pattern| pattern.matches(file_name)),
+        None if cfg!(windows) => file_name.to_ascii_lowercase().ends_with(".exe"),
+        None => file_name.starts_with("aw") && !file_name.contains('.'),
+    };
+    if !matches {
+        return None;
+    }
+    let name = if cfg!(windows) && file_name.to_ascii_lowercase().ends_with(".exe") {
+        &file_name[..file_name.len() - ".exe".len()]
+    } else {
+        file_name
+    };
+    (!EXCLUDED_MODULES.contains(&name)).then(|| name.to_string())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    // Follows symlinks, a dangling one isn't a module
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
 }
 
 #[cfg(windows)]
-fn get_modules_in_path() -> BTreeMap<String, PathBuf> {
-    let excluded = ["aw-tauri", "aw-client", "aw-cli", "aw-qt"];
+fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file(),
+        // App execution aliases are reparse points that can't be followed but can be run
+        Err(_) => fs::symlink_metadata(path).is_ok_and(|metadata| !metadata.is_dir()),
+    }
+}
+
+/// Returns a key identifying a folder, so a folder listed twice, reached through a junction or
+/// spelled with another case on Windows is only searched once
+fn folder_key(path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if cfg!(windows) {
+        PathBuf::from(path.to_string_lossy().to_lowercase())
+    } else {
+        path
+    }
+}
 
-    // Get the discovery path from config
-    let config = crate::get_config();
+/// Returns the folder scoop puts the shims of installed apps in
+#[cfg(windows)]
+fn scoop_shims_dir() -> PathBuf {
+    env::var_os("SCOOP")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| crate::dirs::home_dir().join("scoop"))
+        .join("shims")
+}
+
+/// File in the app data dir listing the modules the user stopped
+const USER_STOPPED_FILE: &str = "stopped-modules.json";
+
+fn user_stopped_path() -> PathBuf {
+    crate::dirs::app_data_dir().join(USER_STOPPED_FILE)
+}
+
+fn load_user_stopped(path: &Path) -> BTreeSet<String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_user_stopped(path: &Path, modules: &BTreeSet<String>) {
+    let result = crate::dirs::create_dir_with_retry(path.parent().expect("no parent"))
+        .map_err(|e| e.to_string())
+        .and_then(|_| serde_json::to_string(modules).map_err(|e| e.to_string()))
+        .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        error!("Failed to save the stopped modules: {e}");
+    }
+}
+
+pub(crate) fn get_modules_in_path() -> BTreeMap<String, PathBuf> {
+    let config = get_config();
+    let patterns = executable_patterns();
 
-    // Get the current PATH
     let path = env::var_os("PATH").unwrap_or_default();
     let mut paths = env::split_paths(&path).collect::<Vec<_>>();
 
-    // Add discovery path if not already in PATH
     if !paths.contains(&config.defaults.discovery_path) {
+        // add to the front of the path list
         paths.insert(0, config.defaults.discovery_path.to_owned());
     }
 
-    // Create new PATH-like string
-    let new_paths = env::join_paths(paths).unwrap_or_default();
+    #[cfg(windows)]
+    paths.push(scoop_shims_dir());
+
+    let mut visited = BTreeSet::new();
+    paths.retain(|path| visited.insert(folder_key(path)));
 
-    // Use the combined paths to find modules
-    env::split_paths(&new_paths)
+    paths
+        .iter()
         .flat_map(|path| fs::read_dir(path).ok())
         .flatten()
         .filter_map(Result::ok)
         .filter_map(|entry| {
-            let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "exe") {
-                let name = path.file_stem()?.to_str()?.to_string();
-                if !excluded.contains(&name.as_str()) {
-                    Some((name, path))
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
+            let name = module_name(entry.file_name().to_str()?, patterns.as_deref())?;
+            is_executable(&entry.path()).then(|| (name, entry.path()))
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Harness;
+
+    #[test]
+    fn modules_get_the_port_in_use() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        let port = get_config().defaults.port + 1;
+        state.server_port = port;
+        assert_eq!(
+            state.default_module_args(),
+            ["--port".to_string(), port.to_string()]
+        );
+    }
+
+    /// Sets up a module that started `uptime` ago and restarted `restart_count` times
+    fn crashing_module(state: &mut ManagerState, uptime: Duration, restart_count: u32) -> bool {
+        let Some(started_at) = Instant::now().checked_sub(uptime) else {
+            // The clock starts at boot on some platforms
+            return false;
+        };
+        state
+            .modules_started_at
+            .insert("aw-mock-crash".to_string(), started_at);
+        state
+            .modules_restart_count
+            .insert("aw-mock-crash".to_string(), restart_count);
+        true
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn multi_line_block_has_a_title() {
+        assert_eq!(
+            parse_notification(&lines(
+                "\nTime goal reached\n4h of coding today\nKeep going\n"
+            )),
+            Some(Notification {
+                title: Some("Time goal reached".to_string()),
+                body: "4h of coding today\nKeep going".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn single_line_block_is_the_body() {
+        assert_eq!(
+            parse_notification(&lines("  Take a break  \n\n")),
+            Some("Take a break".into())
+        );
+    }
+
+    #[test]
+    fn empty_block_is_not_shown() {
+        assert_eq!(parse_notification(&[]), None);
+        assert_eq!(parse_notification(&lines("\n   \n")), None);
+    }
+
+    #[test]
+    fn blocks_are_split_at_the_delimiter() {
+        let mut blocks = NotificationBlocks::default();
+        let shown: Vec<Notification> = [
+            NOTIFICATION_DELIMITER,
+            "Time goal reached",
+            "4h of coding today",
+            NOTIFICATION_DELIMITER,
+            NOTIFICATION_DELIMITER,
+            "Take a break",
+            NOTIFICATION_DELIMITER,
+            "unfinished",
+        ]
+        .into_iter()
+        .filter_map(|line| blocks.push(line))
+        .collect();
+        assert_eq!(
+            shown,
+            [
+                Notification {
+                    title: Some("Time goal reached".to_string()),
+                    body: "4h of coding today".to_string(),
+                },
+                "Take a break".into(),
+            ]
+        );
+    }
+
+    fn module(name: &str, after: &[&str]) -> ModuleConfig {
+        ModuleConfig {
+            name: name.to_string(),
+            id: None,
+            args: String::new(),
+            low_priority: None,
+            cwd: None,
+            after: after.iter().map(|after| after.to_string()).collect(),
+            emit_notifications: false,
+        }
+    }
+
+    fn keys<'a>(modules: &[&'a ModuleConfig]) -> Vec<&'a str> {
+        modules.iter().map(|module| module.key()).collect()
+    }
+
+    #[test]
+    fn modules_come_after_the_modules_they_wait_for() {
+        let modules = [
+            module("aw-watcher-window", &["aw-watcher-afk"]),
+            module("aw-sync", &["aw-watcher-window", "aw-watcher-afk"]),
+            module("aw-watcher-afk", &[]),
+        ];
+        let (ordered, cycle) = order_modules(&modules.iter().collect::<Vec<_>>());
+        assert_eq!(
+            keys(&ordered),
+            ["aw-watcher-afk", "aw-watcher-window", "aw-sync"]
+        );
+        assert!(cycle.is_empty());
+    }
+
+    #[test]
+    fn order_is_kept_without_dependencies() {
+        let modules = [
+            module("aw-watcher-window", &[]),
+            // Waiting for a module that isn't listed doesn't hold it back
+            module("aw-watcher-input", &["aw-watcher-missing"]),
+            module("aw-watcher-afk", &[]),
+        ];
+        let (ordered, cycle) = order_modules(&modules.iter().collect::<Vec<_>>());
+        assert_eq!(
+            keys(&ordered),
+            ["aw-watcher-window", "aw-watcher-input", "aw-watcher-afk"]
+        );
+        assert!(cycle.is_empty());
+    }
+
+    #[test]
+    fn modules_in_a_cycle_are_reported_and_kept() {
+        let modules = [
+            module("aw-watcher-window", &["aw-sync"]),
+            module("aw-watcher-afk", &[]),
+            module("aw-sync", &["aw-watcher-window"]),
+            module("aw-watcher-input", &["aw-watcher-afk"]),
+        ];
+        let (ordered, cycle) = order_modules(&modules.iter().collect::<Vec<_>>());
+        assert_eq!(
+            keys(&ordered),
+            [
+                "aw-watcher-afk",
+                "aw-watcher-input",
+                "aw-watcher-window",
+                "aw-sync"
+            ]
+        );
+        assert_eq!(cycle, ["aw-watcher-window", "aw-sync"]);
+    }
+
+    #[test]
+    fn module_waiting_for_itself_is_a_cycle() {
+        let modules = [module("aw-watcher-afk", &["aw-watcher-afk"])];
+        let (ordered, cycle) = order_modules(&modules.iter().collect::<Vec<_>>());
+        assert_eq!(keys(&ordered), ["aw-watcher-afk"]);
+        assert_eq!(cycle, ["aw-watcher-afk"]);
+    }
+
+    #[test]
+    fn crash_after_a_stable_run_resets_the_restart_count() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        let limit = get_config().restart.limit;
+        let stable = Duration::from_secs(get_config().restart.stable_secs);
+        if !crashing_module(&mut state, stable + Duration::from_secs(1), limit) {
+            return;
+        }
+        assert!(state
+            .module_crashed("aw-mock-crash", Some(3), String::new())
+            .is_restarting());
+        assert_eq!(state.modules_restart_count.get("aw-mock-crash"), Some(&0));
+        assert!(state.pending_restarts.contains_key("aw-mock-crash"));
+    }
+
+    #[test]
+    fn crash_soon_after_starting_counts_against_the_limit() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        let limit = get_config().restart.limit;
+        assert!(crashing_module(&mut state, Duration::ZERO, limit - 1));
+        assert!(state
+            .module_crashed("aw-mock-crash", Some(3), String::new())
+            .is_restarting());
+        assert_eq!(
+            state.modules_restart_count.get("aw-mock-crash"),
+            Some(&limit)
+        );
+        assert_eq!(
+            state.module_crashed("aw-mock-crash", Some(3), String::new()),
+            CrashOutcome::LimitReached
+        );
+    }
+
+    /// Processes of the reconcile tests, by pid
+    fn processes(state: &ManagerState) -> HashMap<u32, PathBuf> {
+        HashMap::from([
+            (101, PathBuf::from("/usr/bin/something-else")),
+            (102, state.modules_in_path["aw-mock-running"].clone()),
+        ])
+    }
+
+    #[test]
+    fn reconcile_drops_dead_and_reused_pids() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        // Gone, its pid reused by another program, and still running
+        for (name, pid) in [
+            ("aw-mock-clean", 100),
+            ("aw-mock-crash", 101),
+            ("aw-mock-running", 102),
+        ] {
+            state.modules_pid.insert(name.to_string(), pid);
+            state.modules_running.insert(name.to_string(), true);
+        }
+        let processes = processes(&state);
+        let is_alive = |pid: u32, exe: Option<&Path>| {
+            processes
+                .get(&pid)
+                .is_some_and(|running| exe.is_none_or(|exe| exe == running))
+        };
+
+        // A process missing once may still be starting or exiting
+        state.reconcile(is_alive);
+        assert_eq!(state.modules_pid.len(), 3);
+        assert_eq!(
+            state.modules_vanishing,
+            BTreeSet::from(["aw-mock-clean".to_string(), "aw-mock-crash".to_string()])
+        );
+
+        state.reconcile(is_alive);
+        assert_eq!(
+            state.modules_pid,
+            HashMap::from([("aw-mock-running".to_string(), 102)])
+        );
+        assert_eq!(state.modules_running.get("aw-mock-clean"), Some(&false));
+        assert_eq!(state.modules_running.get("aw-mock-crash"), Some(&false));
+        assert!(state.modules_vanishing.is_empty());
+    }
+
+    #[test]
+    fn reconcile_keeps_live_pids() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        state.modules_pid.insert("aw-mock-running".to_string(), 102);
+        let processes = processes(&state);
+        for _ in 0..3 {
+            state.reconcile(|pid, exe| {
+                processes
+                    .get(&pid)
+                    .is_some_and(|running| exe.is_none_or(|exe| exe == running))
+            });
+        }
+        assert_eq!(state.modules_pid.get("aw-mock-running"), Some(&102));
+        assert!(state.modules_vanishing.is_empty());
+    }
+
+    #[test]
+    fn user_stopped_module_stays_stopped_after_a_restart() {
+        let harness = Harness::new();
+        let file = {
+            let mut state = harness.state.lock().unwrap();
+            state.user_stop_module("aw-mock-notify").unwrap();
+            state.user_stopped_file.clone()
+        };
+
+        let rebuilt = Harness::new();
+        let mut state = rebuilt.state.lock().unwrap();
+        assert!(state.modules_user_stopped.is_empty());
+        state.use_user_stopped_file(file);
+        assert!(state.modules_user_stopped.contains("aw-mock-notify"));
+        state.start_autostart_modules();
+        assert!(!state.modules_spawning.contains("aw-mock-notify"));
+        assert!(!state.is_module_running("aw-mock-notify"));
+    }
+
+    #[test]
+    fn module_priority_falls_back_to_the_default() {
+        let mut config = UserConfig::default();
+        let mut lowered = module("aw-watcher-afk", &[]);
+        lowered.low_priority = Some(true);
+        let mut normal = module("aw-watcher-window", &[]);
+        normal.low_priority = Some(false);
+        config.autostart_modules = vec![lowered, normal, module("aw-watcher-input", &[])];
+
+        for default in [false, true] {
+            config.defaults.low_priority_modules = default;
+            assert!(is_low_priority(&config, "aw-watcher-afk"));
+            assert!(!is_low_priority(&config, "aw-watcher-window"));
+            assert_eq!(is_low_priority(&config, "aw-watcher-input"), default);
+            assert_eq!(is_low_priority(&config, "aw-unknown"), default);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn low_priority_lowers_the_niceness_of_the_child() {
+        // SAFETY: getpriority has no preconditions
+        let own = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        if own > LOW_PRIORITY_NICENESS {
+            // Only root could raise the priority back up to it
+            return;
+        }
+        let mut command = Command::new("nice");
+        set_low_priority(&mut command);
+        let output = command.output().expect("failed to run nice");
+        let niceness: libc::c_int = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .expect("nice printed no niceness");
+        assert_eq!(niceness, LOW_PRIORITY_NICENESS);
+    }
+
+    #[test]
+    fn uptimes_are_refreshed_only_for_running_modules() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        state.refresh_uptimes();
+        assert!(harness.frontend.labels.lock().unwrap().is_empty());
+
+        let Some(started_at) = Instant::now().checked_sub(Duration::from_secs(2 * 3600)) else {
+            return;
+        };
+        for (module, running) in [("aw-mock-running", true), ("aw-mock-crash", false)] {
+            state.modules_running.insert(module.to_string(), running);
+            state.modules_pid.insert(module.to_string(), 42);
+            state
+                .modules_started_at
+                .insert(module.to_string(), started_at);
+        }
+        state.refresh_uptimes();
+        assert_eq!(
+            *harness.frontend.labels.lock().unwrap(),
+            [vec![(
+                "aw-mock-running".to_string(),
+                "aw-mock-running (pid 42, up 2h0m)".to_string()
+            )]]
+        );
+    }
+}
+
+/// Scenarios running the mock modules in `tests/mock-modules`
+#[cfg(all(test, unix))]
+mod scenarios {
+    use super::*;
+    use crate::test_support::{Harness, Shown, TEST_PORT};
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn stop(harness: &mut Harness, name: &str) {
+        harness.state.lock().unwrap().stop_module(name);
+        assert!(harness.run_until(TIMEOUT, |state| !state.is_module_running(name)));
+    }
+
+    /// Returns the pid of a process that already exited, which can't be killed
+    fn exited_pid() -> u32 {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        child.id()
+    }
+
+    #[test]
+    fn reset_keeps_the_server_running() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        let mut server = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let mut module = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        state
+            .modules_pid
+            .insert(SERVER_MODULE.to_string(), server.id());
+        state
+            .modules_pid
+            .insert("aw-mock-running".to_string(), module.id());
+        state
+            .modules_user_stopped
+            .insert("aw-mock-clean".to_string());
+        state.active_profile = Some("work".to_string());
+
+        let config = UserConfig {
+            autostart_modules: Vec::new(),
+            ..UserConfig::default()
+        };
+        state.reset_modules(&config);
+        assert!(state
+            .modules_pending_shutdown
+            .contains_key("aw-mock-running"));
+        assert!(!state.modules_pending_shutdown.contains_key(SERVER_MODULE));
+        assert!(state.modules_user_stopped.is_empty());
+        assert!(load_user_stopped(&state.user_stopped_file).is_empty());
+        assert_eq!(state.active_profile, None);
+
+        let _ = server.kill();
+        let _ = module.kill();
+        let _ = server.wait();
+        let _ = module.wait();
+    }
+
+    #[test]
+    fn stale_stop_timeout_is_ignored() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        let pid = exited_pid();
+        state.modules_pid.insert("aw-mock-running".to_string(), pid);
+        // Started again since the stop request
+        state
+            .modules_pending_shutdown
+            .insert("aw-mock-running".to_string(), StopReason::Stop);
+        state.stop_timed_out("aw-mock-running", pid + 1);
+        // Stopped in time
+        state.modules_pending_shutdown.clear();
+        state.stop_timed_out("aw-mock-running", pid);
+        assert!(state.modules_stop_failed.is_empty());
+        assert!(harness.frontend.shown().is_empty());
+    }
+
+    #[test]
+    fn module_that_cannot_be_killed_is_reported() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        let pid = exited_pid();
+        state.modules_pid.insert("aw-mock-running".to_string(), pid);
+        state
+            .modules_pending_shutdown
+            .insert("aw-mock-running".to_string(), StopReason::Stop);
+        state.stop_timed_out("aw-mock-running", pid);
+        assert!(state.modules_stop_failed.contains("aw-mock-running"));
+        assert!(state.modules_pending_shutdown.is_empty());
+        assert_eq!(
+            harness.frontend.shown(),
+            [Shown::Notification(
+                format!(
+                    "aw-mock-running (pid {pid}) did not stop. Use Force stop in the tray or end \
+                    it from your task manager."
+                )
+                .into()
+            )]
+        );
+    }
+
+    #[test]
+    fn external_instance_is_found_by_its_executable() {
+        // A copy of sleep with a module's name, the mock modules all run as the shell
+        let dir = std::env::temp_dir().join(format!("aw-tauri-fake-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fake = dir.join("aw-fake-watcher");
+        let sleep = ["/bin/sleep", "/usr/bin/sleep"]
+            .into_iter()
+            .find(|path| Path::new(path).exists())
+            .expect("sleep not found");
+        std::fs::copy(sleep, &fake).unwrap();
+        let mut child = std::process::Command::new(&fake).arg("30").spawn().unwrap();
+        let pid = child.id();
+
+        assert_eq!(find_external_instance(&fake, &BTreeSet::new()), Some(pid));
+        // Not someone else's once it is ours
+        assert_eq!(find_external_instance(&fake, &BTreeSet::from([pid])), None);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(find_external_instance(&fake, &BTreeSet::new()), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_the_first_start_looks_for_external_instances() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        assert!(state.should_scan("aw-mock-running"));
+        assert!(!state.should_scan("aw-mock-running"));
+    }
+
+    #[test]
+    fn no_look_while_another_instance_is_ours() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        for instance in ["keyboard", "mouse"] {
+            state
+                .modules_binary
+                .insert(instance.to_string(), "aw-mock-running".to_string());
+        }
+        state.modules_spawning.insert("keyboard".to_string());
+        assert!(!state.should_scan("mouse"));
+        state.modules_spawning.clear();
+        state.modules_pid.insert("keyboard".to_string(), 1);
+        assert!(!state.should_scan("aw-mock-running"));
+    }
+
+    #[test]
+    fn module_starting_is_not_started_twice() {
+        let mut harness = Harness::new();
+        {
+            let mut state = harness.state.lock().unwrap();
+            state.start_module("aw-mock-running", None);
+            state.start_module("aw-mock-running", None);
+        }
+        assert!(harness.run_until(TIMEOUT, |state| state.is_module_running("aw-mock-running")));
+        assert!(harness.state.lock().unwrap().modules_spawning.is_empty());
+        stop(&mut harness, "aw-mock-running");
+        assert_eq!(harness.messages, ["Started", "Stopped"]);
+    }
+
+    #[test]
+    fn module_exiting_cleanly_is_not_restarted() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-clean", None);
+        assert!(
+            harness.run_until(TIMEOUT, |state| state.modules_running.get("aw-mock-clean")
+                == Some(&false))
+        );
+        assert_eq!(harness.messages, ["Started", "Stopped"]);
+        let state = harness.state.lock().unwrap();
+        assert!(state.pending_restarts.is_empty());
+        assert!(!state.modules_restart_count.contains_key("aw-mock-clean"));
+        assert!(harness.frontend.shown().is_empty());
+    }
+
+    #[test]
+    fn module_flooding_its_output_exits_and_is_reaped() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-flood", None);
+        // Blocks on a full pipe if the output isn't read while it runs
+        assert!(
+            harness.run_until(TIMEOUT, |state| state.modules_running.get("aw-mock-flood")
+                == Some(&false))
+        );
+        assert_eq!(harness.messages, ["Started", "Stopped"]);
+        let state = harness.state.lock().unwrap();
+        assert!(!state.modules_pid.contains_key("aw-mock-flood"));
+        assert!(state.pending_restarts.is_empty());
+        assert!(harness.frontend.shown().is_empty());
+    }
+
+    #[test]
+    fn stopping_a_crashed_module_cancels_its_restart() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-crash", None);
+        // Handled by hand, `run_until` would run the restart as soon as it is due
+        for _ in 0..2 {
+            let msg = harness.rx.recv_timeout(TIMEOUT).unwrap();
+            handle_message(&mut harness.state.lock().unwrap(), msg);
+        }
+        {
+            let mut state = harness.state.lock().unwrap();
+            assert!(state.pending_restarts.contains_key("aw-mock-crash"));
+            state.stop_module("aw-mock-crash");
+            assert!(state.pending_restarts.is_empty());
+        }
+        assert!(!harness.run_until(Duration::from_millis(500), |state| state
+            .modules_running
+            .get("aw-mock-crash")
+            == Some(&true)));
+        assert!(harness.messages.is_empty());
+        assert_eq!(
+            harness.frontend.shown(),
+            [Shown::Crashed {
+                name: "aw-mock-crash".to_string(),
+                outcome: CrashOutcome::Restarting,
+            }]
+        );
+    }
+
+    #[test]
+    fn concurrent_starts_and_stops_leave_no_process_behind() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        const THREADS: usize = 4;
+        let mut harness = Harness::new();
+        let finished = Arc::new(AtomicUsize::new(0));
+        for thread in 0..THREADS {
+            let state = Arc::clone(&harness.state);
+            let finished = Arc::clone(&finished);
+            std::thread::spawn(move || {
+                for i in 0..20 {
+                    let name = ["aw-mock-running", "aw-mock-clean"][(thread + i) % 2];
+                    let mut state = state.lock().unwrap();
+                    if i % 3 == 0 {
+                        state.stop_module(name);
+                    } else {
+                        state.start_module(name, None);
+                    }
+                    drop(state);
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                finished.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        // The manager handles the messages while the threads race
+        assert!(
+            harness.run_until(TIMEOUT, |state| finished.load(Ordering::SeqCst) == THREADS
+                && state.modules_spawning.is_empty())
+        );
+        for name in ["aw-mock-running", "aw-mock-clean"] {
+            stop(&mut harness, name);
+        }
+        assert!(
+            harness.run_until(TIMEOUT, |state| state.modules_pid.is_empty()
+                && state.modules_spawning.is_empty())
+        );
+        // Each started process was reported stopped
+        let started = harness.messages.iter().filter(|m| **m == "Started").count();
+        let stopped = harness.messages.iter().filter(|m| **m == "Stopped").count();
+        assert_eq!(started, stopped);
+    }
+
+    #[test]
+    fn switching_profiles_applies_the_module_diff() {
+        let harness = Harness::new();
+        let mut state = harness.state.lock().unwrap();
+        let mut processes = Vec::new();
+        for name in [SERVER_MODULE, "aw-mock-running", "aw-mock-crash"] {
+            let process = std::process::Command::new("sleep")
+                .arg("30")
+                .spawn()
+                .unwrap();
+            state.modules_pid.insert(name.to_string(), process.id());
+            state.modules_running.insert(name.to_string(), true);
+            processes.push(process);
+        }
+
+        let profile: Vec<ModuleConfig> = ["aw-mock-crash", "aw-mock-clean"]
+            .iter()
+            .map(|name| toml::from_str(&format!("name = \"{name}\"")).unwrap())
+            .collect();
+        state.run_profile_modules(&profile);
+        // Left out of the profile
+        assert!(state
+            .modules_pending_shutdown
+            .contains_key("aw-mock-running"));
+        // Kept, the server always
+        assert!(!state.modules_pending_shutdown.contains_key("aw-mock-crash"));
+        assert!(!state.modules_pending_shutdown.contains_key(SERVER_MODULE));
+        assert!(!state.modules_spawning.contains("aw-mock-crash"));
+        // Added
+        assert!(state.modules_spawning.contains("aw-mock-clean"));
+
+        for mut process in processes {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+
+    #[test]
+    fn stopped_module_is_not_handled_as_crashed() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-running", None);
+        assert!(harness.run_until(TIMEOUT, |state| state.is_module_running("aw-mock-running")));
+        stop(&mut harness, "aw-mock-running");
+        assert_eq!(harness.messages, ["Started", "Stopped"]);
+        let state = harness.state.lock().unwrap();
+        assert!(state.modules_pending_shutdown.is_empty());
+        assert!(state.pending_restarts.is_empty());
+        assert!(harness.frontend.shown().is_empty());
+    }
+
+    #[test]
+    fn crashing_module_is_restarted_until_the_limit() {
+        let mut harness = Harness::new();
+        let args = vec!["0.2".to_string()];
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-crash", Some(&args));
+        let frontend = Arc::clone(&harness.frontend);
+        assert!(
+            harness.run_until(TIMEOUT, |_| frontend.shown().contains(&Shown::Crashed {
+                name: "aw-mock-crash".to_string(),
+                outcome: CrashOutcome::LimitReached,
+            }))
+        );
+        // Started once and restarted twice, the limit of the test config
+        assert_eq!(
+            harness.messages,
+            ["Started", "Stopped", "Started", "Stopped", "Started", "Stopped"]
+        );
+        let state = harness.state.lock().unwrap();
+        assert_eq!(state.modules_restart_count.get("aw-mock-crash"), Some(&2));
+        assert!(state.pending_restarts.is_empty());
+        // The restarts kept the arguments
+        assert_eq!(state.modules_args.get("aw-mock-crash"), Some(&Some(args)));
+        // Told once about each crash
+        let crashes: Vec<_> = harness
+            .frontend
+            .shown()
+            .into_iter()
+            .filter_map(|shown| match shown {
+                Shown::Crashed { outcome, .. } => Some(outcome),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            crashes,
+            [
+                CrashOutcome::Restarting,
+                CrashOutcome::Restarting,
+                CrashOutcome::LimitReached
+            ]
+        );
+        assert_eq!(harness.frontend.shown().len(), 3);
+    }
+
+    #[test]
+    fn module_ignoring_the_stop_request_is_killed() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-ignore-term", None);
+        assert!(harness.run_until(TIMEOUT, |state| state
+            .is_module_running("aw-mock-ignore-term")));
+        stop(&mut harness, "aw-mock-ignore-term");
+        assert_eq!(harness.messages, ["Started", "StopTimedOut", "Stopped"]);
+        let state = harness.state.lock().unwrap();
+        assert!(state.modules_stop_failed.is_empty());
+        assert!(state.modules_pending_shutdown.is_empty());
+        assert!(harness.frontend.shown().is_empty());
+    }
+
+    #[test]
+    fn restarted_module_runs_again() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-running", None);
+        assert!(harness.run_until(TIMEOUT, |state| state.is_module_running("aw-mock-running")));
+        let first_pid = harness.state.lock().unwrap().modules_pid["aw-mock-running"];
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .restart_module("aw-mock-running");
+        assert!(harness.run_until(TIMEOUT, |state| {
+            state
+                .modules_pid
+                .get("aw-mock-running")
+                .is_some_and(|pid| *pid != first_pid)
+        }));
+        assert_eq!(harness.messages, ["Started", "Stopped", "Started"]);
+        assert!(harness.frontend.shown().is_empty());
+        stop(&mut harness, "aw-mock-running");
+    }
+
+    #[test]
+    fn aw_notify_runs_output_only() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-notify", None);
+        assert!(
+            harness.run_until(TIMEOUT, |state| state.modules_running.get("aw-notify")
+                == Some(&false))
+        );
+        assert_eq!(
+            harness.frontend.shown(),
+            [Shown::Notification(
+                format!("--port {TEST_PORT} --output-only").into()
+            )]
+        );
+    }
+
+    #[test]
+    fn framed_output_is_shown_as_notifications() {
+        let mut harness = Harness::new();
+        harness
+            .state
+            .lock()
+            .unwrap()
+            .start_module("aw-mock-notify", None);
+        assert!(
+            harness.run_until(TIMEOUT, |state| state.modules_running.get("aw-mock-notify")
+                == Some(&false))
+        );
+        assert_eq!(
+            harness.frontend.shown(),
+            [
+                Shown::Notification(Notification {
+                    title: Some("Time goal reached".to_string()),
+                    body: "4h of coding today".to_string(),
+                }),
+                Shown::Notification("Take a break".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+/// Ids of the tray menu items
+///
+/// Ids are namespaced, e.g. `app:quit`, `action:export_today` or `module:aw-watcher-afk`, and
+/// are only created and parsed through `MenuId`. An id that doesn't parse is logged and ignored
+/// by the menu event handler, so a new item can't be mistaken for a module to toggle.
+use std::fmt;
+
+const APP: &str = "app:";
+const ACTION: &str = "action:";
+const MODULE: &str = "module:";
+const FORCE_STOP: &str = "force_stop:";
+const MODULE_LOG: &str = "module_log:";
+const PROFILE: &str = "profile:";
+const STATUS: &str = "status:";
+/// Names of the status items, `Status` only takes these
+const STATUSES: [&str; 3] = ["safe_mode", "paused", "server"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuId {
+    Open,
+    Quit,
+    ConfigFolder,
+    LogFolder,
+    DataFolder,
+    Settings,
+    About,
+    ApplyUpdate,
+    OpenRelease,
+    TogglePause,
+    RestartAll,
+    ExportToday,
+    ExportSettings,
+    ImportSettings,
+    /// Starts or stops a module
+    Module(String),
+    ForceStop(String),
+    /// Opens the log of a module's output
+    ModuleLog(String),
+    Profile(String),
+    /// A disabled item only showing a status, one of `STATUSES`
+    Status(&'static str),
+}
+
+impl MenuId {
+    /// Parses a menu item id, `None` for ids not created by `MenuId`
+    pub fn parse(id: &str) -> Option<MenuId> {
+        if let Some(name) = id.strip_prefix(APP) {
+            return match name {
+                "open" => Some(MenuId::Open),
+                "quit" => Some(MenuId::Quit),
+                "config_folder" => Some(MenuId::ConfigFolder),
+                "log_folder" => Some(MenuId::LogFolder),
+                "data_folder" => Some(MenuId::DataFolder),
+                "settings" => Some(MenuId::Settings),
+                "about" => Some(MenuId::About),
+                "apply_update" => Some(MenuId::ApplyUpdate),
+                "open_release" => Some(MenuId::OpenRelease),
+                _ => None,
+            };
+        }
+        if let Some(name) = id.strip_prefix(ACTION) {
+            return match name {
+                "toggle_pause" => Some(MenuId::TogglePause),
+                "restart_all" => Some(MenuId::RestartAll),
+                "export_today" => Some(MenuId::ExportToday),
+                "export_settings" => Some(MenuId::ExportSettings),
+                "import_settings" => Some(MenuId::ImportSettings),
+                _ => None,
+            };
+        }
+        let named = |prefix: &str| id.strip_prefix(prefix).filter(|name| !name.is_empty());
+        if let Some(name) = named(MODULE) {
+            return Some(MenuId::Module(name.to_string()));
+        }
+        if let Some(name) = named(FORCE_STOP) {
+            return Some(MenuId::ForceStop(name.to_string()));
+        }
+        if let Some(name) = named(MODULE_LOG) {
+            return Some(MenuId::ModuleLog(name.to_string()));
+        }
+        if let Some(name) = named(PROFILE) {
+            return Some(MenuId::Profile(name.to_string()));
+        }
+        if let Some(name) = id.strip_prefix(STATUS) {
+            return STATUSES
+                .into_iter()
+                .find(|status| *status == name)
+                .map(MenuId::Status);
+        }
+        None
+    }
+}
+
+impl fmt::Display for MenuId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MenuId::Open => write!(f, "{APP}open"),
+            MenuId::Quit => write!(f, "{APP}quit"),
+            MenuId::ConfigFolder => write!(f, "{APP}config_folder"),
+            MenuId::LogFolder => write!(f, "{APP}log_folder"),
+            MenuId::DataFolder => write!(f, "{APP}data_folder"),
+            MenuId::Settings => write!(f, "{APP}settings"),
+            MenuId::About => write!(f, "{APP}about"),
+            MenuId::ApplyUpdate => write!(f, "{APP}apply_update"),
+            MenuId::OpenRelease => write!(f, "{APP}open_release"),
+            MenuId::TogglePause => write!(f, "{ACTION}toggle_pause"),
+            MenuId::RestartAll => write!(f, "{ACTION}restart_all"),
+            MenuId::ExportToday => write!(f, "{ACTION}export_today"),
+            MenuId::ExportSettings => write!(f, "{ACTION}export_settings"),
+            MenuId::ImportSettings => write!(f, "{ACTION}import_settings"),
+            MenuId::Module(name) => write!(f, "{MODULE}{name}"),
+            MenuId::ForceStop(name) => write!(f, "{FORCE_STOP}{name}"),
+            MenuId::ModuleLog(name) => write!(f, "{MODULE_LOG}{name}"),
+            MenuId::Profile(name) => write!(f, "{PROFILE}{name}"),
+            MenuId::Status(name) => write!(f, "{STATUS}{name}"),
+        }
+    }
+}
+
+impl From<MenuId> for tauri::menu::MenuId {
+    fn from(id: MenuId) -> Self {
+        tauri::menu::MenuId::new(id.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every kind of id the tray menu creates
+    fn all_ids() -> Vec<MenuId> {
+        let mut ids = vec![
+            MenuId::Open,
+            MenuId::Quit,
+            MenuId::ConfigFolder,
+            MenuId::LogFolder,
+            MenuId::DataFolder,
+            MenuId::Settings,
+            MenuId::About,
+            MenuId::ApplyUpdate,
+            MenuId::OpenRelease,
+            MenuId::TogglePause,
+            MenuId::RestartAll,
+            MenuId::ExportToday,
+            MenuId::ExportSettings,
+            MenuId::ImportSettings,
+            MenuId::Module("aw-watcher-afk".to_string()),
+            MenuId::ForceStop("aw-watcher-window".to_string()),
+            MenuId::ModuleLog("aw-watcher-input".to_string()),
+            MenuId::Profile("work".to_string()),
+        ];
+        ids.extend(STATUSES.map(MenuId::Status));
+        // Fails to compile when a new id isn't listed above
+        for id in &ids {
+            match id {
+                MenuId::Open
+                | MenuId::Quit
+                | MenuId::ConfigFolder
+                | MenuId::LogFolder
+                | MenuId::DataFolder
+                | MenuId::Settings
+                | MenuId::About
+                | MenuId::ApplyUpdate
+                | MenuId::OpenRelease
+                | MenuId::TogglePause
+                | MenuId::RestartAll
+                | MenuId::ExportToday
+                | MenuId::ExportSettings
+                | MenuId::ImportSettings
+                | MenuId::Module(_)
+                | MenuId::ForceStop(_)
+                | MenuId::ModuleLog(_)
+                | MenuId::Profile(_)
+                | MenuId::Status(_) => {}
+            }
+        }
+        ids
+    }
+
+    #[test]
+    fn ids_round_trip() {
+        for id in all_ids() {
+            assert_eq!(MenuId::parse(&id.to_string()), Some(id.clone()), "{id}");
+        }
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        let ids: Vec<String> = all_ids().iter().map(MenuId::to_string).collect();
+        let unique: std::collections::BTreeSet<&String> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len());
+    }
+
+    #[test]
+    fn module_names_keep_their_colons() {
+        assert_eq!(
+            MenuId::parse("module:aw-watcher-afk:work"),
+            Some(MenuId::Module("aw-watcher-afk:work".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_ids_are_not_parsed() {
+        for id in [
+            "",
+            "quit",
+            "aw-watcher-afk",
+            "app:",
+            "app:unknown",
+            "action:quit",
+            "module:",
+            "force_stop:",
+            "module_log:",
+            "profile:",
+            "status:",
+            "status:unknown",
+            "unknown:aw-watcher-afk",
+        ] {
+            assert_eq!(MenuId::parse(id), None, "{id}");
+        }
+    }
+}
@@ -0,0 +1,262 @@
+/// Actions and delivery of notifications
+///
+/// Notifications are tagged with an action type so platforms with notification actions can offer
+/// e.g. "Restart" on a crash notification. The plugin only takes action types from the webview,
+/// so the frontend registers the ones of `action_types` on startup and forwards the chosen
+/// action to the `handle_notification_action` command, which routes it here. Desktop
+/// notifications have no actions, a click only focuses the app, so their text must stand on
+/// its own.
+use log::{error, info, warn};
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::{NotificationExt, PermissionState};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::manager::ManagerState;
+
+/// Action type of crash notifications, offering `RESTART_ACTION` and `OPEN_LOG_ACTION`
+pub const MODULE_CRASH_TYPE: &str = "module_crash";
+pub const RESTART_ACTION: &str = "restart";
+pub const OPEN_LOG_ACTION: &str = "open_log";
+/// Key of the crashed module in the notification extras
+pub const MODULE_EXTRA: &str = "module";
+
+/// Returns the action types to register, as the plugin's `registerActionTypes` takes them
+pub fn action_types() -> serde_json::Value {
+    serde_json::json!([{
+        "id": MODULE_CRASH_TYPE,
+        "actions": [
+            { "id": RESTART_ACTION, "title": "Restart" },
+            { "id": OPEN_LOG_ACTION, "title": "Open log" },
+        ],
+    }])
+}
+
+/// A notification to show, under the app's name unless it has a title of its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: Option<String>,
+    pub body: String,
+}
+
+impl From<String> for Notification {
+    fn from(body: String) -> Self {
+        Notification { title: None, body }
+    }
+}
+
+impl From<&str> for Notification {
+    fn from(body: &str) -> Self {
+        body.to_string().into()
+    }
+}
+
+/// What a notification is about, deciding the actions it offers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationKind {
+    Info,
+    ModuleCrash { module: String },
+}
+
+/// Marker in the app data dir, so the user is asked for permission only once
+const PERMISSION_REQUESTED_FILE: &str = "notification-permission-requested";
+
+/// Notification permission found on startup, `None` until checked
+static PERMISSION: OnceLock<PermissionState> = OnceLock::new();
+
+/// How a notification reaches the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Notification,
+    /// A dialog, for notifications too important to only be logged
+    Dialog,
+    Log,
+}
+
+/// Decides how to deliver a notification given the notification permission.
+///
+/// Without a known permission, e.g. on platforms without a permission concept, notifications
+/// are sent as before.
+pub fn route(permission: Option<PermissionState>, important: bool) -> Route {
+    match permission {
+        Some(PermissionState::Denied) if important => Route::Dialog,
+        Some(PermissionState::Denied) => Route::Log,
+        _ => Route::Notification,
+    }
+}
+
+impl NotificationKind {
+    /// Whether the notification should reach the user even without notification permission
+    pub fn is_important(&self) -> bool {
+        matches!(self, NotificationKind::ModuleCrash { .. })
+    }
+}
+
+/// Returns how to deliver a notification of `kind`
+pub fn route_for(kind: &NotificationKind) -> Route {
+    route(PERMISSION.get().copied(), kind.is_important())
+}
+
+/// Checks the notification permission, asking for it once if the user hasn't decided yet
+pub fn check_permission(app: &AppHandle) {
+    let notification = app.notification();
+    let mut permission = match notification.permission_state() {
+        Ok(permission) => permission,
+        Err(e) => {
+            warn!("Failed to get the notification permission: {e}");
+            return;
+        }
+    };
+    let marker = crate::dirs::app_data_dir().join(PERMISSION_REQUESTED_FILE);
+    if matches!(
+        permission,
+        PermissionState::Prompt | PermissionState::PromptWithRationale
+    ) && !marker.exists()
+    {
+        info!("Asking for notification permission");
+        match notification.request_permission() {
+            Ok(granted) => permission = granted,
+            Err(e) => warn!("Failed to request notification permission: {e}"),
+        }
+        if let Err(e) = crate::dirs::create_dir_with_retry(marker.parent().expect("no parent"))
+            .and_then(|()| std::fs::write(&marker, ""))
+        {
+            warn!("Failed to remember the notification permission request: {e}");
+        }
+    }
+    info!("Notification permission: {permission:?}");
+    let _ = PERMISSION.set(permission);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationAction {
+    ShowDashboard,
+    RestartModule(String),
+    /// Opens the log of the module
+    OpenLog(String),
+}
+
+impl NotificationAction {
+    /// Maps an action id and the notification's module to the action to take.
+    ///
+    /// Clicking the notification itself, or any unknown action, shows the dashboard.
+    pub fn parse(action_id: &str, module: Option<&str>) -> NotificationAction {
+        match (action_id, module) {
+            (RESTART_ACTION, Some(module)) => NotificationAction::RestartModule(module.to_string()),
+            (OPEN_LOG_ACTION, Some(module)) => NotificationAction::OpenLog(module.to_string()),
+            _ => NotificationAction::ShowDashboard,
+        }
+    }
+}
+
+pub fn handle_action(
+    app: &AppHandle,
+    manager_state: &Arc<Mutex<ManagerState>>,
+    action: NotificationAction,
+) {
+    info!("Notification action: {action:?}");
+    match action {
+        NotificationAction::ShowDashboard => {
+            if let Some(window) = app.webview_windows().get("main") {
+                if let Err(e) = window.show().and_then(|()| window.set_focus()) {
+                    error!("Failed to show the main window: {e}");
+                }
+            }
+        }
+        NotificationAction::RestartModule(name) => {
+            manager_state.lock().unwrap().retry_module(&name);
+        }
+        NotificationAction::OpenLog(module) => {
+            let log_file = crate::logging::module_log_path(&module);
+            if let Err(e) = app
+                .opener()
+                .open_path(log_file.to_string_lossy(), None::<&str>)
+            {
+                error!("Failed to open {}: {e}", log_file.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_by_permission() {
+        use PermissionState::*;
+        let cases = [
+            (None, false, Route::Notification),
+            (None, true, Route::Notification),
+            (Some(Granted), false, Route::Notification),
+            (Some(Granted), true, Route::Notification),
+            (Some(Prompt), false, Route::Notification),
+            (Some(Prompt), true, Route::Notification),
+            (Some(PromptWithRationale), false, Route::Notification),
+            (Some(PromptWithRationale), true, Route::Notification),
+            (Some(Denied), false, Route::Log),
+            (Some(Denied), true, Route::Dialog),
+        ];
+        for (permission, important, expected) in cases {
+            assert_eq!(
+                route(permission, important),
+                expected,
+                "{permission:?} important={important}"
+            );
+        }
+    }
+
+    #[test]
+    fn crashes_are_important() {
+        assert!(NotificationKind::ModuleCrash {
+            module: "aw-watcher-afk".to_string()
+        }
+        .is_important());
+        assert!(!NotificationKind::Info.is_important());
+    }
+
+    #[test]
+    fn parse_actions() {
+        let cases = [
+            (
+                RESTART_ACTION,
+                Some("aw-watcher-afk"),
+                NotificationAction::RestartModule("aw-watcher-afk".to_string()),
+            ),
+            (
+                OPEN_LOG_ACTION,
+                Some("aw-watcher-afk"),
+                NotificationAction::OpenLog("aw-watcher-afk".to_string()),
+            ),
+            // Without a module there is nothing to restart or open
+            (RESTART_ACTION, None, NotificationAction::ShowDashboard),
+            (OPEN_LOG_ACTION, None, NotificationAction::ShowDashboard),
+            (
+                "tap",
+                Some("aw-watcher-afk"),
+                NotificationAction::ShowDashboard,
+            ),
+            ("", None, NotificationAction::ShowDashboard),
+        ];
+        for (action_id, module, expected) in cases {
+            assert_eq!(
+                NotificationAction::parse(action_id, module),
+                expected,
+                "{action_id:?} {module:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn action_types_offer_the_parsed_actions() {
+        let types = action_types();
+        assert_eq!(types[0]["id"], MODULE_CRASH_TYPE);
+        let actions: Vec<&str> = types[0]["actions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|action| action["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(actions, [RESTART_ACTION, OPEN_LOG_ACTION]);
+    }
+}
@@ -0,0 +1,175 @@
+/// macOS permission preflight for the watchers
+///
+/// aw-watcher-window needs the Accessibility permission, and Screen Recording to read window
+/// titles on newer macOS versions. Without them it runs but records nothing, so modules missing a
+/// permission are held back: the user is told which permission to grant, and the module is
+/// started once a periodic re-check finds it granted.
+use log::{info, warn};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::get_app_handle;
+use crate::manager::ModuleMessage;
+use crate::shutdown;
+
+const RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Accessibility,
+    ScreenRecording,
+}
+
+impl Permission {
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "Accessibility",
+            Permission::ScreenRecording => "Screen Recording",
+        }
+    }
+    /// System Settings pane where the permission is granted
+    fn settings_url(self) -> &'static str {
+        match self {
+            Permission::Accessibility => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility"
+            }
+            Permission::ScreenRecording => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture"
+            }
+        }
+    }
+    pub fn is_granted(self) -> bool {
+        // SAFETY: both functions take no arguments and only query the permission state
+        unsafe {
+            match self {
+                Permission::Accessibility => AXIsProcessTrusted(),
+                Permission::ScreenRecording => CGPreflightScreenCaptureAccess(),
+            }
+        }
+    }
+}
+
+/// Permissions a module needs to record anything
+pub fn required_permissions(module: &str) -> &'static [Permission] {
+    match module {
+        "aw-watcher-window" => &[Permission::Accessibility, Permission::ScreenRecording],
+        _ => &[],
+    }
+}
+
+/// Returns the permissions required by `module` that `is_granted` reports as missing
+pub fn missing_permissions(
+    module: &str,
+    is_granted: impl Fn(Permission) -> bool,
+) -> Vec<Permission> {
+    required_permissions(module)
+        .iter()
+        .copied()
+        .filter(|permission| !is_granted(*permission))
+        .collect()
+}
+
+/// Returns the permissions `module` is missing right now
+pub fn check_module(module: &str) -> Vec<Permission> {
+    missing_permissions(module, Permission::is_granted)
+}
+
+/// Explains the missing permissions and waits for them to be granted in the background.
+///
+/// Sends `PermissionsGranted` once everything the module needs is granted.
+pub fn wait_for_permissions(module: String, missing: Vec<Permission>, tx: Sender<ModuleMessage>) {
+    let names: Vec<&str> = missing.iter().map(|permission| permission.name()).collect();
+    info!("{module} is waiting for permissions: {}", names.join(", "));
+    thread::spawn(move || {
+        let app = get_app_handle();
+        let first_missing = missing[0];
+        let open_settings = app
+            .dialog()
+            .message(format!(
+                "{module} needs the {} permission to track your activity.\n\n\
+                Grant it to Aw-Tauri in System Settings → Privacy & Security, {module} will be \
+                started automatically afterwards.",
+                names.join(" and ")
+            ))
+            .kind(MessageDialogKind::Warning)
+            .title("Aw-Tauri")
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                "Open System Settings".to_string(),
+                "Later".to_string(),
+            ))
+            .blocking_show();
+        if open_settings {
+            if let Err(e) = app
+                .opener()
+                .open_url(first_missing.settings_url(), None::<&str>)
+            {
+                warn!("Failed to open System Settings: {e}");
+            }
+        }
+
+        while !check_module(&module).is_empty() {
+            if shutdown::sleep(RECHECK_INTERVAL) {
+                return;
+            }
+        }
+        info!("Permissions for {module} granted");
+        let _ = tx.send(ModuleMessage::PermissionsGranted { name: module });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_permissions_of_the_window_watcher() {
+        use Permission::*;
+        let cases: [(&[Permission], &[Permission]); 4] = [
+            (&[], &[Accessibility, ScreenRecording]),
+            (&[Accessibility], &[ScreenRecording]),
+            (&[ScreenRecording], &[Accessibility]),
+            (&[Accessibility, ScreenRecording], &[]),
+        ];
+        for (granted, missing) in cases {
+            assert_eq!(
+                missing_permissions("aw-watcher-window", |permission| granted
+                    .contains(&permission)),
+                missing,
+                "granted {granted:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn other_modules_need_no_permissions() {
+        for module in ["aw-watcher-afk", "aw-watcher-input", "aw-sync"] {
+            assert!(
+                missing_permissions(module, |_| false).is_empty(),
+                "{module}"
+            );
+        }
+    }
+
+    #[test]
+    fn settings_open_the_pane_of_the_permission() {
+        assert!(Permission::Accessibility
+            .settings_url()
+            .ends_with("Privacy_Accessibility"));
+        assert!(Permission::ScreenRecording
+            .settings_url()
+            .ends_with("Privacy_ScreenCapture"));
+    }
+}
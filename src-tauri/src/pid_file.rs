@@ -0,0 +1,60 @@
+/// Pids of the running modules, for cleaning up after a crashed session
+///
+/// The modules keep running when aw-tauri is killed without stopping them, e.g. by SIGKILL, and
+/// would be started a second time on the next launch. The running modules are saved whenever one
+/// starts or stops, and the file is removed once none runs, e.g. after a clean shutdown. On
+/// startup the manager adopts the modules a crashed session left running.
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, remove_file, write};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+const PID_FILE: &str = "modules.pids";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleProcess {
+    pub pid: u32,
+    /// Executable of the module, to tell it apart from a process that got the pid later
+    pub exe: PathBuf,
+}
+
+fn pid_file_path() -> PathBuf {
+    crate::dirs::app_data_dir().join(PID_FILE)
+}
+
+/// Returns the modules saved as running, empty if aw-tauri shut down cleanly
+pub fn load() -> BTreeMap<String, ModuleProcess> {
+    let path = pid_file_path();
+    let content = match read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return BTreeMap::new(),
+        Err(e) => {
+            warn!("Failed to read {}: {e}", path.display());
+            return BTreeMap::new();
+        }
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!("Ignoring invalid {}: {e}", path.display());
+        BTreeMap::new()
+    })
+}
+
+/// Saves the running modules, removing the file if there are none
+pub fn save(modules: &BTreeMap<String, ModuleProcess>) {
+    let path = pid_file_path();
+    let result = if modules.is_empty() {
+        match remove_file(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => result.map_err(|e| e.to_string()),
+        }
+    } else {
+        serde_json::to_string_pretty(modules)
+            .map_err(|e| e.to_string())
+            .and_then(|json| write(&path, json).map_err(|e| e.to_string()))
+    };
+    if let Err(e) = result {
+        warn!("Failed to save the module pids to {}: {e}", path.display());
+    }
+}
@@ -0,0 +1,387 @@
+/// Helpers for resolving conflicts on the server port
+///
+/// Used to tell whether the program occupying a port is an ActivityWatch server, and to find a
+/// free port to fall back to.
+use log::{error, info, warn};
+use std::fs::{read_to_string, remove_file, write};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri_plugin_dialog::{
+    DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+
+use crate::{
+    get_app_handle, get_config_path, is_address_available, is_port_available, read_current_config,
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+/// How many ports the server tries when the checked port gets taken before it binds
+const BIND_ATTEMPTS: usize = 5;
+/// How many ports above the configured one to try when looking for a free port
+const FREE_PORT_SEARCH_RANGE: u16 = 100;
+/// Holds the configured port and the port the server last ran on
+const LAST_PORT_FILE: &str = "last-port";
+
+/// Returns `true` if the server listening on the port answers like an ActivityWatch server
+pub fn probe_activitywatch_server(port: u16) -> bool {
+    let host = crate::local_host();
+    let Some(addr) = (host.as_str(), port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+    else {
+        return false;
+    };
+    let Ok(mut stream) = TcpStream::connect_timeout(&addr, PROBE_TIMEOUT) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(PROBE_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(PROBE_TIMEOUT));
+
+    let request = format!("GET /api/0/info HTTP/1.0\r\nHost: {addr}\r\n\r\n");
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+    let mut response = Vec::new();
+    let _ = stream.take(64 * 1024).read_to_end(&mut response);
+    is_activitywatch_info_response(&String::from_utf8_lossy(&response))
+}
+
+/// Checks for a successful response with the body of aw-server's `/api/0/info`
+fn is_activitywatch_info_response(response: &str) -> bool {
+    let Some((head, body)) = response.split_once("\r\n\r\n") else {
+        return false;
+    };
+    let status_ok = head
+        .lines()
+        .next()
+        .is_some_and(|status| status.starts_with("HTTP/1.") && status.contains(" 200"));
+    status_ok
+        && serde_json::from_str::<serde_json::Value>(body)
+            .is_ok_and(|info| info.get("hostname").is_some() && info.get("version").is_some())
+}
+
+/// Returns the first free port above `port`
+pub fn find_free_port(port: u16) -> Option<u16> {
+    find_free_port_on(&crate::get_config().defaults.host, port)
+}
+
+/// Returns the first port above `port` that can be bound on `address`
+fn find_free_port_on(address: &str, port: u16) -> Option<u16> {
+    (port.saturating_add(1)..=port.saturating_add(FREE_PORT_SEARCH_RANGE))
+        .find(|candidate| is_address_available(address, *candidate).unwrap_or(false))
+}
+
+/// Runs `launch` on `port` of `address`, moving on to the next free port when another program
+/// took the port between checking and binding it, e.g. a second instance started at the same
+/// time. Returns the port of the last attempt and how it ended.
+pub async fn launch_on_free_port<F, Fut, E>(
+    address: &str,
+    mut port: u16,
+    mut launch: F,
+) -> (u16, Result<(), E>)
+where
+    F: FnMut(u16) -> Fut,
+    Fut: std::future::Future<Output = Result<(), E>>,
+{
+    let mut attempts = 1;
+    loop {
+        let result = launch(port).await;
+        // A port that can't be checked is treated as taken
+        if result.is_err()
+            && attempts < BIND_ATTEMPTS
+            && !is_address_available(address, port).unwrap_or(false)
+        {
+            if let Some(next) = find_free_port_on(address, port) {
+                warn!("Port {port} was taken before the server could bind it, trying {next}");
+                port = next;
+                attempts += 1;
+                continue;
+            }
+        }
+        return (port, result);
+    }
+}
+
+fn last_port_path() -> PathBuf {
+    get_config_path()
+        .parent()
+        .expect("config path has no parent")
+        .join(LAST_PORT_FILE)
+}
+
+/// Returns the port the server last ran on if it is still free, so the port stays stable across
+/// restarts instead of bouncing back to the configured one. Falls back to `configured`.
+///
+/// The remembered port is dropped when the configured port has changed since.
+pub fn preferred_port(configured: u16) -> u16 {
+    let Ok(last_port) = read_to_string(last_port_path()) else {
+        return configured;
+    };
+    let ports = last_port
+        .split_whitespace()
+        .map(str::parse::<u16>)
+        .collect::<Result<Vec<_>, _>>();
+    match ports.as_deref() {
+        Ok(&[remembered_configured, last]) if remembered_configured == configured => {
+            if last != configured && is_port_available(last).unwrap_or(false) {
+                info!("Using port {last} from the last run instead of {configured}");
+                return last;
+            }
+            configured
+        }
+        _ => {
+            let _ = remove_file(last_port_path());
+            configured
+        }
+    }
+}
+
+/// Remembers the port the server was started on, see `preferred_port`
+pub fn remember_port(port: u16) {
+    let configured = read_current_config().defaults.port;
+    if let Err(e) = write(last_port_path(), format!("{configured} {port}")) {
+        warn!("Failed to remember server port: {e}");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortResolution {
+    /// Leave the existing ActivityWatch server running and report to it
+    UseExisting,
+    /// Run the server on another port
+    Port(u16),
+    /// Give up and quit
+    Quit,
+}
+
+/// What the user can do about a taken port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConflictChoice {
+    UseExisting,
+    Retry,
+    PickFreePort,
+    Quit,
+}
+
+impl ConflictChoice {
+    fn label(self) -> &'static str {
+        match self {
+            ConflictChoice::UseExisting => "Use the existing server",
+            ConflictChoice::Retry => "Retry",
+            ConflictChoice::PickFreePort => "Pick a free port",
+            ConflictChoice::Quit => "Quit",
+        }
+    }
+}
+
+/// Returns the message and the choices to offer about `port` being taken, by an ActivityWatch
+/// server if `is_activitywatch`
+fn conflict_prompt(
+    port: u16,
+    first_run: bool,
+    is_activitywatch: bool,
+) -> (String, [ConflictChoice; 3]) {
+    let welcome = if first_run {
+        "Welcome to ActivityWatch!\n\n"
+    } else {
+        ""
+    };
+    if is_activitywatch {
+        (
+            format!(
+                "{welcome}Port {port} is already used by another ActivityWatch server, \
+                probably from a previous installation that is still running.\n\n\
+                You can keep using that server, or let Aw-Tauri run its own server on a free \
+                port."
+            ),
+            [
+                ConflictChoice::UseExisting,
+                ConflictChoice::PickFreePort,
+                ConflictChoice::Quit,
+            ],
+        )
+    } else {
+        (
+            format!(
+                "{welcome}Port {port} is already used by another program.\n\n\
+                Close it and retry, or let Aw-Tauri run its server on a free port instead."
+            ),
+            [
+                ConflictChoice::Retry,
+                ConflictChoice::PickFreePort,
+                ConflictChoice::Quit,
+            ],
+        )
+    }
+}
+
+/// What a choice about a taken port leads to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Resolved(PortResolution),
+    /// The port is still taken, ask again
+    AskAgain,
+    NoFreePort,
+}
+
+/// Applies the user's choice about `port` being taken on `address`
+fn decide(choice: ConflictChoice, address: &str, port: u16) -> Decision {
+    match choice {
+        ConflictChoice::UseExisting => Decision::Resolved(PortResolution::UseExisting),
+        ConflictChoice::Retry if is_address_available(address, port).unwrap_or(false) => {
+            Decision::Resolved(PortResolution::Port(port))
+        }
+        ConflictChoice::Retry => {
+            info!("Port {port} is still in use");
+            Decision::AskAgain
+        }
+        ConflictChoice::PickFreePort => match find_free_port_on(address, port) {
+            Some(free_port) => Decision::Resolved(PortResolution::Port(free_port)),
+            None => Decision::NoFreePort,
+        },
+        ConflictChoice::Quit => Decision::Resolved(PortResolution::Quit),
+    }
+}
+
+/// Lets the user decide what to do when the server port is taken.
+///
+/// Blocks on the dialog, so this must not be called from the main thread.
+pub fn resolve_port_conflict(port: u16, first_run: bool) -> PortResolution {
+    let app = get_app_handle();
+    let address = crate::get_config().defaults.host.clone();
+
+    loop {
+        let (message, choices) = conflict_prompt(port, first_run, probe_activitywatch_server(port));
+        let [yes, no, cancel] = choices.map(|choice| choice.label().to_string());
+        let result = app
+            .dialog()
+            .message(message)
+            .kind(MessageDialogKind::Warning)
+            .title("Aw-Tauri")
+            .buttons(MessageDialogButtons::YesNoCancelCustom(yes, no, cancel))
+            .blocking_show_with_result();
+
+        // Closing the dialog quits
+        let choice = match result {
+            MessageDialogResult::Custom(label) => choices
+                .into_iter()
+                .find(|choice| choice.label() == label)
+                .unwrap_or(ConflictChoice::Quit),
+            _ => ConflictChoice::Quit,
+        };
+        match decide(choice, &address, port) {
+            Decision::Resolved(resolution) => return resolution,
+            Decision::AskAgain => {}
+            Decision::NoFreePort => {
+                error!("No free port found near {port}");
+                app.dialog()
+                    .message(format!("No free port was found near {port}."))
+                    .kind(MessageDialogKind::Error)
+                    .title("Aw-Tauri")
+                    .blocking_show();
+                return PortResolution::Quit;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    const HOST: &str = "127.0.0.1";
+
+    #[test]
+    fn second_instance_moves_to_the_next_free_port() {
+        let first = TcpListener::bind((HOST, 0)).unwrap();
+        let taken = first.local_addr().unwrap().port();
+        let mut listeners = Vec::new();
+        let (port, result) =
+            tauri::async_runtime::block_on(launch_on_free_port(HOST, taken, |port| {
+                std::future::ready(TcpListener::bind((HOST, port)).map(|l| listeners.push(l)))
+            }));
+        assert!(result.is_ok());
+        assert_ne!(port, taken);
+        assert_eq!(listeners[0].local_addr().unwrap().port(), port);
+        assert_eq!(first.local_addr().unwrap().port(), taken);
+    }
+
+    #[test]
+    fn launch_errors_on_a_free_port_are_not_retried() {
+        let port = TcpListener::bind((HOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut attempts = 0;
+        let (tried, result) =
+            tauri::async_runtime::block_on(launch_on_free_port(HOST, port, |_| {
+                attempts += 1;
+                std::future::ready(Err::<(), _>("datastore failed"))
+            }));
+        assert_eq!(result, Err("datastore failed"));
+        assert_eq!((tried, attempts), (port, 1));
+    }
+
+    #[test]
+    fn gives_up_after_the_bind_attempts() {
+        let first = TcpListener::bind((HOST, 0)).unwrap();
+        let taken = first.local_addr().unwrap().port();
+        let mut held = Vec::new();
+        // Each port gets taken by another program right before the server binds it
+        let (_, result) =
+            tauri::async_runtime::block_on(launch_on_free_port(HOST, taken, |port| {
+                held.extend(TcpListener::bind((HOST, port)).ok());
+                std::future::ready(Err::<(), _>("address in use"))
+            }));
+        assert!(result.is_err());
+        assert_eq!(held.len() + 1, BIND_ATTEMPTS);
+    }
+
+    #[test]
+    fn another_activitywatch_server_can_be_used() {
+        crate::test_support::init();
+        let port = crate::test_support::fake_activitywatch_server();
+        assert!(probe_activitywatch_server(port));
+        let (message, choices) = conflict_prompt(port, false, true);
+        assert!(message.contains("another ActivityWatch server"));
+        assert_eq!(choices[0], ConflictChoice::UseExisting);
+        assert_eq!(
+            decide(ConflictChoice::UseExisting, HOST, port),
+            Decision::Resolved(PortResolution::UseExisting)
+        );
+    }
+
+    #[test]
+    fn port_taken_by_another_program_offers_to_retry() {
+        crate::test_support::init();
+        let other = TcpListener::bind((HOST, 0)).unwrap();
+        let port = other.local_addr().unwrap().port();
+        assert!(!probe_activitywatch_server(port));
+        let (message, choices) = conflict_prompt(port, true, false);
+        assert!(message.starts_with("Welcome to ActivityWatch!"));
+        assert_eq!(choices[0], ConflictChoice::Retry);
+
+        assert_eq!(
+            decide(ConflictChoice::Retry, HOST, port),
+            Decision::AskAgain
+        );
+        match decide(ConflictChoice::PickFreePort, HOST, port) {
+            Decision::Resolved(PortResolution::Port(free_port)) => assert_ne!(free_port, port),
+            decision => panic!("no free port picked: {decision:?}"),
+        }
+        drop(other);
+        assert_eq!(
+            decide(ConflictChoice::Retry, HOST, port),
+            Decision::Resolved(PortResolution::Port(port))
+        );
+        assert_eq!(
+            decide(ConflictChoice::Quit, HOST, port),
+            Decision::Resolved(PortResolution::Quit)
+        );
+    }
+}
@@ -0,0 +1,93 @@
+/// Pausing the watchers on low battery
+///
+/// When `[power] pause_below_percent` is set, the battery is checked every few minutes and the
+/// watchers are paused while discharging below the threshold. Tracking resumes once charging or
+/// back above the threshold plus a hysteresis margin.
+///
+/// A pause or resume by the user always wins: a manual pause is never lifted by the monitor, and
+/// after a manual resume on low battery the monitor waits until the battery has recovered before
+/// it pauses again.
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use starship_battery::units::ratio::percent;
+use starship_battery::State;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::get_config;
+use crate::manager::{ManagerState, PauseReason};
+use crate::shutdown;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(3 * 60);
+/// Percentage points above the threshold needed to resume
+const HYSTERESIS_PERCENT: f32 = 5.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PowerConfig {
+    /// Pause the watchers when discharging below this battery percentage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_below_percent: Option<u8>,
+}
+
+struct BatteryStatus {
+    percent: f32,
+    charging: bool,
+}
+
+/// Reads the combined status of all batteries, `None` if there are none
+fn read_battery() -> Result<Option<BatteryStatus>, starship_battery::Error> {
+    let manager = starship_battery::Manager::new()?;
+    let batteries = manager.batteries()?.collect::<Result<Vec<_>, _>>()?;
+    if batteries.is_empty() {
+        return Ok(None);
+    }
+    let percent = batteries
+        .iter()
+        .map(|battery| battery.state_of_charge().get::<percent>())
+        .sum::<f32>()
+        / batteries.len() as f32;
+    let charging = batteries
+        .iter()
+        .any(|battery| matches!(battery.state(), State::Charging | State::Full));
+    Ok(Some(BatteryStatus { percent, charging }))
+}
+
+/// Starts the battery monitor if a threshold is configured
+pub fn start_battery_monitor(manager_state: Arc<Mutex<ManagerState>>) {
+    let Some(threshold) = get_config().power.pause_below_percent else {
+        return;
+    };
+    let threshold = f32::from(threshold);
+    shutdown::spawn("battery monitor", move || {
+        // Cleared after pausing, so a manual resume on low battery isn't overridden
+        let mut armed = true;
+        loop {
+            match read_battery() {
+                Ok(Some(status)) => {
+                    let low = !status.charging && status.percent < threshold;
+                    let recovered =
+                        status.charging || status.percent >= threshold + HYSTERESIS_PERCENT;
+                    let mut state = manager_state.lock().unwrap();
+                    if recovered {
+                        armed = true;
+                        if state.pause_reason == Some(PauseReason::LowBattery) {
+                            info!("Battery recovered ({:.0}%)", status.percent);
+                            state.resume_tracking();
+                        }
+                    } else if low && armed && state.pause_tracking(PauseReason::LowBattery) {
+                        info!("Battery low ({:.0}%)", status.percent);
+                        armed = false;
+                    }
+                }
+                Ok(None) => {
+                    info!("No battery found, not monitoring battery level");
+                    return;
+                }
+                Err(e) => debug!("Failed to read battery status: {e}"),
+            }
+            if shutdown::sleep(CHECK_INTERVAL) {
+                return;
+            }
+        }
+    });
+}
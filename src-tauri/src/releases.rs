@@ -0,0 +1,153 @@
+/// Notifications about new releases for builds without the updater
+///
+/// Packaged builds (e.g. from a distribution) don't carry the updater key, so their users would
+/// never hear about new versions. For those, the GitHub releases are checked periodically and a
+/// notification is posted once per new version, with a tray item linking to the release page.
+use log::{debug, info, warn};
+use semver::Version;
+use serde::Deserialize;
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_opener::OpenerExt;
+
+use crate::manager::ManagerState;
+use crate::shutdown;
+use crate::updater::{UpdateChannel, UPDATER_PUBKEY};
+use crate::{get_config, get_data_dir};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/ActivityWatch/aw-tauri/releases";
+const LAST_NOTIFIED_FILE: &str = "last-notified-release";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub draft: bool,
+}
+
+impl Release {
+    fn version(&self) -> Option<Version> {
+        Version::parse(self.tag_name.trim_start_matches('v')).ok()
+    }
+}
+
+static NEW_RELEASE: Mutex<Option<Release>> = Mutex::new(None);
+
+/// Returns the tag of the newer release found, if any
+pub fn new_release_tag() -> Option<String> {
+    NEW_RELEASE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|release| release.tag_name.clone())
+}
+
+/// Picks the newest release that is newer than `current`.
+///
+/// Pre-releases are only considered on the beta channel or when already running a pre-release.
+pub fn newest_release<'a>(
+    releases: &'a [Release],
+    current: &Version,
+    channel: UpdateChannel,
+) -> Option<(&'a Release, Version)> {
+    let include_pre = channel == UpdateChannel::Beta || !current.pre.is_empty();
+    releases
+        .iter()
+        .filter(|release| !release.draft)
+        .filter_map(|release| Some((release, release.version()?)))
+        .filter(|(_, version)| include_pre || version.pre.is_empty())
+        .filter(|(_, version)| version > current)
+        .max_by(|(_, a), (_, b)| a.cmp(b))
+}
+
+fn last_notified_path() -> PathBuf {
+    get_data_dir().join(LAST_NOTIFIED_FILE)
+}
+
+fn fetch_releases() -> Result<Vec<Release>, reqwest::Error> {
+    reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("aw-tauri/", env!("CARGO_PKG_VERSION")))
+        .build()?
+        .get(RELEASES_URL)
+        .send()?
+        .error_for_status()?
+        .json()
+}
+
+/// Periodically checks for new releases, unless the updater takes care of updates
+pub fn start_release_checks(app: AppHandle) {
+    let config = get_config().updates.clone();
+    if config.check && UPDATER_PUBKEY.is_some() {
+        return;
+    }
+    if !config.notify_releases {
+        info!("Release notifications are disabled");
+        return;
+    }
+    let interval = Duration::from_secs(config.release_check_interval_days.max(1) * 24 * 60 * 60);
+    let current = Version::parse(env!("CARGO_PKG_VERSION")).expect("invalid package version");
+    shutdown::spawn("release check", move || loop {
+        check_for_release(&app, &current, config.channel);
+        if shutdown::sleep(interval) {
+            return;
+        }
+    });
+}
+
+fn check_for_release(app: &AppHandle, current: &Version, channel: UpdateChannel) {
+    let releases = match fetch_releases() {
+        Ok(releases) => releases,
+        Err(e) => {
+            debug!("Failed to check for new releases: {e}");
+            return;
+        }
+    };
+    let Some((release, version)) = newest_release(&releases, current, channel) else {
+        debug!("No new release available");
+        return;
+    };
+    let release = release.clone();
+    info!("New release available: {}", release.tag_name);
+    *NEW_RELEASE.lock().unwrap() = Some(release.clone());
+    let manager_state = app.state::<Arc<Mutex<ManagerState>>>();
+    manager_state.lock().unwrap().update_tray_menu();
+
+    let last_notified = read_to_string(last_notified_path())
+        .ok()
+        .and_then(|version| Version::parse(version.trim()).ok());
+    if last_notified.as_ref() == Some(&version) {
+        return;
+    }
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Aw-Tauri")
+        .body(format!(
+            "ActivityWatch {} is available, see the tray menu for the release notes",
+            release.tag_name
+        ))
+        .show()
+    {
+        warn!("Failed to show release notification: {e}");
+    }
+    if let Err(e) = write(last_notified_path(), version.to_string()) {
+        warn!("Failed to remember notified release: {e}");
+    }
+}
+
+/// Opens the page of the newer release
+pub fn open_release_page(app: &AppHandle) {
+    let Some(release) = NEW_RELEASE.lock().unwrap().clone() else {
+        return;
+    };
+    if let Err(e) = app.opener().open_url(&release.html_url, None::<&str>) {
+        warn!("Failed to open release page {}: {e}", release.html_url);
+    }
+}
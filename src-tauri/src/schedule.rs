@@ -0,0 +1,237 @@
+/// Tracking hours
+///
+/// With `[schedule]` enabled, the watchers are paused outside the configured days and hours. The
+/// schedule is evaluated against the local wall clock every tick, so timezone and DST changes are
+/// picked up on their own. "Track anyway" from the tray suspends the schedule for an hour.
+use chrono::{Datelike, Duration as ChronoDuration, NaiveDateTime, NaiveTime, Weekday};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::get_config;
+use crate::manager::{ManagerState, PauseReason};
+use crate::shutdown;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How long "Track anyway" suspends the schedule
+pub const OVERRIDE_DURATION: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Days the window starts on, e.g. `["mon", "tue"]`
+    #[serde(default = "default_days")]
+    pub days: Vec<String>,
+    /// Start of the window, `HH:MM`
+    #[serde(default = "default_start")]
+    pub start: String,
+    /// End of the window, `HH:MM`. Before `start`, the window ends on the next day.
+    #[serde(default = "default_end")]
+    pub end: String,
+}
+
+fn default_days() -> Vec<String> {
+    ["mon", "tue", "wed", "thu", "fri"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_start() -> String {
+    "08:00".to_string()
+}
+
+fn default_end() -> String {
+    "18:00".to_string()
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            enabled: false,
+            days: default_days(),
+            start: default_start(),
+            end: default_end(),
+        }
+    }
+}
+
+/// A validated tracking window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl ScheduleConfig {
+    pub fn window(&self) -> Result<Window, String> {
+        let days = self
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| format!("invalid day in schedule: {day}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| format!("invalid time in schedule: {time}"))
+        };
+        Ok(Window {
+            days,
+            start: parse_time(&self.start)?,
+            end: parse_time(&self.end)?,
+        })
+    }
+}
+
+/// Returns `true` if `now` is inside the window.
+///
+/// The days are the days a window starts on: a window from 22:00 to 06:00 on Friday also covers
+/// early Saturday. Equal start and end times cover the whole day.
+pub fn is_within_window(window: &Window, now: NaiveDateTime) -> bool {
+    let time = now.time();
+    let today = now.weekday();
+    if window.start < window.end {
+        window.days.contains(&today) && time >= window.start && time < window.end
+    } else if window.start == window.end {
+        window.days.contains(&today)
+    } else {
+        let yesterday = (now - ChronoDuration::days(1)).weekday();
+        (window.days.contains(&today) && time >= window.start)
+            || (window.days.contains(&yesterday) && time < window.end)
+    }
+}
+
+/// Starts the scheduler if tracking hours are enabled
+pub fn start_scheduler(manager_state: Arc<Mutex<ManagerState>>) {
+    let user_config = get_config();
+    let config = &user_config.schedule;
+    if !config.enabled {
+        return;
+    }
+    let window = match config.window() {
+        Ok(window) => window,
+        Err(e) => {
+            warn!("Not using the tracking schedule: {e}");
+            return;
+        }
+    };
+    shutdown::spawn("schedule", move || loop {
+        let inside = is_within_window(&window, chrono::Local::now().naive_local());
+        let mut state = manager_state.lock().unwrap();
+        if state
+            .schedule_override_until
+            .is_some_and(|until| Instant::now() >= until)
+        {
+            info!("Tracking schedule override ended");
+            state.schedule_override_until = None;
+        }
+        let track = inside || state.schedule_override_until.is_some();
+        if track && state.pause_reason == Some(PauseReason::Schedule) {
+            info!("Inside tracking hours");
+            state.resume_tracking();
+        } else if !track && state.pause_tracking(PauseReason::Schedule) {
+            info!("Outside tracking hours");
+        }
+        drop(state);
+        if shutdown::sleep(CHECK_INTERVAL) {
+            return;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn window(days: &[&str], start: &str, end: &str) -> Window {
+        ScheduleConfig {
+            enabled: true,
+            days: days.iter().map(|day| day.to_string()).collect(),
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+        .window()
+        .unwrap()
+    }
+
+    /// Returns the wall clock time on a day of March 2024, the 4th being a Monday
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, day)
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+    }
+
+    #[test]
+    fn window_on_the_listed_days() {
+        let window = window(&["mon", "tue"], "08:00", "18:00");
+        assert!(is_within_window(&window, at(4, "08:00")));
+        assert!(is_within_window(&window, at(5, "17:59")));
+        assert!(!is_within_window(&window, at(4, "18:00")));
+        assert!(!is_within_window(&window, at(4, "07:59")));
+    }
+
+    #[test]
+    fn days_outside_the_list() {
+        let window = window(&["mon", "tue"], "08:00", "18:00");
+        assert!(!is_within_window(&window, at(6, "12:00")));
+        assert!(!is_within_window(&window, at(10, "12:00")));
+    }
+
+    #[test]
+    fn window_crossing_midnight() {
+        // Starting Friday the 8th
+        let window = window(&["fri"], "22:00", "06:00");
+        assert!(!is_within_window(&window, at(8, "21:59")));
+        assert!(is_within_window(&window, at(8, "22:00")));
+        assert!(is_within_window(&window, at(9, "05:59")));
+        assert!(!is_within_window(&window, at(9, "06:00")));
+        // The night to Friday started on Thursday, which isn't listed
+        assert!(!is_within_window(&window, at(8, "05:00")));
+        assert!(!is_within_window(&window, at(9, "22:00")));
+    }
+
+    #[test]
+    fn equal_start_and_end_cover_the_whole_day() {
+        let window = window(&["mon"], "09:00", "09:00");
+        assert!(is_within_window(&window, at(4, "00:00")));
+        assert!(is_within_window(&window, at(4, "08:59")));
+        assert!(is_within_window(&window, at(4, "23:59")));
+        assert!(!is_within_window(&window, at(5, "09:00")));
+    }
+
+    #[test]
+    fn dst_changes_follow_the_wall_clock() {
+        // In Europe the clocks jump from 02:00 to 03:00 on Sunday the 31st and back from 03:00
+        // to 02:00 on Sunday, 27 October. The window only sees the local wall clock.
+        let window = window(&["sat"], "23:00", "02:30");
+        assert!(is_within_window(&window, at(31, "01:59")));
+        // The next minute on the wall clock after 01:59
+        assert!(!is_within_window(&window, at(31, "03:00")));
+
+        let repeated = NaiveDate::from_ymd_opt(2024, 10, 27)
+            .unwrap()
+            .and_hms_opt(2, 15, 0)
+            .unwrap();
+        // 02:15 happens twice that night and is inside the window both times
+        assert!(is_within_window(&window, repeated));
+    }
+
+    #[test]
+    fn invalid_schedule_is_rejected() {
+        let config = ScheduleConfig {
+            days: vec!["someday".to_string()],
+            ..ScheduleConfig::default()
+        };
+        assert!(config.window().is_err());
+        let config = ScheduleConfig {
+            start: "25:00".to_string(),
+            ..ScheduleConfig::default()
+        };
+        assert!(config.window().is_err());
+    }
+}
@@ -0,0 +1,494 @@
+/// Where the aw-server the modules report to runs
+///
+/// By default aw-tauri runs aw-server-rust in-process. In process mode the `aw-server` binary is
+/// started and supervised like any other module, so a datastore crash can't take down the tray.
+/// In remote mode no server is started: the modules report to `remote_url` and the webview shows
+/// the remote dashboard. Spawned and remote servers are checked periodically so an unreachable
+/// server shows up as offline in the tray.
+use aw_server::endpoints::build_rocket;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Url};
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+
+use crate::manager::ManagerState;
+use crate::send_notification;
+use crate::shutdown;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time a spawned server gets to start listening before the first check
+const STARTUP_GRACE: Duration = Duration::from_secs(10);
+
+/// Module started in process mode
+pub const SERVER_MODULE: &str = "aw-server";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerMode {
+    #[default]
+    Embedded,
+    Process,
+    Remote,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub mode: ServerMode,
+    /// Base URL of the server in remote mode, e.g. `http://homeserver:5600`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_url: Option<String>,
+}
+
+impl ServerConfig {
+    /// Returns the server to use, a spawned server storing its data in `db_path`
+    pub fn server(&self, db_path: PathBuf) -> Result<Server, String> {
+        match self.mode {
+            ServerMode::Embedded => Ok(Server::Embedded),
+            ServerMode::Process => Ok(Server::Process { db_path }),
+            ServerMode::Remote => {
+                let url = self
+                    .remote_url
+                    .as_deref()
+                    .ok_or("remote mode requires server.remote_url")?;
+                let url =
+                    Url::parse(url).map_err(|e| format!("invalid server.remote_url {url}: {e}"))?;
+                if url.host_str().is_none() {
+                    return Err(format!("server.remote_url {url} has no host"));
+                }
+                Ok(Server::Remote(url))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Server {
+    /// aw-server-rust running inside aw-tauri
+    Embedded,
+    /// The `aw-server` binary, supervised as a module
+    Process { db_path: PathBuf },
+    /// A server aw-tauri doesn't manage
+    Remote(Url),
+}
+
+impl Server {
+    /// Returns the base URL for API requests, without a trailing slash
+    pub fn base_url(&self, port: u16) -> String {
+        match self {
+            Server::Remote(url) => url.as_str().trim_end_matches('/').to_string(),
+            _ => match crate::local_host() {
+                host if host.contains(':') => format!("http://[{host}]:{port}"),
+                host => format!("http://{host}:{port}"),
+            },
+        }
+    }
+
+    pub fn remote_url(&self) -> Option<&Url> {
+        match self {
+            Server::Remote(url) => Some(url),
+            _ => None,
+        }
+    }
+
+    /// Whether the server runs outside aw-tauri and needs health checks
+    pub fn is_separate(&self) -> bool {
+        !matches!(self, Server::Embedded)
+    }
+
+    /// Returns the arguments the server module is started with, `None` unless in process mode
+    pub fn process_args(&self, port: u16) -> Option<Vec<String>> {
+        let Server::Process { db_path } = self else {
+            return None;
+        };
+        let mut args = vec![
+            "--port".to_string(),
+            port.to_string(),
+            "--dbpath".to_string(),
+            db_path.display().to_string(),
+        ];
+        let host = &crate::get_config().defaults.host;
+        if host != "127.0.0.1" {
+            args.extend(["--host".to_string(), host.clone()]);
+        }
+        Some(args)
+    }
+
+    /// Returns `true` if the server answers on its info endpoint
+    pub fn is_healthy(&self, port: u16) -> bool {
+        reqwest::blocking::Client::builder()
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .build()
+            .and_then(|client| {
+                client
+                    .get(format!("{}/api/0/info", self.base_url(port)))
+                    .send()
+            })
+            .is_ok_and(|response| response.status().is_success())
+    }
+}
+
+/// What the embedded server needs to be launched again, on another port
+struct EmbeddedServer {
+    config: aw_server::config::AWConfig,
+    db_path: PathBuf,
+    asset_path: Option<PathBuf>,
+    device_id: String,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+static EMBEDDED_SERVER: Mutex<Option<EmbeddedServer>> = Mutex::new(None);
+
+/// How long to wait for the old embedded server to release its port
+const RELAUNCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn spawn_embedded(
+    config: &aw_server::config::AWConfig,
+    db_path: &std::path::Path,
+    asset_path: Option<PathBuf>,
+    device_id: &str,
+    legacy_import: bool,
+) -> tauri::async_runtime::JoinHandle<()> {
+    let config = config.clone();
+    let db_path = db_path.to_str().unwrap().to_string();
+    let device_id = device_id.to_string();
+    tauri::async_runtime::spawn(async move {
+        let first_port = config.port;
+        // The port can be taken between the availability check and the bind, the server then
+        // moves on to the next free port
+        let (port, result) =
+            crate::ports::launch_on_free_port(&config.address, first_port, |port| {
+                if port != first_port {
+                    embedded_moved(port);
+                }
+                let server_state = aw_server::endpoints::ServerState {
+                    // Even if legacy_import is set to true it is disabled on Android so
+                    // it will not happen there
+                    datastore: Mutex::new(aw_datastore::Datastore::new(
+                        db_path.clone(),
+                        legacy_import,
+                    )),
+                    asset_resolver: aw_server::endpoints::AssetResolver::new(asset_path.clone()),
+                    device_id: device_id.clone(),
+                };
+                let mut config = config.clone();
+                config.port = port;
+                build_rocket(server_state, config).launch()
+            })
+            .await;
+        if let Err(e) = result {
+            error!("Embedded server failed on port {port}: {e}");
+            embedded_failed(port, &e.to_string());
+        }
+    })
+}
+
+/// Returns the port the embedded server runs on, `None` unless it was launched
+pub fn embedded_port() -> Option<u16> {
+    EMBEDDED_SERVER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|embedded| embedded.config.port)
+}
+
+/// Called with the port the embedded server moved to after its port got taken
+type PortMoved = Box<dyn Fn(u16) + Send>;
+
+static PORT_MOVED: Mutex<Option<PortMoved>> = Mutex::new(None);
+
+/// Calls `moved` with the new port whenever the embedded server moves off a taken port, right
+/// away with the current port in case it moved before this was called
+pub fn on_port_moved(moved: impl Fn(u16) + Send + 'static) {
+    let mut callback = PORT_MOVED.lock().unwrap();
+    if let Some(port) = embedded_port() {
+        moved(port);
+    }
+    *callback = Some(Box::new(moved));
+}
+
+/// Records that the embedded server is now launching on `port`
+fn embedded_moved(port: u16) {
+    if let Some(embedded) = EMBEDDED_SERVER.lock().unwrap().as_mut() {
+        embedded.config.port = port;
+    }
+    crate::ports::remember_port(port);
+    if let Some(moved) = PORT_MOVED.lock().unwrap().as_ref() {
+        moved(port);
+    }
+}
+
+/// Tells the user the embedded server stopped, e.g. because its port got taken
+fn embedded_failed(port: u16, reason: &str) {
+    if crate::is_headless() {
+        std::process::exit(1);
+    }
+    let app = crate::get_app_handle();
+    app.dialog()
+        .message(format!(
+            "The ActivityWatch server could not run on port {port}. Another program may be \
+            using it, change the port in the settings and restart Aw-Tauri.\n\n{reason}"
+        ))
+        .kind(MessageDialogKind::Error)
+        .title("Aw-Tauri")
+        .show(|_| {});
+}
+
+/// Launches aw-server-rust in-process on `port`
+pub fn launch_embedded(
+    mut config: aw_server::config::AWConfig,
+    port: u16,
+    db_path: PathBuf,
+    asset_path: Option<PathBuf>,
+    device_id: String,
+    legacy_import: bool,
+) {
+    config.port = port;
+    crate::ports::remember_port(port);
+    // Held until the server is stored, in case it has to move to another port right away
+    let mut embedded = EMBEDDED_SERVER.lock().unwrap();
+    let task = spawn_embedded(
+        &config,
+        &db_path,
+        asset_path.clone(),
+        &device_id,
+        legacy_import,
+    );
+    *embedded = Some(EmbeddedServer {
+        config,
+        db_path,
+        asset_path,
+        device_id,
+        task,
+    });
+}
+
+/// Stops the embedded server and launches it again on `port`
+pub async fn relaunch_embedded(port: u16) -> Result<(), String> {
+    let old_port = {
+        let mut embedded = EMBEDDED_SERVER.lock().unwrap();
+        let embedded = embedded
+            .as_mut()
+            .ok_or("The embedded server is not running")?;
+        embedded.task.abort();
+        embedded.config.port
+    };
+    // Dropping the server releases its port and datastore, give it a moment
+    let deadline = std::time::Instant::now() + RELAUNCH_TIMEOUT;
+    while !crate::is_port_available(old_port).unwrap_or(true) {
+        if std::time::Instant::now() > deadline {
+            warn!("Port {old_port} is still in use after stopping the embedded server");
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let mut embedded = EMBEDDED_SERVER.lock().unwrap();
+    let embedded = embedded
+        .as_mut()
+        .ok_or("The embedded server is not running")?;
+    embedded.config.port = port;
+    crate::ports::remember_port(port);
+    embedded.task = spawn_embedded(
+        &embedded.config,
+        &embedded.db_path,
+        embedded.asset_path.clone(),
+        &embedded.device_id,
+        false,
+    );
+    info!("Embedded server relaunched on port {port}");
+    Ok(())
+}
+
+/// Largest request body `query` forwards
+const MAX_REQUEST_BYTES: usize = 1024 * 1024;
+/// Largest response body `query` returns
+const MAX_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize)]
+pub struct QueryResponse {
+    pub status: u16,
+    /// The response as JSON, a string if it isn't JSON, `null` if empty
+    pub body: serde_json::Value,
+}
+
+fn query_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(QUERY_TIMEOUT)
+            .build()
+            .expect("failed to create the query client")
+    })
+}
+
+/// Forwards a request to the REST API of the server, for pages that can't reach it directly
+pub async fn query(
+    base_url: &str,
+    method: &str,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> Result<QueryResponse, String> {
+    if !path.starts_with("/api/") || path.contains("..") {
+        return Err(format!("Only /api/ paths can be queried, not {path}"));
+    }
+    let method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())
+        .map_err(|_| format!("Invalid method {method}"))?;
+    let mut request = query_client().request(method, format!("{base_url}{path}"));
+    if let Some(body) = body {
+        let body = serde_json::to_vec(&body).map_err(|e| e.to_string())?;
+        if body.len() > MAX_REQUEST_BYTES {
+            return Err(format!("Request body exceeds {MAX_REQUEST_BYTES} bytes"));
+        }
+        request = request
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query the server: {e}"))?;
+    let status = response.status().as_u16();
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_RESPONSE_BYTES as u64)
+    {
+        return Err(format!("Response exceeds {MAX_RESPONSE_BYTES} bytes"));
+    }
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read the response: {e}"))?;
+    if bytes.len() > MAX_RESPONSE_BYTES {
+        return Err(format!("Response exceeds {MAX_RESPONSE_BYTES} bytes"));
+    }
+    let body = if bytes.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+            serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned())
+        })
+    };
+    Ok(QueryResponse { status, body })
+}
+
+/// Builds the URL of a dashboard route, e.g. `/#/settings`, falling back to the root for
+/// anything that isn't a plain path or fragment on the server
+pub fn dashboard_url(base_url: &str, path: Option<&str>) -> Option<Url> {
+    let root = Url::parse(&format!("{base_url}/")).ok()?;
+    let Some(path) = path.filter(|path| !path.is_empty()) else {
+        return Some(root);
+    };
+    let valid = (path.starts_with('/') || path.starts_with('#'))
+        && !path.starts_with("//")
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/#-_.?=&%".contains(c));
+    match root.join(path) {
+        Ok(url) if valid && url.origin() == root.origin() => Some(url),
+        _ => {
+            warn!("Invalid dashboard path {path}, opening the dashboard root");
+            Some(root)
+        }
+    }
+}
+
+/// Periodically checks a spawned or remote server, showing the remote dashboard once reachable
+pub fn start_health_checks(app: AppHandle, manager_state: Arc<Mutex<ManagerState>>) {
+    shutdown::spawn("health checks", move || {
+        if matches!(manager_state.lock().unwrap().server, Server::Process { .. }) {
+            if shutdown::sleep(STARTUP_GRACE) {
+                return;
+            }
+        }
+        let mut navigated = false;
+        loop {
+            let (server, port) = {
+                let state = manager_state.lock().unwrap();
+                (state.server.clone(), state.server_port)
+            };
+            let base_url = server.base_url(port);
+            let online = server.is_healthy(port);
+            let changed = {
+                let mut state = manager_state.lock().unwrap();
+                let changed = state.server_online != online;
+                state.server_online = online;
+                if changed {
+                    state.update_tray_menu();
+                }
+                changed
+            };
+            if let Some(url) = server.remote_url().filter(|_| online && !navigated) {
+                if let Some(window) = app.webview_windows().get("main") {
+                    match window.navigate(url.clone()) {
+                        Ok(()) => navigated = true,
+                        Err(e) => warn!("Failed to show the remote dashboard: {e}"),
+                    }
+                }
+            }
+            if changed {
+                if online {
+                    info!("Server {base_url} is reachable");
+                } else {
+                    warn!("Server {base_url} is unreachable");
+                    send_notification(format!(
+                        "Cannot reach the ActivityWatch server at {base_url}"
+                    ));
+                }
+            }
+            if shutdown::sleep(HEALTH_CHECK_INTERVAL) {
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Waits until the health of the server on `port` is `healthy`, returning `false` on timeout
+    fn wait_for_health(server: &Server, port: u16, healthy: bool) -> bool {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while server.is_healthy(port) != healthy {
+            if std::time::Instant::now() > deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        true
+    }
+
+    #[test]
+    fn embedded_server_is_healthy_while_it_runs() {
+        crate::test_support::init();
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("no free port")
+            .port();
+        let config = aw_server::config::AWConfig {
+            port,
+            ..aw_server::config::AWConfig::default()
+        };
+        let server_state = aw_server::endpoints::ServerState {
+            datastore: Mutex::new(aw_datastore::Datastore::new_in_memory(false)),
+            asset_resolver: aw_server::endpoints::AssetResolver::new(None),
+            device_id: "test".to_string(),
+        };
+        let task = tauri::async_runtime::spawn(async move {
+            let _ = build_rocket(server_state, config).launch().await;
+        });
+
+        let server = Server::Embedded;
+        assert!(wait_for_health(&server, port, true), "server never came up");
+        task.abort();
+        assert!(
+            wait_for_health(&server, port, false),
+            "server still healthy after stopping"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+/// Export and import of the settings as a single JSON bundle
+///
+/// The bundle holds the config and the names of the installed modules, so a setup can be copied
+/// to another machine. The event database is deliberately not included.
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::{read_current_config, save_config, UserConfig};
+
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsBundle {
+    pub version: u32,
+    pub config: UserConfig,
+    /// Modules found in PATH and the discovery folder when exporting
+    #[serde(default)]
+    pub installed_modules: BTreeSet<String>,
+}
+
+impl SettingsBundle {
+    pub fn new(config: UserConfig, installed_modules: BTreeSet<String>) -> SettingsBundle {
+        SettingsBundle {
+            version: BUNDLE_VERSION,
+            config,
+            installed_modules,
+        }
+    }
+
+    pub fn parse(bundle: &str) -> Result<SettingsBundle, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(bundle).map_err(|e| format!("Not a settings bundle: {e}"))?;
+        let version = value.get("version").and_then(serde_json::Value::as_u64);
+        if version != Some(BUNDLE_VERSION.into()) {
+            return Err(format!(
+                "Unsupported settings bundle version {}",
+                version.map_or("(missing)".to_string(), |v| v.to_string())
+            ));
+        }
+        serde_json::from_value(value).map_err(|e| format!("Invalid settings bundle: {e}"))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write(path, contents).map_err(|e| format!("Failed to write {}: {e}", path.display()))
+    }
+
+    pub fn read(path: &Path) -> Result<SettingsBundle, String> {
+        let contents =
+            read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        SettingsBundle::parse(&contents)
+    }
+}
+
+fn module_names(config: &UserConfig) -> BTreeSet<String> {
+    config
+        .autostart_modules
+        .iter()
+        .chain(
+            config
+                .profiles
+                .values()
+                .flat_map(|profile| &profile.modules),
+        )
+        .map(|module| module.name.clone())
+        .collect()
+}
+
+/// Describes what importing `bundle` over `current` changes
+pub fn summarize(
+    current: &UserConfig,
+    bundle: &SettingsBundle,
+    installed: &BTreeSet<String>,
+) -> String {
+    let mut lines = Vec::new();
+    let (old, new) = (&current.defaults, &bundle.config.defaults);
+    if old.port != new.port {
+        lines.push(format!("Port: {} → {}", old.port, new.port));
+    }
+    if old.autostart != new.autostart {
+        lines.push(format!(
+            "Start on login: {} → {}",
+            old.autostart, new.autostart
+        ));
+    }
+    let (old_modules, new_modules) = (module_names(current), module_names(&bundle.config));
+    let added: Vec<&str> = new_modules
+        .difference(&old_modules)
+        .map(String::as_str)
+        .collect();
+    let removed: Vec<&str> = old_modules
+        .difference(&new_modules)
+        .map(String::as_str)
+        .collect();
+    if !added.is_empty() {
+        lines.push(format!("Modules added: {}", added.join(", ")));
+    }
+    if !removed.is_empty() {
+        lines.push(format!("Modules removed: {}", removed.join(", ")));
+    }
+    let missing: Vec<&str> = new_modules
+        .iter()
+        .chain(&bundle.installed_modules)
+        .filter(|name| !installed.contains(*name))
+        .map(String::as_str)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if !missing.is_empty() {
+        lines.push(format!(
+            "Not installed on this machine: {}",
+            missing.join(", ")
+        ));
+    }
+    if lines.is_empty() {
+        lines.push("Only minor settings change.".to_string());
+    }
+    lines.join("\n")
+}
+
+/// Writes the settings bundle to a file picked by the user, returning its path.
+///
+/// Blocks on the dialog, so this must not be called from the main thread.
+pub fn export_settings(app: &AppHandle, installed: BTreeSet<String>) -> Result<PathBuf, String> {
+    let bundle = SettingsBundle::new(read_current_config(), installed);
+    let path = app
+        .dialog()
+        .file()
+        .add_filter("Aw-Tauri settings", &["json"])
+        .set_file_name("aw-tauri-settings.json")
+        .blocking_save_file()
+        .ok_or("Export cancelled")?
+        .into_path()
+        .map_err(|e| e.to_string())?;
+    bundle.write(&path)?;
+    info!("Exported settings to {}", path.display());
+    Ok(path)
+}
+
+/// Imports a settings bundle picked by the user, after showing what would change.
+///
+/// Returns `false` if the user cancelled. Blocks on the dialogs, so this must not be called from
+/// the main thread.
+pub fn import_settings(app: &AppHandle, installed: &BTreeSet<String>) -> Result<bool, String> {
+    let Some(path) = app
+        .dialog()
+        .file()
+        .add_filter("Aw-Tauri settings", &["json"])
+        .blocking_pick_file()
+    else {
+        return Ok(false);
+    };
+    let path = path.into_path().map_err(|e| e.to_string())?;
+    let bundle = SettingsBundle::read(&path)?;
+
+    let summary = summarize(&read_current_config(), &bundle, installed);
+    let confirmed = app
+        .dialog()
+        .message(format!(
+            "Importing these settings will make the following changes:\n\n{summary}\n\n\
+            Aw-Tauri needs to be restarted afterwards."
+        ))
+        .kind(MessageDialogKind::Info)
+        .title("Aw-Tauri")
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Import".to_string(),
+            "Cancel".to_string(),
+        ))
+        .blocking_show();
+    if !confirmed {
+        return Ok(false);
+    }
+    save_config(&bundle.config)?;
+    info!("Imported settings from {}", path.display());
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModuleConfig, Profile};
+
+    fn module(name: &str) -> ModuleConfig {
+        toml::from_str(&format!("name = \"{name}\"")).unwrap()
+    }
+
+    #[test]
+    fn exported_settings_import_unchanged() {
+        let mut config = UserConfig::default();
+        config.defaults.port = 5700;
+        config.autostart_modules = vec![module("aw-watcher-afk"), module("aw-sync")];
+        config.profiles.insert(
+            "work".to_string(),
+            Profile {
+                modules: vec![module("aw-watcher-window")],
+            },
+        );
+        config.active_profile = Some("work".to_string());
+        let installed = BTreeSet::from(["aw-sync".to_string(), "aw-watcher-afk".to_string()]);
+
+        let path =
+            std::env::temp_dir().join(format!("aw-tauri-bundle-{}.json", std::process::id()));
+        SettingsBundle::new(config.clone(), installed.clone())
+            .write(&path)
+            .unwrap();
+        let imported = SettingsBundle::read(&path);
+        let _ = std::fs::remove_file(&path);
+        let imported = imported.unwrap();
+
+        assert_eq!(imported.version, BUNDLE_VERSION);
+        assert_eq!(imported.installed_modules, installed);
+        assert_eq!(
+            serde_json::to_value(&imported.config).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+        assert_eq!(
+            summarize(&config, &imported, &installed),
+            "Not installed on this machine: aw-watcher-window"
+        );
+    }
+
+    #[test]
+    fn other_bundle_versions_are_rejected() {
+        let mut bundle =
+            serde_json::to_value(SettingsBundle::new(UserConfig::default(), BTreeSet::new()))
+                .unwrap();
+        bundle["version"] = (BUNDLE_VERSION + 1).into();
+        assert_eq!(
+            SettingsBundle::parse(&bundle.to_string()).unwrap_err(),
+            format!("Unsupported settings bundle version {}", BUNDLE_VERSION + 1)
+        );
+        bundle.as_object_mut().unwrap().remove("version");
+        assert_eq!(
+            SettingsBundle::parse(&bundle.to_string()).unwrap_err(),
+            "Unsupported settings bundle version (missing)"
+        );
+    }
+
+    #[test]
+    fn summary_lists_the_changes() {
+        let current = UserConfig::default();
+        let mut config = current.clone();
+        config.defaults.port = current.defaults.port + 1;
+        config.autostart_modules = vec![module("aw-sync")];
+        let bundle = SettingsBundle::new(config, BTreeSet::new());
+        let installed = BTreeSet::from(["aw-sync".to_string()]);
+
+        let removed: Vec<String> = module_names(&current).into_iter().collect();
+        assert_eq!(
+            summarize(&current, &bundle, &installed),
+            format!(
+                "Port: {} → {}\nModules added: aw-sync\nModules removed: {}",
+                current.defaults.port,
+                current.defaults.port + 1,
+                removed.join(", ")
+            )
+        );
+    }
+}
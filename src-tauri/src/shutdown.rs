@@ -0,0 +1,202 @@
+/// Shutdown of the background threads
+///
+/// Background loops are started with [`spawn`] and wait with [`sleep`] instead of
+/// `thread::sleep`, so [`request`] wakes them on quit and [`join`] can wait for them to finish
+/// before the process exits. Threads blocked on something other than a sleep register a
+/// callback with [`on_shutdown`] to be unblocked.
+use log::{debug, warn};
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Background threads and what wakes them, one per process in [`SHUTDOWN`]
+struct Shutdown {
+    requested: (Mutex<bool>, Condvar),
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    callbacks: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+static SHUTDOWN: Shutdown = Shutdown::new();
+
+/// Interval in which `join` checks whether the threads have finished
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub fn is_requested() -> bool {
+    SHUTDOWN.is_requested()
+}
+
+/// Tells the background threads to finish, waking those sleeping in [`sleep`]
+pub fn request() {
+    SHUTDOWN.request();
+}
+
+/// Sleeps for `duration` or until shutdown is requested.
+///
+/// Returns `true` if shutdown was requested, in which case the thread should finish.
+pub fn sleep(duration: Duration) -> bool {
+    SHUTDOWN.sleep(duration)
+}
+
+/// Runs `callback` when shutdown is requested, or right away if it already was
+pub fn on_shutdown(callback: impl FnOnce() + Send + 'static) {
+    SHUTDOWN.on_shutdown(callback);
+}
+
+/// Starts a named background thread that `join` waits for
+pub fn spawn(name: &str, f: impl FnOnce() + Send + 'static) {
+    SHUTDOWN.spawn(name, f);
+}
+
+/// Waits up to `timeout` for the background threads to finish after [`request`]
+pub fn join(timeout: Duration) {
+    SHUTDOWN.join(timeout);
+}
+
+impl Shutdown {
+    const fn new() -> Self {
+        Shutdown {
+            requested: (Mutex::new(false), Condvar::new()),
+            threads: Mutex::new(Vec::new()),
+            callbacks: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn is_requested(&self) -> bool {
+        *self.requested.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn request(&self) {
+        let (lock, cvar) = &self.requested;
+        {
+            let mut requested = lock.lock().unwrap_or_else(|e| e.into_inner());
+            if *requested {
+                return;
+            }
+            *requested = true;
+            cvar.notify_all();
+        }
+        debug!("Shutdown requested");
+        let callbacks =
+            std::mem::take(&mut *self.callbacks.lock().unwrap_or_else(|e| e.into_inner()));
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    fn sleep(&self, duration: Duration) -> bool {
+        let (lock, cvar) = &self.requested;
+        let requested = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let (requested, _) = cvar
+            .wait_timeout_while(requested, duration, |requested| !*requested)
+            .unwrap_or_else(|e| e.into_inner());
+        *requested
+    }
+
+    fn on_shutdown(&self, callback: impl FnOnce() + Send + 'static) {
+        {
+            let mut callbacks = self.callbacks.lock().unwrap_or_else(|e| e.into_inner());
+            if !self.is_requested() {
+                callbacks.push(Box::new(callback));
+                return;
+            }
+        }
+        callback();
+    }
+
+    fn spawn(&self, name: &str, f: impl FnOnce() + Send + 'static) {
+        match thread::Builder::new().name(name.to_string()).spawn(f) {
+            Ok(handle) => {
+                let mut threads = self.threads.lock().unwrap_or_else(|e| e.into_inner());
+                // Threads that finished on their own have nothing left to wait for
+                threads.retain(|thread| !thread.is_finished());
+                threads.push(handle);
+            }
+            Err(e) => warn!("Failed to start the {name} thread: {e}"),
+        }
+    }
+
+    fn join(&self, timeout: Duration) {
+        let threads = std::mem::take(&mut *self.threads.lock().unwrap_or_else(|e| e.into_inner()));
+        let deadline = Instant::now() + timeout;
+        while threads.iter().any(|handle| !handle.is_finished()) && Instant::now() < deadline {
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+        for handle in threads {
+            if handle.is_finished() {
+                let _ = handle.join();
+            } else {
+                warn!(
+                    "Thread {} did not finish in time",
+                    handle.thread().name().unwrap_or("<unnamed>")
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn request_runs_the_callbacks_in_order_and_join_returns_in_time() {
+        static SHUTDOWN: Shutdown = Shutdown::new();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..3 {
+            let calls = Arc::clone(&calls);
+            SHUTDOWN.on_shutdown(move || calls.lock().unwrap().push(i));
+        }
+        SHUTDOWN.spawn(
+            "sleeper",
+            || while !SHUTDOWN.sleep(Duration::from_secs(60)) {},
+        );
+        let started = Instant::now();
+        SHUTDOWN.request();
+        SHUTDOWN.join(TIMEOUT);
+        assert!(started.elapsed() < TIMEOUT);
+        assert_eq!(*calls.lock().unwrap(), [0, 1, 2]);
+        assert!(SHUTDOWN.threads.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn callback_registered_after_the_request_runs_right_away() {
+        static SHUTDOWN: Shutdown = Shutdown::new();
+        SHUTDOWN.request();
+        let called = Arc::new(Mutex::new(false));
+        let called_by_callback = Arc::clone(&called);
+        SHUTDOWN.on_shutdown(move || *called_by_callback.lock().unwrap() = true);
+        assert!(*called.lock().unwrap());
+        assert!(SHUTDOWN.sleep(TIMEOUT));
+    }
+
+    #[test]
+    fn join_gives_up_on_threads_still_running() {
+        static SHUTDOWN: Shutdown = Shutdown::new();
+        // Ignores the request, like a thread blocked elsewhere
+        SHUTDOWN.spawn("stuck", || thread::sleep(Duration::from_secs(2)));
+        SHUTDOWN.request();
+        let started = Instant::now();
+        SHUTDOWN.join(Duration::from_millis(100));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn spawn_prunes_finished_threads() {
+        static SHUTDOWN: Shutdown = Shutdown::new();
+        SHUTDOWN.spawn("finishes", || {});
+        let deadline = Instant::now() + TIMEOUT;
+        while !SHUTDOWN.threads.lock().unwrap()[0].is_finished() && Instant::now() < deadline {
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+        SHUTDOWN.spawn(
+            "sleeper",
+            || while !SHUTDOWN.sleep(Duration::from_secs(60)) {},
+        );
+        assert_eq!(SHUTDOWN.threads.lock().unwrap().len(), 1);
+        SHUTDOWN.request();
+        SHUTDOWN.join(TIMEOUT);
+    }
+}
@@ -0,0 +1,94 @@
+/// Notification of Explorer restarts on Windows
+///
+/// When explorer.exe restarts, tray icons have to be registered again. Explorer broadcasts the
+/// `TaskbarCreated` message to all top-level windows once the new taskbar exists, so a hidden
+/// window is kept around in its own thread to receive it.
+use log::{error, info};
+use std::iter::once;
+use std::ptr::{null, null_mut};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW,
+    RegisterWindowMessageW, TranslateMessage, MSG, WNDCLASSW,
+};
+
+static TASKBAR_CREATED: AtomicU32 = AtomicU32::new(0);
+static ON_TASKBAR_CREATED: OnceLock<Box<dyn Fn() + Send + Sync>> = OnceLock::new();
+
+fn wide(value: &str) -> Vec<u16> {
+    value.encode_utf16().chain(once(0)).collect()
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    message: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    let taskbar_created = TASKBAR_CREATED.load(Ordering::Relaxed);
+    if taskbar_created != 0 && message == taskbar_created {
+        info!("Received TaskbarCreated");
+        if let Some(callback) = ON_TASKBAR_CREATED.get() {
+            callback();
+        }
+        return 0;
+    }
+    DefWindowProcW(hwnd, message, wparam, lparam)
+}
+
+/// Calls `callback` from the listener thread whenever the taskbar is recreated
+pub fn on_taskbar_created(callback: impl Fn() + Send + Sync + 'static) {
+    if ON_TASKBAR_CREATED.set(Box::new(callback)).is_err() {
+        error!("The taskbar listener is already running");
+        return;
+    }
+    thread::spawn(|| unsafe {
+        let message = RegisterWindowMessageW(wide("TaskbarCreated").as_ptr());
+        if message == 0 {
+            error!("Failed to register the TaskbarCreated message");
+            return;
+        }
+        TASKBAR_CREATED.store(message, Ordering::Relaxed);
+
+        let class_name = wide("aw-tauri-taskbar-listener");
+        let instance = GetModuleHandleW(null());
+        let mut class: WNDCLASSW = std::mem::zeroed();
+        class.lpfnWndProc = Some(window_proc);
+        class.hInstance = instance;
+        class.lpszClassName = class_name.as_ptr();
+        if RegisterClassW(&class) == 0 {
+            error!("Failed to register the taskbar listener window class");
+            return;
+        }
+        // A hidden top-level window, message-only windows don't receive the broadcast
+        let hwnd = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            class_name.as_ptr(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            null_mut(),
+            null_mut(),
+            instance,
+            null_mut(),
+        );
+        if hwnd.is_null() {
+            error!("Failed to create the taskbar listener window");
+            return;
+        }
+
+        let mut msg: MSG = std::mem::zeroed();
+        while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    });
+}
@@ -0,0 +1,214 @@
+/// Helpers for the tests
+///
+/// `init` replaces the config with one suited for the tests, so they never read the user's.
+/// `Harness` runs a manager over the mock modules in `tests/mock-modules`, handling its messages
+/// like the manager's thread would, and records what the manager shows the user.
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use crate::frontend::{CrashOutcome, Frontend, OnAccept};
+use crate::manager::{self, ManagerState, ModuleMessage, RestartConfig};
+use crate::notifications::Notification;
+use crate::server::Server;
+use crate::tray_menu::TrayMenu;
+use crate::{ModuleConfig, UserConfig};
+
+/// Port the harness points the modules at, nothing listens on it
+pub const TEST_PORT: u16 = 5699;
+
+/// Sets the config used by all tests: quick restarts and stop timeouts, and `aw-mock-notify`
+/// emitting notifications
+pub fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let mut config = UserConfig::default();
+        config.defaults.stop_timeout_secs = 1;
+        config.restart = RestartConfig {
+            limit: 2,
+            delay_secs: 0,
+            backoff_multiplier: 1.0,
+            ..RestartConfig::default()
+        };
+        config.autostart_modules = vec![toml::from_str::<ModuleConfig>(
+            "name = \"aw-mock-notify\"\nemit_notifications = true",
+        )
+        .expect("invalid mock module config")];
+        crate::set_config(config);
+    });
+}
+
+/// Returns the mock modules by name
+pub fn mock_modules() -> BTreeMap<String, PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/mock-modules");
+    std::fs::read_dir(&dir)
+        .expect("mock modules not found")
+        .filter_map(Result::ok)
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            )
+        })
+        .collect()
+}
+
+/// What the manager showed the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shown {
+    Crashed { name: String, outcome: CrashOutcome },
+    AskedToReplace { name: String, pid: u32 },
+    Notification(Notification),
+}
+
+/// A frontend recording what it is shown, ignoring the tray rebuilds
+#[derive(Debug, Default)]
+pub struct RecordingFrontend {
+    pub shown: Mutex<Vec<Shown>>,
+    /// The module labels updated, per update
+    pub labels: Mutex<Vec<Vec<(String, String)>>>,
+}
+
+impl RecordingFrontend {
+    pub fn shown(&self) -> Vec<Shown> {
+        self.shown.lock().unwrap().clone()
+    }
+
+    fn record(&self, shown: Shown) {
+        self.shown.lock().unwrap().push(shown);
+    }
+}
+
+impl Frontend for RecordingFrontend {
+    fn update_modules(&self, _menu: TrayMenu) {}
+
+    fn update_labels(&self, labels: Vec<(String, String)>) {
+        self.labels.lock().unwrap().push(labels);
+    }
+
+    fn module_crashed(&self, name: &str, outcome: CrashOutcome, _retry: OnAccept) {
+        self.record(Shown::Crashed {
+            name: name.to_string(),
+            outcome,
+        });
+    }
+
+    fn ask_to_replace(&self, name: &str, pid: u32, _replace: OnAccept) {
+        self.record(Shown::AskedToReplace {
+            name: name.to_string(),
+            pid,
+        });
+    }
+
+    fn notify(&self, notification: Notification) {
+        self.record(Shown::Notification(notification));
+    }
+}
+
+/// A manager over the mock modules, driven by the test
+pub struct Harness {
+    pub state: Arc<Mutex<ManagerState>>,
+    pub frontend: Arc<RecordingFrontend>,
+    rx: Receiver<ModuleMessage>,
+    /// Kinds of the messages handled so far, e.g. `Started`
+    pub messages: Vec<&'static str>,
+}
+
+impl Harness {
+    pub fn new() -> Harness {
+        init();
+        let (tx, rx) = channel();
+        let frontend = Arc::new(RecordingFrontend::default());
+        let mut state = ManagerState::with_modules(
+            tx,
+            TEST_PORT,
+            Server::Embedded,
+            Arc::clone(&frontend) as Arc<dyn Frontend>,
+            mock_modules(),
+        );
+        // The tests run in parallel, each keeps the modules stopped by the user to itself
+        static HARNESSES: AtomicUsize = AtomicUsize::new(0);
+        let id = HARNESSES.fetch_add(1, Ordering::SeqCst);
+        state.use_user_stopped_file(
+            crate::dirs::app_data_dir().join(format!("stopped-modules-{id}.json")),
+        );
+        Harness {
+            state: Arc::new(Mutex::new(state)),
+            frontend,
+            rx,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Handles the manager's messages and due restarts until `done` holds, returning `false` if
+    /// it still doesn't after `timeout`
+    pub fn run_until(&mut self, timeout: Duration, done: impl Fn(&ManagerState) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                state.run_due_restarts();
+                if done(&state) {
+                    return true;
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            match self
+                .rx
+                .recv_timeout((deadline - now).min(Duration::from_millis(20)))
+            {
+                Ok(msg) => {
+                    self.messages.push(kind(&msg));
+                    let mut state = self.state.lock().unwrap();
+                    manager::handle_message(&mut state, msg);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return false,
+            }
+        }
+    }
+}
+
+/// Stands in for an ActivityWatch server on a free port, answering its info endpoint
+pub fn fake_activitywatch_server() -> u16 {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let body = r#"{"hostname":"test","version":"v0.13.2"}"#;
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    port
+}
+
+fn kind(msg: &ModuleMessage) -> &'static str {
+    match msg {
+        ModuleMessage::Started { .. } => "Started",
+        ModuleMessage::Stopped { .. } => "Stopped",
+        ModuleMessage::Reconcile {} => "Reconcile",
+        ModuleMessage::AdoptedExited { .. } => "AdoptedExited",
+        ModuleMessage::ReplaceAdopted { .. } => "ReplaceAdopted",
+        ModuleMessage::Init {} => "Init",
+        ModuleMessage::RefreshUptimes {} => "RefreshUptimes",
+        ModuleMessage::PermissionsGranted { .. } => "PermissionsGranted",
+        ModuleMessage::StopTimedOut { .. } => "StopTimedOut",
+        ModuleMessage::Discovered { .. } => "Discovered",
+        ModuleMessage::SpawnFailed { .. } => "SpawnFailed",
+        ModuleMessage::Retry { .. } => "Retry",
+    }
+}
@@ -0,0 +1,55 @@
+/// Timings of the startup phases
+///
+/// `setup` marks the end of each phase; the durations are logged at debug level and kept for the
+/// `get_startup_timings` command so slow startups can be reported with specifics.
+use log::debug;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupPhase {
+    pub name: &'static str,
+    pub millis: f64,
+}
+
+static STARTUP_TIMINGS: Mutex<Vec<StartupPhase>> = Mutex::new(Vec::new());
+
+pub struct PhaseTimer {
+    start: Instant,
+    last: Instant,
+}
+
+impl PhaseTimer {
+    pub fn start() -> PhaseTimer {
+        let now = Instant::now();
+        PhaseTimer {
+            start: now,
+            last: now,
+        }
+    }
+    /// Records the time since the previous phase ended
+    pub fn phase(&mut self, name: &'static str) {
+        let now = Instant::now();
+        let millis = (now - self.last).as_secs_f64() * 1000.0;
+        self.last = now;
+        debug!("Startup phase {name} took {millis:.1} ms");
+        STARTUP_TIMINGS
+            .lock()
+            .unwrap()
+            .push(StartupPhase { name, millis });
+    }
+    /// Records the total time since the timer was started
+    pub fn finish(self) {
+        let millis = (Instant::now() - self.start).as_secs_f64() * 1000.0;
+        debug!("Startup took {millis:.1} ms");
+        STARTUP_TIMINGS.lock().unwrap().push(StartupPhase {
+            name: "total",
+            millis,
+        });
+    }
+}
+
+pub fn get_startup_timings() -> Vec<StartupPhase> {
+    STARTUP_TIMINGS.lock().unwrap().clone()
+}
@@ -0,0 +1,418 @@
+/// Building the tray menu
+///
+/// Rebuilding the menu can be slow on some desktops, so the manager only snapshots what the menu
+/// shows and queues it here. A dedicated thread coalesces the snapshots queued within
+/// `DEBOUNCE`, e.g. when all modules start at login, and builds the menu outside the manager lock.
+/// Label updates, e.g. of the uptimes, change the text of the module items of the menu last set
+/// instead of rebuilding it.
+use log::{debug, error};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::menu::{CheckMenuItem, Menu, MenuItem, SubmenuBuilder};
+use tauri::{AppHandle, Wry};
+
+use crate::manager::PauseReason;
+use crate::menu_id::MenuId;
+use crate::{get_config, get_tray_id, wait_for_app_handle};
+
+/// Window in which queued menu updates are merged into one rebuild
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A module entry of the Modules submenu
+#[derive(Debug, Clone)]
+pub struct MenuModule {
+    pub id: String,
+    pub label: String,
+    /// Whether the module runs, `None` for modules not started this session
+    pub running: Option<bool>,
+}
+
+/// What the tray menu shows, snapshotted from the manager state
+#[derive(Debug, Clone, Default)]
+pub struct TrayMenu {
+    pub modules: Vec<MenuModule>,
+    /// Id and label of the running modules, offered to force stop
+    pub running: Vec<(String, String)>,
+    pub pause_reason: Option<PauseReason>,
+    pub active_profile: Option<String>,
+    pub server_status: Option<String>,
+}
+
+/// Items of the modules started this session in the menu last set, by module id
+static MODULE_ITEMS: Mutex<BTreeMap<String, CheckMenuItem<Wry>>> = Mutex::new(BTreeMap::new());
+
+fn updates() -> &'static Sender<TrayMenu> {
+    static UPDATES: OnceLock<Sender<TrayMenu>> = OnceLock::new();
+    UPDATES.get_or_init(|| {
+        let (tx, rx) = channel::<TrayMenu>();
+        thread::spawn(move || coalesce(&rx, DEBOUNCE, |menu| apply(&menu)));
+        tx
+    })
+}
+
+/// Passes the last of the values received within `window` of the first to `build`, until the
+/// senders are gone. A burst still waiting when they are gone is dropped.
+fn coalesce<T>(rx: &Receiver<T>, window: Duration, mut build: impl FnMut(T)) {
+    while let Ok(mut value) = rx.recv() {
+        let deadline = Instant::now() + window;
+        loop {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(newer) => value = newer,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        build(value);
+    }
+}
+
+/// Queues a rebuild of the tray menu, superseding rebuilds queued within `DEBOUNCE`
+pub fn request_update(menu: TrayMenu) {
+    if updates().send(menu).is_err() {
+        error!("The tray menu updater stopped");
+    }
+}
+
+fn label_updates() -> &'static Sender<Vec<(String, String)>> {
+    static LABEL_UPDATES: OnceLock<Sender<Vec<(String, String)>>> = OnceLock::new();
+    LABEL_UPDATES.get_or_init(|| {
+        let (tx, rx) = channel::<Vec<(String, String)>>();
+        thread::spawn(move || {
+            for labels in rx {
+                set_labels(&labels);
+            }
+        });
+        tx
+    })
+}
+
+/// Queues changing the labels of module items, given by module id, without a rebuild
+pub fn request_labels(labels: Vec<(String, String)>) {
+    if label_updates().send(labels).is_err() {
+        error!("The tray label updater stopped");
+    }
+}
+
+fn set_labels(labels: &[(String, String)]) {
+    let items = MODULE_ITEMS.lock().unwrap();
+    for (module, label) in labels {
+        // Not in the menu yet, the next rebuild shows it
+        let Some(item) = items.get(module) else {
+            continue;
+        };
+        if let Err(e) = item.set_text(label) {
+            error!("Failed to update the tray label of {module}: {e}");
+        }
+    }
+}
+
+fn apply(menu: &TrayMenu) {
+    // Waited for before taking the app handle, setup may need it to create the tray
+    let Some(tray_id) = get_tray_id() else {
+        debug!("No tray, not updating the tray menu");
+        return;
+    };
+    let Some(app) = wait_for_app_handle() else {
+        return;
+    };
+
+    let Some(tray) = app.tray_by_id(tray_id) else {
+        error!("Tray {tray_id:?} not found");
+        return;
+    };
+    // Held until the items are stored, so no label update goes to the replaced menu
+    let mut module_items = MODULE_ITEMS.lock().unwrap();
+    let mut items = BTreeMap::new();
+    if let Err(e) = tray.set_menu(Some(build(&app, menu, &mut items))) {
+        error!("Failed to set the tray menu: {e}");
+        return;
+    }
+    *module_items = items;
+    debug!("Set the tray menu");
+}
+
+/// Builds the menu, collecting its module items into `module_items`
+fn build(
+    app: &AppHandle,
+    state: &TrayMenu,
+    module_items: &mut BTreeMap<String, CheckMenuItem<Wry>>,
+) -> Menu<Wry> {
+    let open = MenuItem::with_id(app, MenuId::Open, "Open", true, None::<&str>)
+        .expect("failed to create open menu item");
+    let quit = MenuItem::with_id(app, MenuId::Quit, "Quit", true, None::<&str>)
+        .expect("failed to create quit menu item");
+
+    let mut modules_submenu_builder = SubmenuBuilder::new(app, "Modules");
+    for module in &state.modules {
+        modules_submenu_builder = match module.running {
+            Some(running) => {
+                let module_menu = CheckMenuItem::with_id(
+                    app,
+                    MenuId::Module(module.id.clone()),
+                    &module.label,
+                    true,
+                    running,
+                    None::<&str>,
+                )
+                .expect("failed to create module menu item");
+                module_items.insert(module.id.clone(), module_menu.clone());
+                modules_submenu_builder.item(&module_menu)
+            }
+            None => {
+                let module_menu = MenuItem::with_id(
+                    app,
+                    MenuId::Module(module.id.clone()),
+                    &module.label,
+                    true,
+                    None::<&str>,
+                )
+                .expect("failed to create module menu item");
+                modules_submenu_builder.item(&module_menu)
+            }
+        };
+    }
+    let module_submenu = modules_submenu_builder
+        .build()
+        .expect("failed to create module submenu");
+
+    let mut force_stop_submenu_builder = SubmenuBuilder::new(app, "Force stop");
+    for (module, label) in &state.running {
+        let force_stop_menu = MenuItem::with_id(
+            app,
+            MenuId::ForceStop(module.clone()),
+            label,
+            true,
+            None::<&str>,
+        )
+        .expect("failed to create force stop menu item");
+        force_stop_submenu_builder = force_stop_submenu_builder.item(&force_stop_menu);
+    }
+    let force_stop_submenu = force_stop_submenu_builder
+        .enabled(!state.running.is_empty())
+        .build()
+        .expect("failed to create force stop submenu");
+
+    let mut module_logs_submenu_builder = SubmenuBuilder::new(app, "Module logs");
+    let mut has_module_logs = false;
+    for module in &state.modules {
+        if !crate::logging::module_log_path(&module.id).exists() {
+            continue;
+        }
+        let module_log_menu = MenuItem::with_id(
+            app,
+            MenuId::ModuleLog(module.id.clone()),
+            &module.label,
+            true,
+            None::<&str>,
+        )
+        .expect("failed to create module log menu item");
+        module_logs_submenu_builder = module_logs_submenu_builder.item(&module_log_menu);
+        has_module_logs = true;
+    }
+    let module_logs_submenu = module_logs_submenu_builder
+        .enabled(has_module_logs)
+        .build()
+        .expect("failed to create module logs submenu");
+
+    let toggle_pause = MenuItem::with_id(
+        app,
+        MenuId::TogglePause,
+        match state.pause_reason {
+            Some(PauseReason::Schedule) => "Track anyway for 1 h",
+            Some(_) => "Resume tracking",
+            None => "Pause tracking",
+        },
+        true,
+        None::<&str>,
+    )
+    .expect("failed to create pause menu item");
+    let restart_all = MenuItem::with_id(
+        app,
+        MenuId::RestartAll,
+        "Restart all modules",
+        !state.running.is_empty(),
+        None::<&str>,
+    )
+    .expect("failed to create restart all menu item");
+
+    let open_settings = MenuItem::with_id(app, MenuId::Settings, "Settings", true, None::<&str>)
+        .expect("failed to create settings menu item");
+    let [config_folder, log_folder, data_folder] = crate::folder_menu_items(app);
+    let export_today = MenuItem::with_id(
+        app,
+        MenuId::ExportToday,
+        "Export today's events…",
+        true,
+        None::<&str>,
+    )
+    .expect("failed to create export menu item");
+    let export_settings = MenuItem::with_id(
+        app,
+        MenuId::ExportSettings,
+        "Export settings…",
+        true,
+        None::<&str>,
+    )
+    .expect("failed to create export settings menu item");
+    let import_settings = MenuItem::with_id(
+        app,
+        MenuId::ImportSettings,
+        "Import settings…",
+        true,
+        None::<&str>,
+    )
+    .expect("failed to create import settings menu item");
+    let about = MenuItem::with_id(app, MenuId::About, "About Aw-Tauri", true, None::<&str>)
+        .expect("failed to create about menu item");
+    let menu = Menu::with_items(
+        app,
+        &[
+            &open,
+            &open_settings,
+            &toggle_pause,
+            &module_submenu,
+            &restart_all,
+            &force_stop_submenu,
+            &module_logs_submenu,
+            &export_today,
+            &export_settings,
+            &import_settings,
+            &config_folder,
+            &log_folder,
+            &data_folder,
+            &about,
+            &quit,
+        ],
+    )
+    .expect("failed to create tray menu");
+    if !get_config().profiles.is_empty() {
+        let mut profile_submenu_builder = SubmenuBuilder::new(app, "Profile");
+        for profile in get_config().profiles.keys() {
+            let active = state.active_profile.as_ref() == Some(profile);
+            let profile_menu = CheckMenuItem::with_id(
+                app,
+                MenuId::Profile(profile.clone()),
+                profile,
+                true,
+                active,
+                None::<&str>,
+            )
+            .expect("failed to create profile menu item");
+            profile_submenu_builder = profile_submenu_builder.item(&profile_menu);
+        }
+        let profile_submenu = profile_submenu_builder
+            .build()
+            .expect("failed to create profile submenu");
+        menu.insert(&profile_submenu, 3)
+            .expect("failed to add profile submenu");
+    }
+    if let Some(version) = crate::updater::ready_update_version() {
+        let apply_update = MenuItem::with_id(
+            app,
+            MenuId::ApplyUpdate,
+            format!("Update {version} available — restart to apply"),
+            true,
+            None::<&str>,
+        )
+        .expect("failed to create update menu item");
+        menu.insert(&apply_update, 0)
+            .expect("failed to add update menu item");
+    } else if let Some(tag) = crate::releases::new_release_tag() {
+        let open_release = MenuItem::with_id(
+            app,
+            MenuId::OpenRelease,
+            format!("ActivityWatch {tag} available"),
+            true,
+            None::<&str>,
+        )
+        .expect("failed to create release menu item");
+        menu.insert(&open_release, 0)
+            .expect("failed to add release menu item");
+    }
+
+    if crate::is_safe_mode() {
+        let safe_mode = MenuItem::with_id(
+            app,
+            MenuId::Status("safe_mode"),
+            "Safe mode: modules not started",
+            false,
+            None::<&str>,
+        )
+        .expect("failed to create safe mode menu item");
+        menu.insert(&safe_mode, 0)
+            .expect("failed to add safe mode menu item");
+    }
+    let pause_status = match state.pause_reason {
+        Some(PauseReason::LowBattery) => Some("Paused: low battery"),
+        Some(PauseReason::Schedule) => Some("Outside tracking hours"),
+        _ => None,
+    };
+    if let Some(pause_status) = pause_status {
+        let paused = MenuItem::with_id(
+            app,
+            MenuId::Status("paused"),
+            pause_status,
+            false,
+            None::<&str>,
+        )
+        .expect("failed to create pause status menu item");
+        menu.insert(&paused, 0)
+            .expect("failed to add pause status menu item");
+    }
+    if let Some(server_status) = &state.server_status {
+        let server_status = MenuItem::with_id(
+            app,
+            MenuId::Status("server"),
+            server_status,
+            false,
+            None::<&str>,
+        )
+        .expect("failed to create server status menu item");
+        menu.insert(&server_status, 0)
+            .expect("failed to add server status menu item");
+    }
+    menu
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn requests_within_the_window_build_once() {
+        let (tx, rx) = channel();
+        let builder = thread::spawn(move || {
+            let mut built = Vec::new();
+            coalesce(&rx, WINDOW, |value| built.push(value));
+            built
+        });
+        for value in 0..10 {
+            tx.send(value).unwrap();
+        }
+        thread::sleep(WINDOW * 3);
+        drop(tx);
+        assert_eq!(builder.join().unwrap(), [9]);
+    }
+
+    #[test]
+    fn requests_after_the_window_build_again() {
+        let (tx, rx) = channel();
+        let builder = thread::spawn(move || {
+            let mut built = Vec::new();
+            coalesce(&rx, WINDOW, |value| built.push(value));
+            built
+        });
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        thread::sleep(WINDOW * 3);
+        tx.send(3).unwrap();
+        thread::sleep(WINDOW * 3);
+        drop(tx);
+        assert_eq!(builder.join().unwrap(), [2, 3]);
+    }
+}
@@ -0,0 +1,256 @@
+/// Self-update through the Tauri updater
+///
+/// Updates are checked on startup and then daily, in the background. A found update is
+/// downloaded right away and offered from the tray; applying it stops the modules the same way
+/// quitting does before the update is installed and the app restarted.
+///
+/// The updater public key is provided at build time through `AW_TAURI_UPDATER_PUBKEY`. Builds
+/// without it (e.g. distribution packages) never check for updates.
+use log::{info, warn};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Url};
+use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_updater::{Update, UpdaterExt};
+
+use crate::get_config;
+use crate::manager::ManagerState;
+use crate::shutdown;
+
+pub const UPDATER_PUBKEY: Option<&str> = option_env!("AW_TAURI_UPDATER_PUBKEY");
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const STABLE_ENDPOINT: &str =
+    "https://github.com/ActivityWatch/aw-tauri/releases/latest/download/latest.json";
+const BETA_ENDPOINT: &str =
+    "https://github.com/ActivityWatch/aw-tauri/releases/download/beta/latest.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    Beta,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdatesConfig {
+    #[serde(default = "default_check")]
+    pub check: bool,
+    #[serde(default)]
+    pub channel: UpdateChannel,
+    /// Notify about new releases in builds without the updater
+    #[serde(default = "default_notify_releases")]
+    pub notify_releases: bool,
+    #[serde(default = "default_release_check_interval_days")]
+    pub release_check_interval_days: u64,
+}
+
+fn default_check() -> bool {
+    true
+}
+
+fn default_notify_releases() -> bool {
+    true
+}
+
+fn default_release_check_interval_days() -> u64 {
+    7
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> Self {
+        UpdatesConfig {
+            check: default_check(),
+            channel: UpdateChannel::default(),
+            notify_releases: default_notify_releases(),
+            release_check_interval_days: default_release_check_interval_days(),
+        }
+    }
+}
+
+/// Downloaded update waiting to be applied
+struct ReadyUpdate {
+    update: Update,
+    bytes: Vec<u8>,
+}
+
+static READY_UPDATE: Mutex<Option<ReadyUpdate>> = Mutex::new(None);
+
+/// Returns the version of the downloaded update, if there is one
+pub fn ready_update_version() -> Option<String> {
+    READY_UPDATE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|ready| ready.update.version.clone())
+}
+
+pub fn endpoint(channel: UpdateChannel) -> &'static str {
+    match channel {
+        UpdateChannel::Stable => STABLE_ENDPOINT,
+        UpdateChannel::Beta => BETA_ENDPOINT,
+    }
+}
+
+/// Returns `true` if `remote` should replace `current` on the given channel.
+///
+/// Pre-releases are only offered on the beta channel.
+pub fn is_newer_on_channel(current: &Version, remote: &Version, channel: UpdateChannel) -> bool {
+    if channel == UpdateChannel::Stable && !remote.pre.is_empty() {
+        return false;
+    }
+    remote > current
+}
+
+/// Periodically checks for updates in a background thread, if enabled
+pub fn start_update_checks(app: AppHandle) {
+    let config = get_config().updates.clone();
+    if !config.check {
+        info!("Update checks are disabled");
+        return;
+    }
+    if UPDATER_PUBKEY.is_none() {
+        info!("Built without an updater public key, not checking for updates");
+        return;
+    }
+    shutdown::spawn("updater", move || loop {
+        if READY_UPDATE.lock().unwrap().is_none() {
+            tauri::async_runtime::block_on(check_and_download(&app, config.channel));
+        }
+        if shutdown::sleep(CHECK_INTERVAL) {
+            return;
+        }
+    });
+}
+
+async fn check_and_download(app: &AppHandle, channel: UpdateChannel) {
+    let endpoint = Url::parse(endpoint(channel)).expect("invalid update endpoint");
+    let updater = app
+        .updater_builder()
+        .version_comparator(move |current, release| {
+            is_newer_on_channel(&current, &release.version, channel)
+        })
+        .endpoints(vec![endpoint])
+        .and_then(|builder| builder.build());
+    let updater = match updater {
+        Ok(updater) => updater,
+        Err(e) => {
+            warn!("Failed to set up the updater: {e}");
+            return;
+        }
+    };
+
+    let update = match updater.check().await {
+        Ok(Some(update)) => update,
+        Ok(None) => {
+            info!("No update available");
+            return;
+        }
+        Err(e) => {
+            info!("Failed to check for updates: {e}");
+            return;
+        }
+    };
+    info!("Update available: {}", update.version);
+
+    let bytes = match update.download(|_, _| {}, || {}).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to download update {}: {e}", update.version);
+            return;
+        }
+    };
+
+    let version = update.version.clone();
+    *READY_UPDATE.lock().unwrap() = Some(ReadyUpdate { update, bytes });
+
+    let manager_state = app.state::<Arc<Mutex<ManagerState>>>();
+    manager_state.lock().unwrap().update_tray_menu();
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Aw-Tauri")
+        .body(format!(
+            "Update {version} available — restart from the tray to apply"
+        ))
+        .show()
+    {
+        warn!("Failed to show update notification: {e}");
+    }
+}
+
+/// Stops the modules, installs the downloaded update and restarts
+pub fn apply_update(app: &AppHandle) {
+    let Some(ready) = READY_UPDATE.lock().unwrap().take() else {
+        return;
+    };
+    info!("Applying update {}", ready.update.version);
+
+    let manager_state = app.state::<Arc<Mutex<ManagerState>>>();
+    crate::manager::stop_modules_and_wait(&manager_state, crate::modules_stop_timeout());
+
+    if let Err(e) = ready.update.install(&ready.bytes) {
+        warn!("Failed to install update {}: {e}", ready.update.version);
+        return;
+    }
+    app.restart();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn channel_selects_the_endpoint() {
+        assert_eq!(endpoint(UpdateChannel::Stable), STABLE_ENDPOINT);
+        assert_eq!(endpoint(UpdateChannel::Beta), BETA_ENDPOINT);
+        let config: UpdatesConfig = toml::from_str("channel = \"beta\"").unwrap();
+        assert_eq!(config.channel, UpdateChannel::Beta);
+        let config: UpdatesConfig = toml::from_str("").unwrap();
+        assert_eq!(config.channel, UpdateChannel::Stable);
+    }
+
+    #[test]
+    fn stable_channel_skips_pre_releases() {
+        let current = version("0.1.0");
+        let stable = UpdateChannel::Stable;
+        assert!(is_newer_on_channel(&current, &version("0.1.1"), stable));
+        assert!(!is_newer_on_channel(
+            &current,
+            &version("0.2.0-beta.1"),
+            stable
+        ));
+        assert!(!is_newer_on_channel(&current, &version("0.1.0"), stable));
+        assert!(!is_newer_on_channel(&current, &version("0.0.9"), stable));
+    }
+
+    #[test]
+    fn beta_channel_orders_pre_releases() {
+        let beta = UpdateChannel::Beta;
+        let cases = [
+            ("0.1.0", "0.2.0-beta.1", true),
+            ("0.2.0-beta.1", "0.2.0-beta.2", true),
+            ("0.2.0-beta.2", "0.2.0-beta.10", true),
+            ("0.2.0-alpha.3", "0.2.0-beta.1", true),
+            ("0.2.0-beta.1", "0.2.0", true),
+            // A pre-release comes before its release
+            ("0.2.0", "0.2.0-beta.3", false),
+            ("0.2.0-beta.2", "0.2.0-beta.1", false),
+            ("0.2.0-beta.1", "0.2.0-beta.1", false),
+        ];
+        for (current, remote, newer) in cases {
+            assert_eq!(
+                is_newer_on_channel(&version(current), &version(remote), beta),
+                newer,
+                "{current} -> {remote}"
+            );
+        }
+    }
+}
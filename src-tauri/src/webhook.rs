@@ -0,0 +1,183 @@
+/// Crash reports to a webhook
+///
+/// For unattended machines, module crashes can be POSTed as JSON to `crash_webhook_url`.
+/// Delivery is retried a couple of times and failures are only logged.
+use log::{debug, warn};
+use serde::Serialize;
+use std::time::Duration;
+use sysinfo::System;
+
+use crate::get_config;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashPayload {
+    pub hostname: String,
+    pub module: String,
+    /// Exit code, `None` when the module was killed by a signal
+    pub exit_status: Option<i32>,
+    pub restart_count: u32,
+    pub restart_limit_reached: bool,
+    pub timestamp: String,
+    pub stderr_tail: String,
+}
+
+impl CrashPayload {
+    pub fn new(
+        module: &str,
+        exit_status: Option<i32>,
+        restart_count: u32,
+        restart_limit_reached: bool,
+        stderr_tail: String,
+    ) -> CrashPayload {
+        CrashPayload {
+            hostname: System::host_name().unwrap_or_default(),
+            module: module.to_string(),
+            exit_status,
+            restart_count,
+            restart_limit_reached,
+            timestamp: chrono::Local::now().to_rfc3339(),
+            stderr_tail,
+        }
+    }
+}
+
+/// Posts the crash to the configured webhook in the background, if one is configured
+pub fn report_crash(payload: CrashPayload) {
+    let Some(url) = get_config().defaults.crash_webhook_url.clone() else {
+        return;
+    };
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = deliver(&url, &payload).await {
+            warn!(
+                "Failed to deliver crash webhook for {}: {e}",
+                payload.module
+            );
+        }
+    });
+}
+
+async fn deliver(url: &str, payload: &CrashPayload) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let mut attempt = 1;
+    loop {
+        let result = client
+            .post(url)
+            .json(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < ATTEMPTS => {
+                debug!("Crash webhook attempt {attempt} failed: {e}");
+                attempt += 1;
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    fn payload() -> CrashPayload {
+        CrashPayload {
+            hostname: "desk".to_string(),
+            module: "aw-watcher-window".to_string(),
+            exit_status: None,
+            restart_count: 2,
+            restart_limit_reached: true,
+            timestamp: "2024-03-04T12:00:00+01:00".to_string(),
+            stderr_tail: "Traceback".to_string(),
+        }
+    }
+
+    /// Answers one request per status with that status, returning the request bodies
+    fn serve(statuses: &'static [u16]) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/crash", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            statuses
+                .iter()
+                .map(|status| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    write!(
+                        reader.get_mut(),
+                        "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    )
+                    .unwrap();
+                    String::from_utf8(body).unwrap()
+                })
+                .collect()
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn payload_serialization() {
+        let json = serde_json::to_value(payload()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "hostname": "desk",
+                "module": "aw-watcher-window",
+                "exit_status": null,
+                "restart_count": 2,
+                "restart_limit_reached": true,
+                "timestamp": "2024-03-04T12:00:00+01:00",
+                "stderr_tail": "Traceback",
+            })
+        );
+    }
+
+    #[test]
+    fn crash_is_delivered() {
+        let (url, server) = serve(&[200]);
+        tauri::async_runtime::block_on(deliver(&url, &payload())).unwrap();
+        let bodies = server.join().unwrap();
+        let delivered: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+        assert_eq!(delivered, serde_json::to_value(payload()).unwrap());
+    }
+
+    #[test]
+    fn failed_delivery_is_retried() {
+        let (url, server) = serve(&[500, 200]);
+        tauri::async_runtime::block_on(deliver(&url, &payload())).unwrap();
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn delivery_gives_up_after_the_attempts() {
+        let (url, server) = serve(&[500, 503, 500]);
+        assert!(tauri::async_runtime::block_on(deliver(&url, &payload())).is_err());
+        assert_eq!(server.join().unwrap().len(), ATTEMPTS as usize);
+    }
+}
@@ -1,4 +1,32 @@
 import { createApp } from "vue";
+import { invoke } from "@tauri-apps/api/core";
+import {
+  onAction,
+  registerActionTypes,
+  type ActionType,
+} from "@tauri-apps/plugin-notification";
 import App from "./App.vue";
 
+// Notification actions are only supported on mobile, where the plugin takes the action types
+// from the webview. The app routes the picked action.
+async function setUpNotificationActions() {
+  const types = await invoke<ActionType[]>("notification_action_types");
+  await registerActionTypes(types);
+  await onAction((event) => {
+    const performed = event as unknown as {
+      actionId?: string;
+      notification?: { extra?: Record<string, unknown> };
+    };
+    const module = performed.notification?.extra?.module;
+    invoke("handle_notification_action", {
+      action: performed.actionId ?? "",
+      module: typeof module === "string" ? module : null,
+    });
+  });
+}
+
+setUpNotificationActions().catch((e) =>
+  console.debug("Notification actions are not available:", e),
+);
+
 createApp(App).mount("#app");